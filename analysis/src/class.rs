@@ -7,6 +7,8 @@ pub struct ClassAssoc {
 pub struct ClassField {
     pub name: SrcNode<Ident>,
     pub ty: SrcNode<TyId>,
+    /// Used by members that do not provide their own value for the field.
+    pub default: Option<TyExpr>,
 }
 
 pub struct Class {
@@ -41,6 +43,15 @@ impl Class {
                 None
             })
     }
+
+    pub fn field_default(&self, field: Ident) -> Option<&TyExpr> {
+        self.fields
+            .as_ref()
+            .expect("Class fields must be known here")
+            .iter()
+            .find(|f| *f.name == field)
+            .and_then(|f| f.default.as_ref())
+    }
 }
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
@@ -62,6 +73,11 @@ pub struct Lang {
     pub and: Option<ClassId>,
     pub or: Option<ClassId>,
     pub join: Option<ClassId>,
+    // Optional, used to generate inputs for property tests
+    pub arbitrary: Option<ClassId>,
+    // Optional, used to display the expressions embedded in interpolated strings
+    pub show: Option<ClassId>,
+    pub debug: Option<ClassId>,
 }
 
 #[derive(Default)]
@@ -116,6 +132,9 @@ impl Classes {
                 if lang.iter().find(|a| &**a.name == "and_").is_some() { self.lang.and = Some(id); }
                 if lang.iter().find(|a| &**a.name == "or_").is_some() { self.lang.or = Some(id); }
                 if lang.iter().find(|a| &**a.name == "join").is_some() { self.lang.join = Some(id); }
+                if lang.iter().find(|a| &**a.name == "arbitrary").is_some() { self.lang.arbitrary = Some(id); }
+                if lang.iter().find(|a| &**a.name == "show").is_some() { self.lang.show = Some(id); }
+                if lang.iter().find(|a| &**a.name == "debug").is_some() { self.lang.debug = Some(id); }
             }
 
             self.classes.push(class);
@@ -149,6 +168,17 @@ impl Classes {
         self.classes[id.0].fields = Some(fields);
     }
 
+    pub fn define_field_default(&mut self, id: ClassId, field: Ident, val: TyExpr) {
+        if let Some(f) = self.classes[id.0].fields
+            .as_mut()
+            .expect("Class fields must be known here")
+            .iter_mut()
+            .find(|f| *f.name == field)
+        {
+            f.default = Some(val);
+        }
+    }
+
     pub fn get_member(&self, id: MemberId) -> &Member {
         &self.members[id.0]
     }
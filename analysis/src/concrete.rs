@@ -22,19 +22,45 @@ impl ConTyId {
 
 #[derive(Debug)]
 pub struct ConEffect {
-    send: ConTyId,
-    recv: ConTyId,
+    pub send: ConTyId,
+    pub recv: ConTyId,
 }
 
 #[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum ConProc {
     Def(DefId, Vec<ConTyId>),
     Field(ConTyId, MemberId, Vec<ConTyId>, Ident),
+    // A harness applied to an entry point, such as an I/O test applied to the `run_io` lang item
+    Harness(DefId, DefId),
+    // A lambda lifted out of a procedure during optimisation, numbered so that no two lifted lambdas share an identifier
+    Lifted(ConProcId, usize),
+    // A procedure (that is a curried function) with its Nth parameter specialised during optimisation to an argument
+    // that is a known procedure
+    Specialized(ConProcId, usize, ConProcId),
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ConProcId(Intern<ConProc>);
 
+impl ConProcId {
+    /// The procedure that this identifier refers to.
+    pub fn proc(&self) -> &ConProc {
+        &self.0
+    }
+
+    /// The procedure that a lambda lifted out of the given procedure is given, where `idx` is unique among lifted
+    /// lambdas.
+    pub fn lifted(parent: Self, idx: usize) -> Self {
+        Self(Intern::new(ConProc::Lifted(parent, idx)))
+    }
+
+    /// The procedure that a function procedure becomes when its Nth parameter is specialised to an argument that is a
+    /// known procedure.
+    pub fn specialized(func: Self, idx: usize, arg: Self) -> Self {
+        Self(Intern::new(ConProc::Specialized(func, idx, arg)))
+    }
+}
+
 impl fmt::Debug for ConProcId {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &*self.0 {
@@ -49,6 +75,9 @@ impl fmt::Debug for ConProcId {
                 .map(|a| format!("{:?}", a))
                 .collect::<Vec<_>>()
                 .join(", "), field),
+            ConProc::Harness(harness, entry) => write!(f, "{:?}({:?})", harness, entry),
+            ConProc::Lifted(parent, idx) => write!(f, "{:?}#{}", parent, idx),
+            ConProc::Specialized(func, idx, arg) => write!(f, "{:?}[{} = {:?}]", func, idx, arg),
         }
     }
 }
@@ -93,12 +122,14 @@ pub struct ConContext {
     ty_lookup: HashMap<ConTy, ConTyId>,
     procs: HashMap<ConProcId, Option<ConExpr>>,
     entry: Option<ConProcId>,
+    // Errors that only become apparent once generic types are known
+    errors: Vec<Error>,
     // Lang items
     pub r#bool: Option<ConDataId>,
 }
 
 impl ConContext {
-    pub fn from_ctx(hir: &Context) -> (Self, Vec<Error>) {
+    fn new(hir: &Context) -> Self {
         let mut this = Self {
             datas: HashMap::default(),
             effects: HashMap::default(),
@@ -106,6 +137,7 @@ impl ConContext {
             ty_lookup: HashMap::default(),
             procs: HashMap::default(),
             entry: None,
+            errors: Vec::new(),
             r#bool: None,
         };
 
@@ -113,6 +145,10 @@ impl ConContext {
         // TODO: Quite hacky
         this.r#bool = Some(this.lower_data(hir, hir.datas.lang.r#bool.unwrap(), &[]));
 
+        this
+    }
+
+    pub fn from_ctx(hir: &Context) -> (Self, Vec<Error>) {
         let mut errors = Vec::new();
 
         let mut entries = hir.defs
@@ -140,18 +176,46 @@ impl ConContext {
                 errors.push(Error::MultipleEntryPoints(main.name.span(), second.name.span()));
             }
 
-            let gen_scope = hir.tys.get_gen_scope(main.gen_scope);
-            if gen_scope.len() == 0 {
-                let main_def = ConProcId(Intern::new(ConProc::Def(id, Vec::new())));
-                this.lower_proc(hir, main_def);
-                this.entry = Some(main_def);
-            } else {
-                errors.push(Error::GenericEntryPoint(main.name.clone(), gen_scope.get(0).name.span()));
-            }
+            let (this, mut errs) = Self::from_entry(hir, id);
+            errors.append(&mut errs);
+            (this, errors)
         } else {
             errors.push(Error::NoEntryPoint(hir.root_span));
+            (Self::new(hir), errors)
+        }
+    }
+
+    /// Create a context that uses the given definition as its entry point, ignoring any other entry points. This is
+    /// used to compile each test as its own program.
+    pub fn from_entry(hir: &Context, entry: DefId) -> (Self, Vec<Error>) {
+        let mut this = Self::new(hir);
+        let mut errors = Vec::new();
+
+        let def = hir.defs.get(entry);
+        let gen_scope = hir.tys.get_gen_scope(def.gen_scope);
+        let performs_effects = def.body
+            .as_ref()
+            .map_or(false, |body| matches!(hir.tys.get(body.meta().1), Ty::Effect(_, _)));
+        if gen_scope.len() > 0 {
+            errors.push(Error::GenericEntryPoint(def.name.clone(), gen_scope.get(0).name.span()));
+        } else if performs_effects && def.attr.iter().any(|attr| attr.name.as_str() == "test") {
+            // Tests that perform I/O need their effects to be handled before they can be run
+            match hir.defs.lang.run_io {
+                Some(run_io) => {
+                    let entry_def = ConProcId(Intern::new(ConProc::Harness(run_io, entry)));
+                    this.lower_proc(hir, entry_def);
+                    this.entry = Some(entry_def);
+                },
+                None => errors.push(Error::MissingLangItem("run_io")),
+            }
+        } else {
+            let entry_def = ConProcId(Intern::new(ConProc::Def(entry, Vec::new())));
+            this.lower_proc(hir, entry_def);
+            this.entry = Some(entry_def);
         }
 
+        errors.append(&mut this.errors);
+
         (this, errors)
     }
 
@@ -266,6 +330,24 @@ impl ConContext {
                 .into_iter()
                 .map(|(name, field)| (name, self.lower_ty(hir, field, ty_insts)))
                 .collect()),
+            // Concrete rows are just records, so flatten the fields of the tail into the record
+            Ty::Row(fields, tail) => {
+                let fields = fields
+                    .into_iter()
+                    .map(|(name, field)| (name, self.lower_ty(hir, field, ty_insts)))
+                    .collect::<Vec<_>>();
+                let con_tail = self.lower_ty(hir, tail, ty_insts);
+                let mut tail_fields = match self.get_ty(con_tail) {
+                    ConTy::Record(tail_fields) => tail_fields.clone(),
+                    _ => {
+                        let found = self.display(hir, con_tail).to_string();
+                        self.errors.push(Error::RecordTailNotRecord(hir.tys.get_span(tail), found));
+                        BTreeMap::new()
+                    },
+                };
+                tail_fields.extend(fields);
+                ConTy::Record(tail_fields)
+            },
             Ty::Func(i, o) => ConTy::Func(
                 self.lower_ty(hir, i, ty_insts),
                 self.lower_ty(hir, o, ty_insts),
@@ -408,13 +490,38 @@ impl ConContext {
                     let gen = (0..member_gen_scope.len())
                         .map(|idx| *links.get(&idx).expect("Generic type not mentioned in member"))
                         .collect::<Vec<_>>();
-                    self.lower_expr(
-                        hir,
-                        member
-                            .field(*field)
-                            .unwrap(),
-                        &TyInsts { self_ty: Some(*self_ty), gen: &gen },
-                    )
+                    match member.field(*field) {
+                        Some(val) => self.lower_expr(hir, val, &TyInsts { self_ty: Some(*self_ty), gen: &gen }),
+                        // The member doesn't define the field, so fall back on the class's default. Defaults are
+                        // generic over the class's parameters rather than the member's.
+                        None => self.lower_expr(
+                            hir,
+                            hir.classes
+                                .get(member.class)
+                                .field_default(*field)
+                                .expect("Member field must be defined or have a default"),
+                            &TyInsts { self_ty: Some(*self_ty), gen: args },
+                        ),
+                    }
+                },
+                ConProc::Harness(harness, entry) => {
+                    let harness = ConProcId(Intern::new(ConProc::Def(*harness, Vec::new())));
+                    let entry = ConProcId(Intern::new(ConProc::Def(*entry, Vec::new())));
+                    self.lower_proc(hir, harness);
+                    self.lower_proc(hir, entry);
+
+                    let harness_ty = *self.get_proc(harness).meta();
+                    let entry_ty = *self.get_proc(entry).meta();
+                    let ConTy::Func(_, output_ty) = self.get_ty(harness_ty) else {
+                        panic!("Harness must be a function")
+                    };
+                    ConNode::new(hir::Expr::Apply(
+                        ConNode::new(hir::Expr::Global(harness), harness_ty),
+                        ConNode::new(hir::Expr::Global(entry), entry_ty),
+                    ), *output_ty)
+                },
+                ConProc::Lifted(_, _) | ConProc::Specialized(_, _, _) => {
+                    unreachable!("Procedures created during optimisation are never concretized")
                 },
             };
             self.procs.insert(proc, Some(body));
@@ -490,8 +597,9 @@ impl ConContext {
                 self.lower_expr(hir, pred, ty_insts),
                 arms
                     .iter()
-                    .map(|(binding, arm)| (
+                    .map(|(binding, guard, arm)| (
                         self.lower_binding(hir, binding, ty_insts),
+                        guard.as_ref().map(|guard| self.lower_expr(hir, guard, ty_insts)),
                         self.lower_expr(hir, arm, ty_insts),
                     ))
                     .collect(),
@@ -556,6 +664,11 @@ impl ConContext {
                 .iter()
                 .map(|(name, field)| (name.clone(), self.lower_expr(hir, field, ty_insts)))
                 .collect()),
+            hir::Expr::Extend(record, fields) => hir::Expr::Extend(self.lower_expr(hir, record, ty_insts), fields
+                .iter()
+                .map(|(name, field)| (name.clone(), self.lower_expr(hir, field, ty_insts)))
+                .collect()),
+            hir::Expr::Restrict(record, names) => hir::Expr::Restrict(self.lower_expr(hir, record, ty_insts), names.clone()),
             hir::Expr::Basin(eff, inner) => hir::Expr::Basin(
                 self.lower_effect(hir, *eff, ty_insts),
                 self.lower_expr(hir, inner, ty_insts),
@@ -6,18 +6,27 @@ pub struct Context {
     pub tys: Types,
     pub defs: Defs,
     pub effects: Effects,
+    /// Functions provided by the host application, callable as intrinsics (i.e: `@name(x, y)`)
+    pub hosts: HashMap<Ident, SrcNode<ast::Type>>,
     pub errors: Vec<Error>,
     pub root_span: Span,
 }
 
 impl Context {
     pub fn from_module(module: &SrcNode<ast::Module>) -> (Self, Vec<Error>) {
+        Self::from_module_with_hosts(module, HashMap::default())
+    }
+
+    /// Like [`Context::from_module`], but makes the given host functions available as intrinsics. Host function types
+    /// must not be generic.
+    pub fn from_module_with_hosts(module: &SrcNode<ast::Module>, hosts: HashMap<Ident, SrcNode<ast::Type>>) -> (Self, Vec<Error>) {
         let mut this = Self {
             classes: Classes::default(),
             datas: Datas::default(),
             tys: Types::default(),
             defs: Defs::default(),
             effects: Effects::default(),
+            hosts,
             errors: Vec::default(),
             root_span: module.span(),
         };
@@ -31,6 +40,7 @@ impl Context {
         let mut effect_aliases = Vec::new();
         let mut members_init = Vec::new();
         let mut defs_init = Vec::new();
+        let derived = derive::derive_members(module, &mut errors);
         // Declare items before declaration
         for (attr, class) in module.classes() {
             let (gen_scope, mut errs) = GenScope::from_ast(&class.generics, class.name.span());
@@ -118,7 +128,7 @@ impl Context {
                 },
             }
         }
-        for (attr, member) in module.members() {
+        for (attr, member) in module.members().chain(derived.iter().map(|member| (&[][..], member))) {
             let class_id = if let Some(class_id) = this.classes.lookup(*member.class.name) {
                 class_id
             } else {
@@ -366,7 +376,7 @@ impl Context {
             let fields = class.items
                 .iter()
                 .filter_map(|item| match item {
-                    ast::ClassItem::Value { name, ty } => {
+                    ast::ClassItem::Value { name, ty, .. } => {
                         let mut infer = Infer::new(&mut this, Some(*gen_scope));
                         let self_ty = infer.set_self_unknown(class.name.span());
                         let args = (0..infer.ctx().tys.get_gen_scope(*gen_scope).len())
@@ -397,6 +407,7 @@ impl Context {
                             Some(ClassField {
                                 name: name.clone(),
                                 ty: SrcNode::new(checked.reify(ty.meta().1), ty.meta().0),
+                                default: None,
                             })
                         }
                     },
@@ -515,7 +526,7 @@ impl Context {
 
             let member_args = member.class.params
                 .iter()
-                .map(|arg| member.member.to_hir(&TypeLowerCfg::other(), &mut infer, &Scope::Empty).meta().1)
+                .map(|arg| arg.to_hir(&TypeLowerCfg::other(), &mut infer, &Scope::Empty).meta().1)
                 .collect::<Vec<_>>();
 
             // infer.add_implied_member(ImpliedMember {
@@ -615,6 +626,50 @@ impl Context {
         // Check for lang items
         this.errors.append(&mut this.defs.check_lang_items());
 
+        // Class default fields
+        for (_, class, class_id, gen_scope) in &classes {
+            for item in &class.items {
+                if let ast::ClassItem::Value { name, default: Some(default), .. } = item {
+                    let field_ty = match this.classes.get(*class_id).field(**name) {
+                        Some(field_ty) => **field_ty,
+                        None => continue,
+                    };
+
+                    let mut infer = Infer::new(&mut this, Some(*gen_scope));
+                    let self_ty = infer.set_self_unknown(class.name.span());
+                    let args = (0..infer.ctx().tys.get_gen_scope(*gen_scope).len())
+                        .map(|idx| {
+                            let span = infer.ctx().tys.get_gen_scope(*gen_scope).get(idx).name.span();
+                            infer.insert(span, TyInfo::Gen(idx, *gen_scope, span))
+                        })
+                        .collect::<Vec<_>>();
+                    infer.add_implied_member(ImpliedMember {
+                        member: SrcNode::new(self_ty, class.name.span()),
+                        class: SrcNode::new(*class_id, class.name.span()),
+                        args: args.clone(),
+                        items: ImpliedItems::Eq(Vec::new()),
+                    });
+                    let mut infer = infer.with_gen_scope_implied();
+
+                    let val = default.to_hir(&(), &mut infer, &Scope::Empty);
+                    let val_ty = infer.instantiate(
+                        field_ty,
+                        Some(name.span()),
+                        &mut |idx, _, _| args.get(idx).copied(),
+                        Some(self_ty),
+                    );
+                    infer.make_flow(val.meta().1, val_ty, EqInfo::new(name.span(), format!("Type of default class item must match its declaration")));
+
+                    let (mut checked, mut errs) = infer.into_checked();
+                    errors.append(&mut errs);
+
+                    let val = val.reify(&mut checked);
+
+                    this.classes.define_field_default(*class_id, **name, val);
+                }
+            }
+        }
+
         // Member fields
         for (member, class_id, member_id, member_ty, gen_scope) in &members {
             let fields = member.items
@@ -682,7 +737,7 @@ impl Context {
             let class = this.classes.get(*class_id);
 
             for field in class.fields.as_ref().expect("Class fields must be known here") {
-                if !fields.contains_key(&*field.name) {
+                if !fields.contains_key(&*field.name) && field.default.is_none() {
                     errors.push(Error::MissingClassItem(member.member.span(), class.name.clone(), field.name.clone()));
                 }
             }
@@ -722,6 +777,72 @@ impl Context {
             this.defs.define_body(id, body);
         }
 
+        // Tests are run as entry points, so they must be concrete and produce something that can be checked
+        let effect_decls = |eff| match this.tys.get_effect(eff) {
+            Effect::Known(effs) => effs
+                .into_iter()
+                .map(|eff| eff.ok().map(|(decl, _)| decl))
+                .collect::<Option<std::collections::BTreeSet<_>>>(),
+            Effect::Error => None,
+        };
+        // I/O tests are run with the `run_io` lang item, so they must perform the effects that it handles
+        let io_decls = this.defs.lang.run_io
+            .and_then(|run_io| this.defs.get(run_io).ty_hint)
+            .and_then(|ty| match this.tys.get(ty) {
+                Ty::Func(i, _) => match this.tys.get(i) {
+                    Ty::Effect(eff, _) => effect_decls(eff),
+                    _ => None,
+                },
+                _ => None,
+            });
+        for (_, def) in this.defs.tests() {
+            let gen_scope = this.tys.get_gen_scope(def.gen_scope);
+            if gen_scope.len() > 0 {
+                errors.push(Error::GenericEntryPoint(def.name.clone(), gen_scope.get(0).name.span()));
+            } else if let Some(body) = &def.body {
+                let ty = body.meta().1;
+                let is_testable = match this.tys.get(ty) {
+                    Ty::Data(data, _) => Some(data) == this.datas.lang.r#bool,
+                    Ty::Effect(eff, out) => matches!(this.tys.get(out), Ty::Record(fields, _) if fields.is_empty())
+                        && io_decls.as_ref().map_or(true, |io_decls| effect_decls(eff).as_ref() == Some(io_decls)),
+                    _ => false,
+                };
+                if !is_testable {
+                    errors.push(Error::InvalidTest(def.name.clone(), ty));
+                }
+            }
+        }
+
+        // Properties are functions from a generated input to a boolean, so their input must be `Arbitrary`
+        let properties = this.defs
+            .properties()
+            .filter_map(|(_, def)| Some((def.name.clone(), def.gen_scope, def.body.as_ref()?.meta().1)))
+            .collect::<Vec<_>>();
+        for (name, gen_scope, ty) in properties {
+            let gen_scope = this.tys.get_gen_scope(gen_scope);
+            if gen_scope.len() > 0 {
+                errors.push(Error::GenericEntryPoint(name, gen_scope.get(0).name.span()));
+                continue;
+            }
+            let input = match this.tys.get(ty) {
+                Ty::Func(i, o) if matches!(this.tys.get(o), Ty::Data(data, _) if Some(data) == this.datas.lang.r#bool) => i,
+                _ => {
+                    errors.push(Error::InvalidProperty(name, ty));
+                    continue;
+                },
+            };
+            let Some(arbitrary) = this.classes.lang.arbitrary else {
+                errors.push(Error::MissingLangItem("arbitrary"));
+                continue;
+            };
+
+            let mut infer = Infer::new(&mut this, None);
+            let input = infer.instantiate(input, name.span(), &mut |_, _, _| None, None);
+            infer.make_impl(input, (arbitrary, Vec::new()), name.span(), Vec::new(), name.span());
+            let (_, mut errs) = infer.into_checked();
+            errors.append(&mut errs);
+        }
+
         /*
         for data in this.datas.iter_datas() {
             let data = this.datas.get_data(data);
@@ -741,6 +862,11 @@ impl Context {
         ConContext::from_ctx(self)
     }
 
+    /// Like [`Context::concretize`], but uses the given definition as the entry point.
+    pub fn concretize_entry(&self, entry: DefId) -> (ConContext, Vec<Error>) {
+        ConContext::from_entry(self, entry)
+    }
+
     pub fn emit(&mut self, error: Error) { self.errors.push(error) }
 
     // Returns (record_ty, field_ty, number_of_indirections)
@@ -762,7 +888,7 @@ impl Context {
                         break None;
                     }
                 },
-                Ty::Record(fields, _) => if let Some((_, field_ty)) = fields.iter().find(|(name, _)| **name == field) {
+                Ty::Record(fields, _) | Ty::Row(fields, _) => if let Some((_, field_ty)) = fields.iter().find(|(name, _)| **name == field) {
                     break Some((ty, *field_ty, already_seen.len()));
                 } else {
                     // Record has no such field
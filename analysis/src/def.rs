@@ -21,6 +21,8 @@ impl fmt::Debug for DefId {
 pub struct Lang {
     pub io_unit: Option<DefId>,
     pub io_bind: Option<DefId>,
+    // Optional, used to run tests that perform I/O
+    pub run_io: Option<DefId>,
 }
 
 #[derive(Default)]
@@ -38,6 +40,15 @@ impl Defs {
             .map(|(i, d)| (DefId(i, *d.name), d))
     }
 
+    /// Definitions marked as tests with `$[test]`.
+    pub fn tests(&self) -> impl Iterator<Item = (DefId, &Def)> {
+        self.iter().filter(|(_, def)| def.attr.iter().any(|attr| attr.name.as_str() == "test"))
+    }
+
+    pub fn properties(&self) -> impl Iterator<Item = (DefId, &Def)> {
+        self.iter().filter(|(_, def)| def.attr.iter().any(|attr| attr.name.as_str() == "property"))
+    }
+
     pub fn get(&self, def: DefId) -> &Def {
         &self.defs[def.0]
     }
@@ -64,6 +75,9 @@ impl Defs {
                 if lang.iter().find(|a| &**a.name == "io_bind").is_some() {
                     self.lang.io_bind = Some(id);
                 }
+                if lang.iter().find(|a| &**a.name == "run_io").is_some() {
+                    self.lang.run_io = Some(id);
+                }
             }
 
             self.defs.push(def);
@@ -0,0 +1,330 @@
+//! Generation of structural members for data types annotated with `$[derive(...)]`.
+//!
+//! Derived members are produced as AST before the module's members are declared, so they are checked exactly like
+//! handwritten members. All generated nodes carry the span of the class named in the attribute.
+
+use super::*;
+
+/// Generate a member for every class named in the `derive` attributes of the module's data types.
+pub fn derive_members(module: &ast::Module, errors: &mut Vec<Error>) -> Vec<ast::Member> {
+    let mut members = Vec::new();
+    for (attrs, data) in module.datas() {
+        for class in attrs
+            .iter()
+            .filter(|attr| attr.name.as_str() == "derive")
+            .flat_map(|attr| attr.args.iter().flatten())
+        {
+            let span = class.name.span();
+            let items = match class.name.as_str() {
+                _ if data.variants.is_empty() => None,
+                "Debug" => Some(derive_debug(data, span)),
+                "Eq" => Some(derive_eq(data, span)),
+                "Ord" => Some(derive_ord(data, span)),
+                "Default" => Some(derive_default(data, span)),
+                _ => None,
+            };
+
+            if let Some(items) = items {
+                members.push(ast::Member {
+                    generics: generics(data, &class.name),
+                    member: SrcNode::new(ast::Type::Data(
+                        data.name.clone(),
+                        data.generics.tys
+                            .iter()
+                            .map(|ty| SrcNode::new(ast::Type::Data(ty.name.clone(), Vec::new()), span))
+                            .collect(),
+                    ), span),
+                    class: SrcNode::new(ast::ClassInst {
+                        name: class.name.clone(),
+                        params: Vec::new(),
+                    }, span),
+                    items,
+                });
+            } else {
+                errors.push(Error::CannotDerive(class.name.clone(), data.name.clone()));
+            }
+        }
+    }
+    members
+}
+
+/// The generics of a derived member: those of the data type, with each parameter required to be a member of the
+/// derived class.
+fn generics(data: &ast::Data, class: &SrcNode<Ident>) -> ast::Generics {
+    let span = class.span();
+    ast::Generics {
+        tys: data.generics.tys
+            .iter()
+            .map(|ty| ast::GenericTy { name: ty.name.clone() })
+            .collect(),
+        implied_members: data.generics.implied_members
+            .iter()
+            .cloned()
+            .chain(data.generics.tys
+                .iter()
+                .map(|ty| SrcNode::new(ast::ImpliedMember {
+                    member: SrcNode::new(ast::Type::Data(ty.name.clone(), Vec::new()), span),
+                    class: SrcNode::new(ast::ClassInst {
+                        name: class.clone(),
+                        params: Vec::new(),
+                    }, span),
+                    assoc: Vec::new(),
+                }, span)))
+            .collect(),
+    }
+}
+
+/// The way in which the inner value of a variant is destructured.
+enum Shape<'a> {
+    Unit,
+    Single(&'a SrcNode<ast::Type>),
+    Tuple(&'a [SrcNode<ast::Type>]),
+    Record(&'a [(SrcNode<Ident>, SrcNode<ast::Type>)]),
+}
+
+impl<'a> Shape<'a> {
+    fn of(ty: &'a SrcNode<ast::Type>) -> Self {
+        match &**ty {
+            ast::Type::Tuple(fields) if fields.is_empty() => Self::Unit,
+            ast::Type::Tuple(fields) => Self::Tuple(fields),
+            ast::Type::Record(fields) => Self::Record(fields),
+            _ => Self::Single(ty),
+        }
+    }
+
+    fn tys(&self) -> Vec<&'a SrcNode<ast::Type>> {
+        match *self {
+            Self::Unit => Vec::new(),
+            Self::Single(ty) => vec![ty],
+            Self::Tuple(fields) => fields.iter().collect(),
+            Self::Record(fields) => fields.iter().map(|(_, ty)| ty).collect(),
+        }
+    }
+
+    /// A binding that destructures the shape, binding the nth field to `{prefix}{n}`.
+    fn binding(&self, prefix: &str, span: Span) -> SrcNode<ast::Binding> {
+        let field = |i: usize| SrcNode::new(ast::Binding {
+            pat: SrcNode::new(ast::Pat::Wildcard, span),
+            name: Some(SrcNode::new(Ident::new(format!("{}{}", prefix, i)), span)),
+            ty: None,
+        }, span);
+        let pat = match self {
+            Self::Unit => ast::Pat::Tuple(Vec::new()),
+            Self::Single(_) => return field(0),
+            Self::Tuple(fields) => ast::Pat::Tuple((0..fields.len()).map(field).collect()),
+            Self::Record(fields) => ast::Pat::Record(fields
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| (SrcNode::new(**name, span), field(i)))
+                .collect()),
+        };
+        binding(pat, span)
+    }
+
+    /// An expression that constructs the shape from an expression for each field.
+    fn construct(&self, fields: Vec<SrcNode<ast::Expr>>, span: Span) -> SrcNode<ast::Expr> {
+        match self {
+            Self::Unit => SrcNode::new(ast::Expr::Tuple(Vec::new()), span),
+            Self::Single(_) => fields.into_iter().next().unwrap(),
+            Self::Tuple(_) => SrcNode::new(ast::Expr::Tuple(fields), span),
+            Self::Record(names) => SrcNode::new(ast::Expr::Record(names
+                .iter()
+                .map(|(name, _)| SrcNode::new(**name, span))
+                .zip(fields)
+                .collect()), span),
+        }
+    }
+}
+
+fn binding(pat: ast::Pat, span: Span) -> SrcNode<ast::Binding> {
+    SrcNode::new(ast::Binding {
+        pat: SrcNode::new(pat, span),
+        name: None,
+        ty: None,
+    }, span)
+}
+
+fn wildcard(span: Span) -> SrcNode<ast::Binding> { binding(ast::Pat::Wildcard, span) }
+
+fn deconstruct(cons: &SrcNode<Ident>, inner: SrcNode<ast::Binding>, span: Span) -> SrcNode<ast::Binding> {
+    binding(ast::Pat::Deconstruct(SrcNode::new(**cons, span), inner), span)
+}
+
+fn local(name: String, span: Span) -> SrcNode<ast::Expr> {
+    SrcNode::new(ast::Expr::Local(Ident::new(name)), span)
+}
+
+fn str_lit(s: String, span: Span) -> SrcNode<ast::Expr> {
+    SrcNode::new(ast::Expr::Literal(ast::Literal::Str(Intern::new(s))), span)
+}
+
+fn cons(name: &str, inner: SrcNode<ast::Expr>, span: Span) -> SrcNode<ast::Expr> {
+    SrcNode::new(ast::Expr::Cons(SrcNode::new(Ident::new(name), span), inner), span)
+}
+
+fn unit_cons(name: &str, span: Span) -> SrcNode<ast::Expr> {
+    cons(name, SrcNode::new(ast::Expr::Tuple(Vec::new()), span), span)
+}
+
+fn binary(op: ast::BinaryOp, a: SrcNode<ast::Expr>, b: SrcNode<ast::Expr>, span: Span) -> SrcNode<ast::Expr> {
+    SrcNode::new(ast::Expr::Binary(SrcNode::new(op, span), a, b), span)
+}
+
+/// `T.item(args...)`
+fn call_item(ty: &SrcNode<ast::Type>, item: &str, args: Vec<SrcNode<ast::Expr>>, span: Span) -> SrcNode<ast::Expr> {
+    let f = SrcNode::new(ast::Expr::ClassAccess(
+        SrcNode::new((**ty).clone(), span),
+        SrcNode::new(Ident::new(item), span),
+    ), span);
+    args
+        .into_iter()
+        .fold(f, |f, arg| SrcNode::new(ast::Expr::Apply(f, arg), span))
+}
+
+fn func(
+    arms: Vec<(Vec<SrcNode<ast::Binding>>, SrcNode<ast::Expr>)>,
+    span: Span,
+) -> SrcNode<ast::Expr> {
+    SrcNode::new(ast::Expr::Func(SrcNode::new(arms
+        .into_iter()
+        .map(|(bindings, body)| (SrcNode::new(bindings, span), None, body))
+        .collect(), span)), span)
+}
+
+fn value(name: &str, val: SrcNode<ast::Expr>, span: Span) -> ast::MemberItem {
+    ast::MemberItem::Value { name: SrcNode::new(Ident::new(name), span), val }
+}
+
+fn derive_debug(data: &ast::Data, span: Span) -> Vec<ast::MemberItem> {
+    let arms = data.variants
+        .iter()
+        .map(|(name, ty)| {
+            let shape = Shape::of(ty);
+            let fields = shape
+                .tys()
+                .into_iter()
+                .enumerate()
+                .map(|(i, ty)| call_item(ty, "debug", vec![local(format!("x{}", i), span)], span))
+                .collect::<Vec<_>>();
+            let mut parts = Vec::new();
+            match &shape {
+                Shape::Unit => parts.push(str_lit(format!("{}", **name), span)),
+                Shape::Single(_) | Shape::Tuple(_) => {
+                    parts.push(str_lit(format!("{}(", **name), span));
+                    for (i, field) in fields.into_iter().enumerate() {
+                        if i > 0 { parts.push(str_lit(", ".to_string(), span)); }
+                        parts.push(field);
+                    }
+                    parts.push(str_lit(")".to_string(), span));
+                },
+                Shape::Record(names) => {
+                    parts.push(str_lit(format!("{} {{ ", **name), span));
+                    for (i, ((field_name, _), field)) in names.iter().zip(fields).enumerate() {
+                        let sep = if i > 0 { ", " } else { "" };
+                        parts.push(str_lit(format!("{}{}: ", sep, **field_name), span));
+                        parts.push(field);
+                    }
+                    parts.push(str_lit(" }".to_string(), span));
+                },
+            }
+            let body = parts
+                .into_iter()
+                .reduce(|a, b| binary(ast::BinaryOp::Join, a, b, span))
+                .unwrap();
+            (vec![deconstruct(name, shape.binding("x", span), span)], body)
+        })
+        .collect();
+
+    vec![value("debug", func(arms, span), span)]
+}
+
+fn derive_eq(data: &ast::Data, span: Span) -> Vec<ast::MemberItem> {
+    let mut arms = data.variants
+        .iter()
+        .map(|(name, ty)| {
+            let shape = Shape::of(ty);
+            let body = shape
+                .tys()
+                .into_iter()
+                .enumerate()
+                .map(|(i, ty)| call_item(ty, "eq", vec![
+                    local(format!("x{}", i), span),
+                    local(format!("y{}", i), span),
+                ], span))
+                .reduce(|a, b| binary(ast::BinaryOp::And, a, b, span))
+                .unwrap_or_else(|| unit_cons("True", span));
+            (vec![
+                deconstruct(name, shape.binding("x", span), span),
+                deconstruct(name, shape.binding("y", span), span),
+            ], body)
+        })
+        .collect::<Vec<_>>();
+
+    if data.variants.len() > 1 {
+        arms.push((vec![wildcard(span), wildcard(span)], unit_cons("False", span)));
+    }
+
+    vec![value("eq", func(arms, span), span)]
+}
+
+fn derive_ord(data: &ast::Data, span: Span) -> Vec<ast::MemberItem> {
+    // Values of the same variant are compared lexicographically, field by field
+    let mut arms = data.variants
+        .iter()
+        .map(|(name, ty)| {
+            let shape = Shape::of(ty);
+            let body = shape
+                .tys()
+                .into_iter()
+                .enumerate()
+                .rev()
+                .fold(unit_cons("Equal", span), |rest, (i, ty)| {
+                    let ord = call_item(ty, "cmp", vec![
+                        local(format!("x{}", i), span),
+                        local(format!("y{}", i), span),
+                    ], span);
+                    SrcNode::new(ast::Expr::Match(SrcNode::new(vec![ord], span), vec![
+                        (
+                            SrcNode::new(vec![deconstruct(&SrcNode::new(Ident::new("Equal"), span), binding(ast::Pat::Tuple(Vec::new()), span), span)], span),
+                            None,
+                            rest,
+                        ),
+                        (
+                            SrcNode::new(vec![SrcNode::new(ast::Binding {
+                                pat: SrcNode::new(ast::Pat::Wildcard, span),
+                                name: Some(SrcNode::new(Ident::new("ord"), span)),
+                                ty: None,
+                            }, span)], span),
+                            None,
+                            local("ord".to_string(), span),
+                        ),
+                    ]), span)
+                });
+            (vec![
+                deconstruct(name, shape.binding("x", span), span),
+                deconstruct(name, shape.binding("y", span), span),
+            ], body)
+        })
+        .collect::<Vec<_>>();
+
+    // Values of different variants are ordered by the order in which the variants were declared
+    for (name, _) in data.variants.iter().take(data.variants.len() - 1) {
+        arms.push((vec![deconstruct(name, wildcard(span), span), wildcard(span)], unit_cons("Less", span)));
+        arms.push((vec![wildcard(span), deconstruct(name, wildcard(span), span)], unit_cons("More", span)));
+    }
+
+    vec![value("cmp", func(arms, span), span)]
+}
+
+fn derive_default(data: &ast::Data, span: Span) -> Vec<ast::MemberItem> {
+    // The default value is the first variant, with every field set to its default
+    let (name, ty) = &data.variants[0];
+    let shape = Shape::of(ty);
+    let fields = shape
+        .tys()
+        .into_iter()
+        .map(|ty| call_item(ty, "default", Vec::new(), span))
+        .collect();
+
+    vec![value("default", cons(name.as_str(), shape.construct(fields, span), span), span)]
+}
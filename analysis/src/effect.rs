@@ -53,6 +53,10 @@ impl Effects {
         self.effect_decls.iter().enumerate().map(|(i, eff)| (EffectDeclId(i, *eff.name), eff))
     }
 
+    pub fn iter_aliases(&self) -> impl Iterator<Item = (EffectAliasId, &EffectAlias)> {
+        self.effect_aliases.iter().enumerate().map(|(i, alias)| (EffectAliasId(i), alias))
+    }
+
     // pub fn iter(&self) -> impl Iterator<Item = (EffectDeclId, &EffectDecl)> {
     //     self.effect_names.iter().enumerate().map(|(i, eff)| (EffectDeclId(i), eff))
     // }
@@ -21,6 +21,8 @@ pub enum Error {
     NoSuchCons(SrcNode<Ident>),
     NoSuchClass(SrcNode<Ident>),
     NoSuchClassItem(SrcNode<Ident>, SrcNode<Ident>),
+    // Class, data type
+    CannotDerive(SrcNode<Ident>, SrcNode<Ident>),
     NoSuchEffect(SrcNode<Ident>),
     AmbiguousClassItem(SrcNode<Ident>, Vec<ClassId>),
     MissingClassItem(Span, SrcNode<Ident>, SrcNode<Ident>),
@@ -43,8 +45,12 @@ pub enum Error {
     NoEntryPoint(Span),
     MultipleEntryPoints(Span, Span),
     GenericEntryPoint(SrcNode<Ident>, Span),
+    InvalidTest(SrcNode<Ident>, TyId),
+    InvalidProperty(SrcNode<Ident>, TyId),
     InvalidIntrinsic(SrcNode<Ident>),
     Unsupported(Span, &'static str),
+    // The tail of a record and the type that it was instantiated with, which isn't a record
+    RecordTailNotRecord(Span, String),
     MissingLangItem(&'static str),
     NoBasin(Span),
 }
@@ -216,6 +222,14 @@ impl Error {
                 vec![(a.span(), format!("Does not exist"), Color::Red)],
                 vec![],
             ),
+            Error::CannotDerive(class, data) => (
+                format!("Class {} cannot be derived for {}", (*class).fg(Color::Red), (*data).fg(Color::Yellow)),
+                vec![
+                    (class.span(), format!("Cannot be derived"), Color::Red),
+                    (data.span(), format!("Data type declared here"), Color::Yellow),
+                ],
+                vec![format!("Only {}, {}, {}, and {} can be derived, and only for data types with at least one variant", "Debug".fg(Color::Blue), "Eq".fg(Color::Blue), "Ord".fg(Color::Blue), "Default".fg(Color::Blue))],
+            ),
             Error::NoSuchClassItem(item, class) => (
                 format!("No such item {} on class {}", (*item).fg(Color::Red), (*class).fg(Color::Red)),
                 vec![
@@ -399,6 +413,20 @@ impl Error {
                 ],
                 vec![format!("A program cannot be generic over types")],
             ),
+            Error::InvalidTest(name, ty) => (
+                format!("Test {} has type {}, which cannot be tested", (*name).fg(Color::Red), display(ty).fg(Color::Yellow)),
+                vec![
+                    (name.span(), format!("Marked as a test here"), Color::Red),
+                ],
+                vec![format!("A test must have type {} or {}", "Bool".fg(Color::Blue), "io ~ ()".fg(Color::Blue))],
+            ),
+            Error::InvalidProperty(name, ty) => (
+                format!("Property {} has type {}, which cannot be tested", (*name).fg(Color::Red), display(ty).fg(Color::Yellow)),
+                vec![
+                    (name.span(), format!("Marked as a property here"), Color::Red),
+                ],
+                vec![format!("A property must have type {}, where {} is a member of {}", "A -> Bool".fg(Color::Blue), "A".fg(Color::Blue), "Arbitrary".fg(Color::Blue))],
+            ),
             Error::AmbiguousClassItem(item, candidate_classes) => (
                 format!("Class item {} is ambiguous", (*item).fg(Color::Red)),
                 vec![
@@ -424,6 +452,13 @@ impl Error {
                 ],
                 vec![],
             ),
+            Error::RecordTailNotRecord(span, found) => (
+                format!("Type {} cannot be the tail of a record", found.fg(Color::Red)),
+                vec![
+                    (span, format!("This was instantiated with {}", found.fg(Color::Red)), Color::Red),
+                ],
+                vec![format!("Only records can be extended with more fields")],
+            ),
             Error::MissingLangItem(name) => (
                 format!("Lang item {} is missing", name.fg(Color::Yellow)),
                 Vec::new(),
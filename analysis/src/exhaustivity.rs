@@ -12,7 +12,7 @@ pub enum AbstractPat {
     Nat(Ranges<u64>),
     Int(Ranges<i64>),
     Real(f64),
-    Char(char),
+    Char(Ranges<char>),
     // (_, is_tuple)
     Record(Vec<(Ident, Self)>, bool),
     Variant(DataId, Ident, Box<Self>),
@@ -36,14 +36,21 @@ impl AbstractPat {
                 range.insert(*x..*x + 1);
                 range
             }),
-            hir::Pat::Literal(hir::Literal::Char(c)) => Self::Char(*c),
-            hir::Pat::Literal(hir::Literal::Str(x)) => Self::ListExact(x.chars().map(Self::Char).collect()),
+            hir::Pat::Literal(hir::Literal::Real(x)) => Self::Real(*x),
+            hir::Pat::Literal(hir::Literal::Char(c)) => Self::Char(Self::char_range(*c)),
+            hir::Pat::Literal(hir::Literal::Str(x)) => Self::ListExact(x
+                .chars()
+                .map(|c| Self::Char(Self::char_range(c)))
+                .collect()),
             hir::Pat::Single(inner) => Self::from_binding(ctx, inner),
             hir::Pat::Add(lhs, rhs) if matches!(&*lhs.pat, hir::Pat::Wildcard) => Self::Nat({
                 let mut range = Ranges::new();
                 range.insert(**rhs..);
                 range
             }),
+            // A refutable left-hand side covers some values of at least `rhs`, but we conservatively assume that it
+            // covers none of them
+            hir::Pat::Add(_, _) => Self::Nat(Ranges::new()),
             hir::Pat::Decons(data, cons, inner) => AbstractPat::Variant(**data, *cons, Box::new(AbstractPat::from_binding(ctx, inner))),
             hir::Pat::ListExact(items) => AbstractPat::ListExact(items
                 .iter()
@@ -57,23 +64,30 @@ impl AbstractPat {
                 .iter()
                 .map(|(name, field)| (*name, AbstractPat::from_binding(ctx, field)))
                 .collect(), *is_tuple),
-            pat => todo!("{:?}", pat),
         }
     }
 
+    fn char_range(c: char) -> Ranges<char> {
+        let mut range = Ranges::new();
+        range.insert(c..=c);
+        range
+    }
+
     fn is_refutable_basic(&self, ctx: &Context) -> bool {
         match self {
             AbstractPat::Wildcard => false,
             AbstractPat::Nat(set) => !set.clone().invert().is_empty(),
             AbstractPat::Int(set) => !set.clone().invert().is_empty(),
-            AbstractPat::Char(_) => true,
+            AbstractPat::Char(set) => !set.clone().invert().is_empty(),
+            // Reals are not treated as a finite domain, so only a wildcard can cover them
+            AbstractPat::Real(_) => true,
             AbstractPat::ListExact(_) => true,
             AbstractPat::ListFront(items, tail) => !items.is_empty() || tail.is_refutable_basic(ctx),
             AbstractPat::Variant(data, _, _) => ctx.datas.get_data(*data).cons.len() > 1,
             AbstractPat::Record(fields, _) => !fields
                 .iter()
                 .all(|(_, field)| !field.is_refutable_basic(ctx)),
-            pat => todo!("{:?}", pat),
+            AbstractPat::Gen(_) => false,
         }
     }
 
@@ -119,24 +133,31 @@ impl AbstractPat {
                 covered.clone().invert().into_iter().next().map(ExamplePrim::Int).map(ExamplePat::Prim)
             },
             Ty::Prim(Prim::Char) => {
+                let mut covered = Ranges::new();
                 for pat in filter {
                     match pat {
                         AbstractPat::Wildcard => return None,
-                        AbstractPat::Char(_) => {},
+                        AbstractPat::Char(x) => covered = covered.clone().union(x.clone()),
                         _ => return None, // Type mismatch, don't yield an error because one was already generated
                     }
                 }
-                Some(ExamplePat::Wildcard)
+                covered.clone().invert().into_iter().next().map(ExamplePrim::Char).map(ExamplePat::Prim)
             },
             Ty::Prim(Prim::Real) => {
+                // Real literals can never cover every value, so we only need to find a value that none of them match
+                let mut covered = Vec::new();
                 for pat in filter {
                     match pat {
                         AbstractPat::Wildcard => return None,
-                        AbstractPat::Real(_) => {},
+                        AbstractPat::Real(x) => covered.push(*x),
                         _ => return None, // Type mismatch, don't yield an error because one was already generated
                     }
                 }
-                Some(ExamplePat::Wildcard)
+                (0u32..)
+                    .map(f64::from)
+                    .find(|x| !covered.contains(x))
+                    .map(ExamplePrim::Real)
+                    .map(ExamplePat::Prim)
             },
             Ty::Prim(Prim::Universe) => {
                 for pat in filter {
@@ -147,7 +168,6 @@ impl AbstractPat {
                 }
                 Some(ExamplePat::Wildcard)
             },
-            Ty::Prim(prim) => todo!("{:?}", prim),
             Ty::Record(fields, is_tuple) if fields.len() == 1 => {
                 let mut inners = Vec::new();
                 for pat in filter {
@@ -233,7 +253,7 @@ impl AbstractPat {
                 }
                 None
             },
-            Ty::Gen(_, _) | Ty::Assoc(_, _, _) => {
+            Ty::Gen(_, _) | Ty::SelfType | Ty::Assoc(_, _, _) | Ty::Row(_, _) => {
                 for pat in filter {
                     match pat {
                         AbstractPat::Wildcard => return None,
@@ -274,7 +294,6 @@ impl AbstractPat {
                 }
                 Some(ExamplePat::Wildcard)
             },
-            ty => todo!("{:?}", ty),
         }
     }
 }
@@ -283,6 +302,8 @@ impl AbstractPat {
 pub enum ExamplePrim {
     Nat(u64),
     Int(i64),
+    Real(f64),
+    Char(char),
 }
 
 impl fmt::Display for ExamplePrim {
@@ -290,6 +311,8 @@ impl fmt::Display for ExamplePrim {
         match self {
             Self::Nat(x) => write!(f, "{}", x),
             Self::Int(x) => write!(f, "{}", x),
+            Self::Real(x) => write!(f, "{:?}", x),
+            Self::Char(c) => write!(f, "{:?}", c),
         }
     }
 }
@@ -145,6 +145,7 @@ pub enum Intrinsic {
     JoinList,
     Propagate,
     Dispatch,
+    Host(Ident),
 }
 
 #[derive(Debug)]
@@ -158,8 +159,8 @@ pub enum Expr<M: Meta> {
     // (_, is_tuple)
     Record(BTreeMap<SrcNode<Ident>, Node<Self, M>>, bool),
     Access(Node<Self, M>, SrcNode<Ident>),
-    // hidden_outer
-    Match(bool, Node<Self, M>, Vec<(Node<Binding<M>, M>, Node<Self, M>)>),
+    // (hidden_outer, pred, [(binding, guard, body)])
+    Match(bool, Node<Self, M>, Vec<(Node<Binding<M>, M>, Option<Node<Self, M>>, Node<Self, M>)>),
     Func(Node<Ident, M>, Node<Self, M>),
     Apply(Node<Self, M>, Node<Self, M>),
     Cons(M::Data, Ident, Node<Self, M>),
@@ -169,6 +170,10 @@ pub enum Expr<M: Meta> {
 
     Intrinsic(SrcNode<Intrinsic>, Vec<Node<Self, M>>),
     Update(Node<Self, M>, Vec<(SrcNode<Ident>, Node<Self, M>)>),
+    // Add fields to a record, or replace existing ones
+    Extend(Node<Self, M>, BTreeMap<SrcNode<Ident>, Node<Self, M>>),
+    // Remove fields from a record
+    Restrict(Node<Self, M>, Vec<SrcNode<Ident>>),
 
     // Blocks propagation of effects, collecting them
     // i.e: `@{ foo?; bar?; x }` gets type `foo + bar ~ X`
@@ -217,9 +222,12 @@ impl Expr<ConMeta> {
                 .for_each(|arg| arg.required_locals_inner(stack, required)),
             Expr::Match(_, pred, arms) => {
                 pred.required_locals_inner(stack, required);
-                for (arm, body) in arms {
+                for (arm, guard, body) in arms {
                     let old_stack = stack.len();
                     arm.visit_bindings_inner(&mut |name, _| stack.push(**name));
+                    if let Some(guard) = guard {
+                        guard.required_locals_inner(stack, required);
+                    }
                     body.required_locals_inner(stack, required);
                     stack.truncate(old_stack);
                 }
@@ -254,6 +262,13 @@ impl Expr<ConMeta> {
                     .iter()
                     .for_each(|(_, field)| field.required_locals_inner(stack, required));
             },
+            Expr::Extend(record, fields) => {
+                record.required_locals_inner(stack, required);
+                fields
+                    .iter()
+                    .for_each(|(_, field)| field.required_locals_inner(stack, required));
+            },
+            Expr::Restrict(record, _) => record.required_locals_inner(stack, required),
             Expr::Basin(_, inner) => inner.required_locals_inner(stack, required),
             Expr::Suspend(_, inner) => inner.required_locals_inner(stack, required),
             Expr::Handle { expr, handlers } => {
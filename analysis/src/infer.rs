@@ -16,6 +16,8 @@ pub enum TyInfo {
     List(TyVar),
     // (_, is_tuple)
     Record(BTreeMap<Ident, TyVar>, bool),
+    // An extensible record: the given fields, plus those of the tail. Fields override those of the tail.
+    Row(BTreeMap<Ident, TyVar>, TyVar),
     Func(TyVar, TyVar),
     Data(DataId, Vec<TyVar>),
     Gen(usize, GenScopeId, Span),
@@ -86,6 +88,8 @@ trait FlowInfer<'a>: AsRef<Infer<'a>> {
     fn set_class_info(&mut self, x: ClassVar, info: ClassInfo);
     fn set_error(&mut self, x: TyVar);
     fn set_unknown_flow(&mut self, x: TyVar, y: TyVar);
+    // Returns `None` if new type variables cannot be created
+    fn insert(&mut self, span: Span, info: TyInfo) -> Option<TyVar>;
     fn emit_error(&mut self, err: InferError);
     fn make_check_eff(&mut self, x: (EffectVar, TyVar), y: (EffectVar, TyVar)) -> bool;
 }
@@ -101,6 +105,7 @@ impl<'a, 'b> FlowInfer<'a> for CheckFlow<'a, 'b> {
     fn set_class_info(&mut self, x: ClassVar, info: ClassInfo) {}
     fn set_error(&mut self, x: TyVar) { self.1 = Some(false); }
     fn set_unknown_flow(&mut self, x: TyVar, y: TyVar) { if self.1 == Some(true) { self.1 = None; } }
+    fn insert(&mut self, span: Span, info: TyInfo) -> Option<TyVar> { None }
     fn emit_error(&mut self, err: InferError) { self.1 = Some(false); }
     fn make_check_eff(&mut self, x: (EffectVar, TyVar), y: (EffectVar, TyVar)) -> bool { false }
 }
@@ -119,6 +124,7 @@ impl<'a, 'b> FlowInfer<'a> for MakeFlow<'a, 'b> {
         self.0.set_error(x);
     }
     fn set_unknown_flow(&mut self, x: TyVar, y: TyVar) {}
+    fn insert(&mut self, span: Span, info: TyInfo) -> Option<TyVar> { Some(self.0.insert(span, info)) }
     fn emit_error(&mut self, err: InferError) {
         self.0.errors.push(err);
     }
@@ -370,6 +376,12 @@ impl<'a> Infer<'a> {
                 TyInfo::Record(fields, _) => fields
                     .into_iter()
                     .for_each(|(_, field)| self.set_error(field)),
+                TyInfo::Row(fields, tail) => {
+                    fields
+                        .into_iter()
+                        .for_each(|(_, field)| self.set_error(field));
+                    self.set_error(tail);
+                },
                 TyInfo::Func(i, o) => {
                     self.set_error(i);
                     self.set_error(o);
@@ -441,6 +453,10 @@ impl<'a> Infer<'a> {
                 .into_iter()
                 .map(|(name, field)| (name, self.instantiate(field, span, f, self_ty)))
                 .collect(), is_tuple),
+            Ty::Row(fields, tail) => TyInfo::Row(fields
+                .into_iter()
+                .map(|(name, field)| (name, self.instantiate(field, span, f, self_ty)))
+                .collect(), self.instantiate(tail, span, f, self_ty)),
             Ty::Func(i, o) => TyInfo::Func(self.instantiate(i, span, f, self_ty), self.instantiate(o, span, f, self_ty)),
             Ty::Data(data, params) => TyInfo::Data(data, params
                 .into_iter()
@@ -653,6 +669,9 @@ impl<'a> Infer<'a> {
                 TyInfo::Record(ys, _) => ys
                     .into_iter()
                     .any(|(_, y)| x == y || self.occurs_in_inner(x, y, seen)),
+                TyInfo::Row(ys, tail) => x == tail || self.occurs_in_inner(x, tail, seen) || ys
+                    .into_iter()
+                    .any(|(_, y)| x == y || self.occurs_in_inner(x, y, seen)),
                 TyInfo::Data(_, ys) => ys
                     .into_iter()
                     .any(|y| x == y || self.occurs_in_inner(x, y, seen)),
@@ -897,6 +916,7 @@ impl<'a> Infer<'a> {
                 .all(|x| ys.contains_key(x)) => xs
                     .into_iter()
                     .try_for_each(|(x, x_ty)| Self::flow_inner(infer, x_ty, ys[&x])),
+            (TyInfo::Row(_, _), _) | (_, TyInfo::Row(_, _)) => Self::flow_rows(infer, x, y),
             (TyInfo::Func(x_i, x_o), TyInfo::Func(y_i, y_o)) => {
                 let i_err = Self::flow_inner(infer, y_i, x_i).err().map(|(a, b)| (b, a)); // Input is contravariant
                 let o_err = Self::flow_inner(infer, x_o, y_o).err();
@@ -944,6 +964,115 @@ impl<'a> Infer<'a> {
         }
     }
 
+    // Flatten a record type into its fields and the tail that contains any further fields (if it is not yet known)
+    fn row_fields(&self, ty: TyVar) -> (BTreeMap<Ident, TyVar>, Option<TyVar>) {
+        match self.follow_info(ty) {
+            TyInfo::Record(fields, false) => (fields, None),
+            TyInfo::Row(fields, tail) => {
+                let (mut tail_fields, tail) = self.row_fields(tail);
+                tail_fields.extend(fields);
+                (tail_fields, tail)
+            },
+            _ => (BTreeMap::new(), Some(self.follow(ty))),
+        }
+    }
+
+    // Flow one record type into another where at least one is extensible. Fields missing from one side must be
+    // provided by the tail of the other, which is only possible if that tail is not yet known.
+    fn flow_rows<I: FlowInfer<'a>>(infer: &mut I, x: TyVar, y: TyVar) -> Result<(), (TyVar, TyVar)> {
+        let (xs, x_tail) = infer.as_ref().row_fields(x);
+        let (ys, y_tail) = infer.as_ref().row_fields(y);
+
+        let (common_x, common_y): (Vec<_>, Vec<_>) = xs
+            .iter()
+            .filter_map(|(name, x_ty)| Some((*x_ty, *ys.get(name)?)))
+            .unzip();
+        let field_err = Self::flow_many(infer, common_x, common_y).err();
+
+        let x_only = xs
+            .iter()
+            .filter(|(name, _)| !ys.contains_key(name))
+            .map(|(name, ty)| (*name, *ty))
+            .collect::<BTreeMap<_, _>>();
+        let y_only = ys
+            .iter()
+            .filter(|(name, _)| !xs.contains_key(name))
+            .map(|(name, ty)| (*name, *ty))
+            .collect::<BTreeMap<_, _>>();
+
+        let is_unknown = |tail: Option<TyVar>| tail
+            .map_or(false, |tail| matches!(infer.as_ref().follow_info(tail), TyInfo::Unknown(_)));
+        let (x_tail_unknown, y_tail_unknown) = (is_unknown(x_tail), is_unknown(y_tail));
+        let is_error = |tail: Option<TyVar>| tail.map_or(false, |tail| infer.as_ref().is_error(tail));
+        let tails_error = is_error(x_tail) || is_error(y_tail);
+        // Only records, generic types, and types not yet known may act as the tail of a record
+        let is_row = |tail: Option<TyVar>| tail.map_or(true, |tail| matches!(
+            infer.as_ref().follow_info(tail),
+            TyInfo::Unknown(_) | TyInfo::Gen(_, _, _),
+        ));
+        let tails_row = is_row(x_tail) && is_row(y_tail);
+
+        let (x_span, y_span) = (infer.as_ref().span(x), infer.as_ref().span(y));
+        let tail_err = if tails_error {
+            Ok(())
+        } else if !tails_row {
+            Err((x, y))
+        } else if x_tail.is_some() && x_tail == y_tail {
+            // Both records share a tail, so they must have the same fields
+            if x_only.is_empty() && y_only.is_empty() { Ok(()) } else { Err((x, y)) }
+        } else if (!x_only.is_empty() && !y_tail_unknown) || (!y_only.is_empty() && !x_tail_unknown) {
+            // Fields cannot be added to a closed record or to a generic row
+            Err((x, y))
+        } else {
+            match (x_tail, y_tail) {
+                (None, None) => Ok(()),
+                (None, Some(y_tail)) => Self::flow_rest(infer, (x_span, x_only, None), y_tail, false, (x, y)),
+                (Some(x_tail), None) => Self::flow_rest(infer, (y_span, y_only, None), x_tail, true, (x, y)),
+                (Some(x_tail), Some(y_tail)) if x_only.is_empty() => {
+                    Self::flow_rest(infer, (y_span, y_only, Some(y_tail)), x_tail, true, (x, y))
+                },
+                (Some(x_tail), Some(y_tail)) if y_only.is_empty() => {
+                    Self::flow_rest(infer, (x_span, x_only, Some(x_tail)), y_tail, false, (x, y))
+                },
+                // Both records have fields that the other lacks, so their tails must share a common tail
+                (Some(x_tail), Some(y_tail)) => match infer.insert(x_span, TyInfo::Unknown(None)) {
+                    Some(common) => Self::flow_rest(infer, (y_span, y_only, Some(common)), x_tail, true, (x, y))
+                        .and_then(|()| Self::flow_rest(infer, (x_span, x_only, Some(common)), y_tail, false, (x, y))),
+                    None => {
+                        infer.set_unknown_flow(x, y);
+                        Ok(())
+                    },
+                },
+            }
+        };
+
+        field_err.map(Err).unwrap_or(tail_err)
+    }
+
+    // Flow the remaining fields of one record (plus an optional tail) into the unknown tail of another
+    fn flow_rest<I: FlowInfer<'a>>(
+        infer: &mut I,
+        (span, fields, inner): (Span, BTreeMap<Ident, TyVar>, Option<TyVar>),
+        tail: TyVar,
+        rev: bool,
+        (x, y): (TyVar, TyVar),
+    ) -> Result<(), (TyVar, TyVar)> {
+        let info = match inner {
+            Some(inner) if fields.is_empty() => TyInfo::Ref(inner),
+            Some(inner) => TyInfo::Row(fields, inner),
+            None => TyInfo::Record(fields, false),
+        };
+        match infer.insert(span, info) {
+            Some(rest) if rev => Self::flow_inner(infer, tail, rest),
+            Some(rest) => Self::flow_inner(infer, rest, tail),
+            // We can't create new types while checking flow, so we don't know whether this flow is valid yet
+            None => {
+                infer.set_unknown_flow(x, y);
+                Ok(())
+            },
+        }
+    }
+
     /// Reinstantiate a type variable, replacing any known generic types with new unknown ones
     // TODO: Is this a good way to resolve the problem of type inference of recursive definitions in the presence of
     // polymorphism?
@@ -972,6 +1101,14 @@ impl<'a> Infer<'a> {
                     .collect();
                 self.insert(self.span(ty), TyInfo::Record(fields, is_tuple))
             },
+            TyInfo::Row(fields, tail) => {
+                let fields = fields
+                    .into_iter()
+                    .map(|(name, field)| (name, self.reinstantiate(span, field)))
+                    .collect();
+                let tail = self.reinstantiate(span, tail);
+                self.insert(self.span(ty), TyInfo::Row(fields, tail))
+            },
             TyInfo::Data(data, args) => {
                 let args = args
                     .into_iter()
@@ -1057,6 +1194,22 @@ impl<'a> Infer<'a> {
             } else {
                 Some(false)
             },
+            TyInfo::Row(_, _) => {
+                let (fields, tail) = self.row_fields(record);
+                if let Some(field_ty) = fields.get(&**field_name) {
+                    if flow_out {
+                        self.make_flow(*field_ty, field, field_name.span());
+                    } else {
+                        self.make_flow(field, *field_ty, field_name.span());
+                    }
+                    Some(true)
+                } else if tail.map_or(false, |tail| matches!(self.follow_info(tail), TyInfo::Unknown(_))) {
+                    // The field might still be provided by the tail
+                    None
+                } else {
+                    Some(false)
+                }
+            },
             // Field access through a data type
             TyInfo::Data(data, params) => {
                 // TODO: Use `self.ctx.follow_field_access(...)` but work out how to instantiate type parameters
@@ -1701,8 +1854,29 @@ impl<'a> Infer<'a> {
         }
     }
 
+    // Field accesses on records that are still not fully known are assumed to be accesses on extensible records that
+    // contain the field. Returns `true` if any new information was produced.
+    fn default_accesses(&mut self) -> bool {
+        let mut progress = false;
+        for c in self.constraints.clone() {
+            if let Constraint::Access(record, field_name, field) | Constraint::Update(record, field_name, field) = c {
+                if let (fields, Some(tail)) = self.row_fields(record) {
+                    if !fields.contains_key(&*field_name) && matches!(self.follow_info(tail), TyInfo::Unknown(_)) {
+                        let rest = self.unknown(self.span(tail));
+                        self.set_info(tail, TyInfo::Row(std::iter::once((*field_name, field)).collect(), rest));
+                        progress = true;
+                    }
+                }
+            }
+        }
+        progress
+    }
+
     pub fn into_checked(mut self) -> (Checked<'a>, Vec<Error>) {
         self.resolve_constraints();
+        while self.default_accesses() {
+            self.resolve_constraints();
+        }
 
         let mut errors = std::mem::take(&mut self.errors);
 
@@ -1833,6 +2007,18 @@ impl<'a> Checked<'a> {
                     .into_iter()
                     .map(|(name, field)| (name, self.reify_inner(field)))
                     .collect(), is_tuple),
+                // Rows whose tails are known records are flattened
+                TyInfo::Row(_, _) => {
+                    let (fields, tail) = self.infer.row_fields(var);
+                    let fields = fields
+                        .into_iter()
+                        .map(|(name, field)| (name, self.reify_inner(field)))
+                        .collect();
+                    match tail {
+                        Some(tail) => Ty::Row(fields, self.reify_inner(tail)),
+                        None => Ty::Record(fields, false),
+                    }
+                },
                 TyInfo::Func(i, o) => Ty::Func(self.reify_inner(i), self.reify_inner(o)),
                 TyInfo::Data(data, args) => Ty::Data(data, args
                     .into_iter()
@@ -5,6 +5,7 @@ pub mod concrete;
 pub mod context;
 pub mod data;
 pub mod def;
+pub mod derive;
 pub mod effect;
 pub mod error;
 pub mod exhaustivity;
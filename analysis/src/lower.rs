@@ -159,6 +159,17 @@ impl ToHir for ast::Type {
                 .iter()
                 .map(|(name, field)| (**name, field.to_hir(cfg, infer, scope).meta().1))
                 .collect(), false),
+            ast::Type::Row(fields, tail) => {
+                let tail_ty = tail.to_hir(cfg, infer, scope).meta().1;
+                // Only records (or types that might yet be records, like generic rows) may act as the tail of a record
+                let rest = infer.unknown(tail.span());
+                let tail_row = infer.insert(tail.span(), TyInfo::Row(BTreeMap::new(), rest));
+                infer.make_flow(tail_ty, tail_row, tail.span());
+                TyInfo::Row(fields
+                    .iter()
+                    .map(|(name, field)| (**name, field.to_hir(cfg, infer, scope).meta().1))
+                    .collect(), tail_ty)
+            },
             ast::Type::Func(i, o) => TyInfo::Func(i.to_hir(cfg, infer, scope).meta().1, o.to_hir(cfg, infer, scope).meta().1),
             ast::Type::Data(name, params) => match (name.as_str(), params.len()) {
                 ("Self", 0) => if let Some(var) = infer.self_type() {
@@ -737,7 +748,7 @@ impl ToHir for ast::Expr {
                             InferNode::new(hir::Expr::Match(
                                 false,
                                 val,
-                                vec![(binding, then)],
+                                vec![(binding, None, then)],
                             ), (span, ty))
                         },
                         None => then.to_hir(cfg, infer, scope),
@@ -766,12 +777,14 @@ impl ToHir for ast::Expr {
 
                     let arms = arms
                         .iter()
-                        .map(|(bindings, body)| {
+                        .map(|(bindings, guard, body)| {
                             let binding = tupleify_binding(bindings, cfg, infer, scope);
                             infer.make_flow(pred.meta().1, binding.meta().1, binding.meta().0);
-                            let body = body.to_hir(cfg, infer, &scope.with_many(&binding.get_binding_tys()));
+                            let scope = scope.with_many(&binding.get_binding_tys());
+                            let guard = guard.as_ref().map(|guard| guard_to_hir(guard, cfg, infer, &scope));
+                            let body = body.to_hir(cfg, infer, &scope);
                             infer.make_flow(body.meta().1, output_ty, EqInfo::new(self.span(), format!("Branches must produce compatible values")));
-                            (binding, body)
+                            (binding, guard, body)
                         })
                         .collect();
 
@@ -789,8 +802,8 @@ impl ToHir for ast::Expr {
                 infer.make_flow(b.meta().1, output_ty, EqInfo::new(self.span(), format!("Branches must produce compatible values")));
                 let unit = InferNode::new(hir::Binding::unit(pred.meta().0), (pred.meta().0, infer.insert(pred.meta().0, TyInfo::tuple(Vec::new()))));
                 let arms = vec![
-                    (InferNode::new(hir::Binding::from_pat(SrcNode::new(hir::Pat::Decons(SrcNode::new(bool_data, pred.meta().0), Ident::new("True"), unit.clone()), pred.meta().0)), *pred.meta()), a),
-                    (InferNode::new(hir::Binding::from_pat(SrcNode::new(hir::Pat::Decons(SrcNode::new(bool_data, pred.meta().0), Ident::new("False"), unit), pred.meta().0)), *pred.meta()), b),
+                    (InferNode::new(hir::Binding::from_pat(SrcNode::new(hir::Pat::Decons(SrcNode::new(bool_data, pred.meta().0), Ident::new("True"), unit.clone()), pred.meta().0)), *pred.meta()), None, a),
+                    (InferNode::new(hir::Binding::from_pat(SrcNode::new(hir::Pat::Decons(SrcNode::new(bool_data, pred.meta().0), Ident::new("False"), unit), pred.meta().0)), *pred.meta()), None, b),
                 ];
                 (TyInfo::Ref(output_ty), hir::Expr::Match(false, pred, arms))
             } else {
@@ -814,7 +827,7 @@ impl ToHir for ast::Expr {
                         (TyInfo::Error(ErrorReason::Unknown), hir::Expr::Error)
                     } else {
                         let output_ty = infer.unknown(if arms.len() == 1 {
-                            arms[0].1.span()
+                            arms[0].2.span()
                         } else {
                             arms.span()
                         });
@@ -834,12 +847,14 @@ impl ToHir for ast::Expr {
 
                         let arms = arms
                             .iter()
-                            .map(|(bindings, body)| {
+                            .map(|(bindings, guard, body)| {
                                 let binding = tupleify_binding(bindings, cfg, infer, scope);
                                 infer.make_flow(pred.meta().1, binding.meta().1, binding.meta().0);
-                                let body = body.to_hir(cfg, infer, &scope.with_many(&binding.get_binding_tys()));
+                                let scope = scope.with_many(&binding.get_binding_tys());
+                                let guard = guard.as_ref().map(|guard| guard_to_hir(guard, cfg, infer, &scope));
+                                let body = body.to_hir(cfg, infer, &scope);
                                 infer.make_flow(body.meta().1, output_ty, EqInfo::new(self.span(), format!("Branches must produce compatible values")));
-                                (binding, body)
+                                (binding, guard, body)
                             })
                             .collect();
 
@@ -923,6 +938,67 @@ impl ToHir for ast::Expr {
                 // TODO: Don't use a hard, preserve inner expression
                 (TyInfo::Error(ErrorReason::Unknown), hir::Expr::Error)
             },
+            ast::Expr::Interp(parts) => {
+                // Interpolated strings desugar to `"a" ++ x->show ++ "b" ++ y->debug`
+                let mut parts = parts
+                    .iter()
+                    .map(|part| match part {
+                        ast::InterpPart::Str(s) => {
+                            let litr = ast::Literal::Str(**s);
+                            let ty_info = litr_ty_info(&litr, infer, s.span());
+                            InferNode::new(hir::Expr::Literal(litr), (s.span(), infer.insert(s.span(), ty_info)))
+                        },
+                        ast::InterpPart::Expr(expr, is_debug) => {
+                            // Type errors should point at the embedded expression rather than the whole string
+                            let span = expr.span();
+                            let expr = expr.to_hir(cfg, infer, scope);
+                            let (class_id, field) = if *is_debug {
+                                (infer.ctx().classes.lang.debug, SrcNode::new(Ident::new("debug"), span))
+                            } else {
+                                (infer.ctx().classes.lang.show, SrcNode::new(Ident::new("show"), span))
+                            };
+
+                            if let Some(class_id) = class_id {
+                                let output_ty = infer.unknown(span);
+                                let func = infer.insert(span, TyInfo::Func(expr.meta().1, output_ty));
+                                let class = infer.make_class_field_known(expr.meta().1, field.clone(), (class_id, vec![]), func, span);
+
+                                InferNode::new(hir::Expr::Apply(
+                                    InferNode::new(hir::Expr::ClassAccess(*expr.meta(), class, field), (span, func)),
+                                    expr,
+                                ), (span, output_ty))
+                            } else {
+                                infer.ctx_mut().emit(Error::MissingLangItem(if *is_debug { "debug" } else { "show" }));
+                                InferNode::new(hir::Expr::Error, (span, infer.insert(span, TyInfo::Error(ErrorReason::Unknown))))
+                            }
+                        },
+                    })
+                    .collect::<Vec<_>>()
+                    .into_iter();
+
+                let first = parts.next().expect("Interpolated string must have at least one part");
+                let join = infer.ctx().classes.lang.join;
+                let expr = parts.fold(first, |a, b| if let Some(class_id) = join {
+                    let output_ty = infer.unknown(span);
+                    let func2 = infer.insert(span, TyInfo::Func(b.meta().1, output_ty));
+                    let func = infer.insert(span, TyInfo::Func(a.meta().1, func2));
+                    let field = SrcNode::new(Ident::new("join"), span);
+                    let class = infer.make_class_field_known(a.meta().1, field.clone(), (class_id, vec![b.meta().1]), func, span);
+
+                    InferNode::new(hir::Expr::Apply(
+                        InferNode::new(hir::Expr::Apply(
+                            InferNode::new(hir::Expr::ClassAccess(*a.meta(), class, field), (span, func)),
+                            a,
+                        ), (span, func2)),
+                        b,
+                    ), (span, output_ty))
+                } else {
+                    InferNode::new(hir::Expr::Error, (span, infer.insert(span, TyInfo::Error(ErrorReason::Unknown))))
+                });
+
+                let ty = expr.meta().1;
+                (TyInfo::Ref(ty), expr.into_inner())
+            },
             ast::Expr::ClassAccess(ty, field) => {
                 let ty = ty.to_hir(&TypeLowerCfg::other(), infer, scope);
                 let field_ty = infer.unknown(field.span());
@@ -1091,7 +1167,28 @@ impl ToHir for ast::Expr {
 
                             (TyInfo::Ref(fallback_out), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::Dispatch, name.span()), args))
                         },
-                        _ => {
+                        _ => if let Some(host_ty) = infer.ctx().hosts.get(&**name).cloned() {
+                            // Host functions take their arguments in order, peeling them off the declared type
+                            let mut ty = &host_ty;
+                            let mut arity_ok = true;
+                            for arg in &args {
+                                if let ast::Type::Func(i, o) = &**ty {
+                                    let i = i.to_hir(&TypeLowerCfg::other(), infer, scope);
+                                    infer.make_flow(arg.meta().1, i.meta().1, EqInfo::from(name.span()));
+                                    ty = o;
+                                } else {
+                                    arity_ok = false;
+                                    break;
+                                }
+                            }
+                            if !arity_ok {
+                                infer.ctx_mut().emit(Error::InvalidIntrinsic(name.clone()));
+                                (TyInfo::Error(ErrorReason::Invalid), hir::Expr::Error)
+                            } else {
+                                let out = ty.to_hir(&TypeLowerCfg::other(), infer, scope);
+                                (TyInfo::Ref(out.meta().1), hir::Expr::Intrinsic(SrcNode::new(Intrinsic::Host(**name), name.span()), args))
+                            }
+                        } else {
                             infer.ctx_mut().emit(Error::InvalidIntrinsic(name.clone()));
                             (TyInfo::Error(ErrorReason::Invalid), hir::Expr::Error)
                         },
@@ -1113,6 +1210,33 @@ impl ToHir for ast::Expr {
 
                 (TyInfo::Ref(record.meta().1), hir::Expr::Update(record, fields))
             },
+            ast::Expr::Extend(record, fields) => {
+                let record = record.to_hir(cfg, infer, scope);
+                // The record being extended must itself be a record
+                let rest = infer.unknown(record.meta().0);
+                let record_ty = infer.insert(record.meta().0, TyInfo::Row(BTreeMap::new(), rest));
+                infer.make_flow(record.meta().1, record_ty, record.meta().0);
+                let fields = fields
+                    .iter()
+                    .map(|(name, field)| (name.clone(), field.to_hir(cfg, infer, scope)))
+                    .collect::<BTreeMap<_, _>>();
+                let tys = fields
+                    .iter()
+                    .map(|(name, field)| (**name, field.meta().1))
+                    .collect();
+                (TyInfo::Row(tys, record.meta().1), hir::Expr::Extend(record, fields))
+            },
+            ast::Expr::Restrict(record, names) => {
+                let record = record.to_hir(cfg, infer, scope);
+                let rest = infer.unknown(self.span());
+                let removed = names
+                    .iter()
+                    .map(|name| (**name, infer.unknown(name.span())))
+                    .collect();
+                let record_ty = infer.insert(record.meta().0, TyInfo::Row(removed, rest));
+                infer.make_flow(record.meta().1, record_ty, record.meta().0);
+                (TyInfo::Ref(rest), hir::Expr::Restrict(record, names.clone()))
+            },
             ast::Expr::Block(init, last) => {
                 let eff = infer.insert_effect(self.span(), EffectInfo::Open(Vec::new()));
 
@@ -1148,6 +1272,7 @@ impl ToHir for ast::Expr {
 
                             InferNode::new(hir::Expr::Match(false, rhs, vec![(
                                 lhs,
+                                None,
                                 then,
                             )]), then_meta)
                         },
@@ -1219,13 +1344,13 @@ impl ToHir for ast::Expr {
                                             false,
                                             InferNode::new(hir::Expr::Local(Ident::new("0")), *send.meta()),
                                             if let Some(state) = state {
-                                                vec![(send, InferNode::new(hir::Expr::Match(
+                                                vec![(send, None, InferNode::new(hir::Expr::Match(
                                                     false,
                                                     InferNode::new(hir::Expr::Local(Ident::new("1")), *state.meta()),
-                                                    vec![(state, recv)],
+                                                    vec![(state, None, recv)],
                                                 ), recv_meta))]
                                             } else {
-                                                vec![(send, recv)]
+                                                vec![(send, None, recv)]
                                             },
                                         ), recv_meta),
                                     };
@@ -1283,6 +1408,15 @@ fn tupleify_expr(items: &SrcNode<Vec<SrcNode<ast::Expr>>>, cfg: &<ast::Expr as T
 }
 
 // Desugar a list of bindings into a tuple
+fn guard_to_hir(guard: &SrcNode<ast::Expr>, cfg: &<ast::Expr as ToHir>::Cfg, infer: &mut Infer, scope: &Scope) -> InferExpr {
+    let guard = guard.to_hir(cfg, infer, scope);
+    if let Some(bool_data) = infer.ctx().datas.lang.r#bool {
+        let bool_ty = infer.insert(guard.meta().0, TyInfo::Data(bool_data, Vec::new()));
+        infer.make_flow(guard.meta().1, bool_ty, EqInfo::new(guard.meta().0, format!("Guards must be booleans")));
+    }
+    guard
+}
+
 fn tupleify_binding(items: &SrcNode<Vec<SrcNode<ast::Binding>>>, cfg: &<ast::Expr as ToHir>::Cfg, infer: &mut Infer, scope: &Scope) -> InferBinding {
     let hir_items = items
         .iter()
@@ -72,10 +72,15 @@ impl Reify for hir::Expr<InferMeta> {
                 let pred = pred.reify(infer);
                 let arms = arms
                     .into_iter()
-                    .map(|(binding, arm)| (binding.reify(infer), arm.reify(infer)))
+                    .map(|(binding, guard, arm)| (binding.reify(infer), guard.map(|guard| guard.reify(infer)), arm.reify(infer)))
                     .collect::<Vec<_>>();
 
-                if let Err(example) = exhaustivity(infer.ctx(), pred.meta().1, arms.iter().map(|(b, _)| b)) {
+                // Guarded arms might not match, so they can't contribute to exhaustivity
+                let unguarded = arms
+                    .iter()
+                    .filter(|(_, guard, _)| guard.is_none())
+                    .map(|(b, _, _)| b);
+                if let Err(example) = exhaustivity(infer.ctx(), pred.meta().1, unguarded) {
                     infer.ctx_mut().emit(Error::NotExhaustive(span, example, hidden_outer));
                 }
 
@@ -98,6 +103,11 @@ impl Reify for hir::Expr<InferMeta> {
                 .into_iter()
                 .map(|(name, field)| (name, field.reify(infer)))
                 .collect()),
+            hir::Expr::Extend(record, fields) => hir::Expr::Extend(record.reify(infer), fields
+                .into_iter()
+                .map(|(name, field)| (name, field.reify(infer)))
+                .collect()),
+            hir::Expr::Restrict(record, names) => hir::Expr::Restrict(record.reify(infer), names),
             hir::Expr::Basin(eff, inner) => hir::Expr::Basin(infer.reify_effect(eff), inner.reify(infer)),
             hir::Expr::Suspend(eff, inner) => hir::Expr::Suspend(infer.reify_effect_inst(eff), inner.reify(infer)),
             hir::Expr::Handle { expr, handlers } => hir::Expr::Handle {
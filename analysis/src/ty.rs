@@ -42,6 +42,9 @@ pub enum Ty {
     List(TyId),
     // (_, is_tuple)
     Record(BTreeMap<Ident, TyId>, bool),
+    // An extensible record: the given fields, plus those of the tail. The tail is usually a generic type, but inference
+    // doesn't always know it to be a record, so concretization reports tails that turn out not to be.
+    Row(BTreeMap<Ident, TyId>, TyId),
     Func(TyId, TyId),
     Data(DataId, Vec<TyId>),
     Gen(usize, GenScopeId),
@@ -128,7 +131,10 @@ impl Types {
             (Ty::Error(_), _) | (_, Ty::Error(_)) => Ordering::Equal,
             (Ty::Prim(x), Ty::Prim(y)) => x.cmp(&y),
             (Ty::List(x), Ty::List(y)) => self.cmp_ty(x, y),
-            (Ty::Record(_, _), Ty::Record(_, _)) => todo!("Record equality"),
+            (Ty::Record(xs, x_tuple), Ty::Record(ys, y_tuple)) => x_tuple.cmp(&y_tuple)
+                .then_with(|| self.cmp_fields(xs, ys)),
+            (Ty::Row(xs, x_tail), Ty::Row(ys, y_tail)) => self.cmp_fields(xs, ys)
+                .then_with(|| self.cmp_ty(x_tail, y_tail)),
             (Ty::Func(x_i, x_o), Ty::Func(y_i, y_o)) => self.cmp_ty(x_i, y_i).then_with(|| self.cmp_ty(x_o, y_o)),
             (Ty::Data(x, xs), Ty::Data(y, ys)) => x.cmp(&y).then_with(|| xs
                 .into_iter()
@@ -157,12 +163,13 @@ impl Types {
                     Ty::Prim(_) => 1,
                     Ty::List(_) => 2,
                     Ty::Record(_, _) => 3,
-                    Ty::Func(_, _) => 4,
-                    Ty::Data(_, _) => 5,
-                    Ty::Gen(_, _) => 6,
-                    Ty::SelfType => 7,
-                    Ty::Assoc(_, _, _) => 8,
-                    Ty::Effect(_, _) => 9,
+                    Ty::Row(_, _) => 4,
+                    Ty::Func(_, _) => 5,
+                    Ty::Data(_, _) => 6,
+                    Ty::Gen(_, _) => 7,
+                    Ty::SelfType => 8,
+                    Ty::Assoc(_, _, _) => 9,
+                    Ty::Effect(_, _) => 10,
                 };
 
                 rank_of(&x).cmp(&rank_of(&y))
@@ -170,6 +177,16 @@ impl Types {
         }
     }
 
+    // Fields are ordered first by name and then by type
+    fn cmp_fields(&self, xs: BTreeMap<Ident, TyId>, ys: BTreeMap<Ident, TyId>) -> Ordering {
+        xs.len().cmp(&ys.len()).then_with(|| xs
+            .into_iter()
+            .zip(ys)
+            .fold(Ordering::Equal, |a, ((x_name, x), (y_name, y))| a
+                .then_with(|| x_name.cmp(&y_name))
+                .then_with(|| self.cmp_ty(x, y))))
+    }
+
     pub fn has_inhabitants(&self, datas: &Datas, ty: TyId, gen: &mut dyn FnMut(usize) -> bool) -> bool {
         match self.get(ty) {
             Ty::Error(_) => false,
@@ -178,6 +195,9 @@ impl Types {
             Ty::Record(fields, _) => fields
                 .into_iter()
                 .all(|(_, field)| self.has_inhabitants(datas, field, gen)),
+            Ty::Row(fields, tail) => fields
+                .into_iter()
+                .all(|(_, field)| self.has_inhabitants(datas, field, gen)) && self.has_inhabitants(datas, tail, gen),
             Ty::Func(_, _) => true,
             Ty::Data(data, args) => datas
                 .get_data(data)
@@ -263,6 +283,17 @@ impl<'a> fmt::Display for TyDisplay<'a> {
                     .collect::<Vec<_>>()
                     .join(", "))
             },
+            Ty::Row(fields, tail) => {
+                write!(f, "{{ ")?;
+                if !fields.is_empty() {
+                    write!(f, "{} ", fields
+                        .into_iter()
+                        .map(|(name, field)| format!("{}: {}", name, self.with_ty(field, false)))
+                        .collect::<Vec<_>>()
+                        .join(", "))?;
+                }
+                write!(f, ".. {} }}", self.with_ty(tail, false))
+            },
             Ty::Func(i, o) if self.lhs_exposed => write!(f, "({} -> {})", self.with_ty(i, true), self.with_ty(o, self.lhs_exposed)),
             Ty::Func(i, o) => write!(f, "{} -> {}", self.with_ty(i, true), self.with_ty(o, self.lhs_exposed)),
             Ty::Data(name, params) if self.lhs_exposed && params.len() > 0 => write!(f, "({}{})", *self.ctx.datas.get_data(name).name, params
@@ -0,0 +1,210 @@
+//! Benchmarks the VM on the programs in `tests/` and on `test_cases/brainfuck.tao`.
+//!
+//! Each program is run by the `tao` binary built from this tree and, if `--baseline <dir>` is given, by the `tao`
+//! binary in `<dir>/target/release`, where `<dir>` is a checkout of another commit (such as the one before the VM's
+//! superinstructions were introduced). Run with `cargo bench --bench vm -- [--baseline <dir>] [filter]`, where programs
+//! are only run if their names contain the filter.
+//!
+//! Whole runs are timed, compilation included, since that's all that can be measured in the same way for any commit.
+//! Each checkout runs the programs against its own standard library, so programs that use features the baseline
+//! doesn't have can't be compared. These are reported as failures rather than left out, and the benchmark panics if
+//! the baseline can run none of the programs at all.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+
+// The number of times each program is run, the fastest of which is reported
+const RUNS: usize = 10;
+
+struct Bench {
+    name: String,
+    // The source of the program, or `None` for `test_cases/brainfuck.tao`, which each checkout has its own copy of
+    src: Option<String>,
+    // The output that the program must produce, if known
+    expected: Option<String>,
+}
+
+impl Bench {
+    // Find the file to run for the checkout at `root`. Test programs are written to `target/tmp` within it, which is
+    // two levels below the root like `compiler/tests`, so that their relative imports find that checkout's library.
+    fn path(&self, root: &Path) -> PathBuf {
+        match &self.src {
+            Some(src) => {
+                let dir = root.join("target").join("tmp");
+                fs::create_dir_all(&dir).unwrap();
+                let path = dir.join(format!("vm-bench-{}.tao", self.name.replace('#', "-")));
+                fs::write(&path, src).unwrap();
+                path
+            },
+            None => root.join("test_cases").join(format!("{}.tao", self.name)),
+        }
+    }
+}
+
+// Test files contain several programs, each introduced by `# >>>> INPUT` and followed by `# >>>> OUTPUT` and its
+// output. Programs followed by `# >>>> ERRORS` are expected not to compile, so they aren't benchmarked.
+fn programs() -> Vec<Bench> {
+    let mut paths = fs::read_dir("tests")
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().map_or(false, |ext| ext == "tao"))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut benches = Vec::new();
+    for path in paths {
+        let src = fs::read_to_string(&path).unwrap();
+        let name = path.file_stem().unwrap().to_string_lossy().into_owned();
+        let mut input = None::<String>;
+        let mut output = None::<String>;
+        let mut idx = 0;
+        for line in src.lines().chain(std::iter::once("# >>>> INPUT")) {
+            match line.trim() {
+                "# >>>> INPUT" | "# >>>> ERRORS" => {
+                    if let (Some(src), Some(expected)) = (input.take(), output.take()) {
+                        benches.push(Bench {
+                            name: format!("{}#{}", name, idx),
+                            src: Some(src),
+                            expected: Some(expected.trim().to_string()),
+                        });
+                        idx += 1;
+                    }
+                    if line.trim() == "# >>>> INPUT" {
+                        input = Some(String::new());
+                    }
+                },
+                "# >>>> OUTPUT" => output = Some(String::new()),
+                _ => if let Some(text) = output.as_mut().or(input.as_mut()) {
+                    *text += line;
+                    *text += "\n";
+                },
+            }
+        }
+    }
+
+    benches.push(Bench {
+        name: "brainfuck".to_string(),
+        src: None,
+        expected: None,
+    });
+
+    benches
+}
+
+// Run a program, returning its output if it succeeded
+fn run(tao: &Path, path: &Path) -> Option<String> {
+    let output = Command::new(tao)
+        .args(["--opt", "fast"])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()
+        .unwrap_or_else(|err| panic!("Failed to run {}: {}", tao.display(), err));
+    if output.status.success() {
+        Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    } else {
+        None
+    }
+}
+
+// Returns the output of the program and the fastest time taken to run it, or `None` if it didn't run successfully
+fn measure(tao: &Path, path: &Path) -> Option<(String, Duration)> {
+    let output = run(tao, path)?;
+    let time = (0..RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            run(tao, path);
+            start.elapsed()
+        })
+        .min()
+        .unwrap();
+    Some((output, time))
+}
+
+fn main() {
+    let mut args = std::env::args().skip(1);
+    let mut baseline = None;
+    let mut filter = None;
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--baseline" => baseline = Some(PathBuf::from(args.next().expect("Expected a directory after --baseline"))),
+            // `cargo bench` passes `--bench`, which isn't a filter
+            _ if arg.starts_with("--") => {},
+            _ => filter = Some(arg),
+        }
+    }
+
+    let tao = Path::new(env!("CARGO_BIN_EXE_tao"));
+    let root = Path::new("..").canonicalize().unwrap();
+    let baseline = baseline.map(|root| {
+        let tao = root.join("target").join("release").join("tao");
+        assert!(tao.is_file(), "No baseline binary at {}, build it with `cargo build --release` first", tao.display());
+        (root, tao)
+    });
+
+    println!("{:<16} | {:>12} | {:>12} | {:>8}", "program", "time (us)", "base (us)", "speedup");
+
+    let mut total = (Duration::ZERO, Duration::ZERO);
+    let mut compared = 0;
+    let mut failed = Vec::new();
+    for bench in programs() {
+        if filter.as_ref().map_or(false, |filter| !bench.name.contains(filter.as_str())) {
+            continue;
+        }
+
+        let (output, new) = measure(tao, &bench.path(&root))
+            .unwrap_or_else(|| panic!("{} failed to run", bench.name));
+        // Anything that the program prints comes before its result
+        if let Some(expected) = &bench.expected {
+            assert!(output.ends_with(expected.as_str()), "{} produced the wrong output: {}", bench.name, output);
+        }
+
+        // The baseline must produce the same output, or it isn't running the same program
+        let old = baseline
+            .as_ref()
+            .map(|(root, tao)| measure(tao, &bench.path(root)).filter(|(old_output, _)| *old_output == output));
+
+        match old {
+            Some(Some((_, old))) => {
+                total.0 += new;
+                total.1 += old;
+                compared += 1;
+                println!(
+                    "{:<16} | {:>12} | {:>12} | {:>7.2}x",
+                    bench.name,
+                    new.as_micros(),
+                    old.as_micros(),
+                    old.as_secs_f64() / new.as_secs_f64().max(f64::EPSILON),
+                );
+            },
+            Some(None) => {
+                failed.push(bench.name.clone());
+                println!("{:<16} | {:>12} | {:>12} | {:>8}", bench.name, new.as_micros(), "failed", "");
+            },
+            None => {
+                total.0 += new;
+                println!("{:<16} | {:>12} | {:>12} | {:>8}", bench.name, new.as_micros(), "", "");
+            },
+        }
+    }
+
+    if baseline.is_some() {
+        println!(
+            "{:<16} | {:>12} | {:>12} | {:>7.2}x",
+            format!("total ({})", compared),
+            total.0.as_micros(),
+            total.1.as_micros(),
+            total.1.as_secs_f64() / total.0.as_secs_f64().max(f64::EPSILON),
+        );
+        if !failed.is_empty() {
+            eprintln!("The baseline failed to run {} programs: {}", failed.len(), failed.join(", "));
+        }
+        assert!(compared > 0, "The baseline failed to run every program, so nothing could be compared");
+    } else {
+        println!("{:<16} | {:>12} |", "total", total.0.as_micros());
+    }
+}
@@ -0,0 +1,586 @@
+use super::*;
+use tao_analysis::{Ty, TyId, GenScopeId, ClassId, Member};
+use tao_syntax::Span;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+impl DocFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            DocFormat::Markdown => "md",
+            DocFormat::Html => "html",
+        }
+    }
+}
+
+impl FromStr for DocFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, &'static str> {
+        match s {
+            "md" | "markdown" => Ok(DocFormat::Markdown),
+            "html" => Ok(DocFormat::Html),
+            _ => Err("Documentation format does not exist"),
+        }
+    }
+}
+
+impl fmt::Display for DocFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DocFormat::Markdown => write!(f, "md"),
+            DocFormat::Html => write!(f, "html"),
+        }
+    }
+}
+
+// The sections of a module page, in the order they appear
+const SECTIONS: [(&str, &str); 6] = [
+    ("data", "Data types"),
+    ("type", "Type aliases"),
+    ("class", "Classes"),
+    ("effect", "Effects"),
+    ("def", "Definitions"),
+    ("member", "Members"),
+];
+
+/// A documented item.
+struct Entry {
+    section: &'static str,
+    name: String,
+    span: Span,
+    sig: String,
+    docs: Option<String>,
+    // Members that the item takes part in, as (signature, member span)
+    members: Vec<(String, Span)>,
+}
+
+pub struct DocGen<'a> {
+    ctx: &'a HirContext,
+    format: DocFormat,
+    page_name: &'a dyn Fn(SrcId) -> String,
+    // Type-level names (data types, aliases, classes and effects) mapped to the item that declares them
+    links: HashMap<String, (SrcId, String)>,
+}
+
+impl<'a> DocGen<'a> {
+    pub fn new(ctx: &'a HirContext, format: DocFormat, page_name: &'a dyn Fn(SrcId) -> String) -> Self {
+        let mut links = HashMap::new();
+        for data in ctx.datas.iter_datas() {
+            let data = ctx.datas.get_data(data);
+            links.insert(data.name.to_string(), (data.name.span().src(), anchor("data", &data.name)));
+        }
+        for alias in ctx.datas.iter_aliases() {
+            if let Some(alias) = ctx.datas.get_alias(alias) {
+                links.insert(alias.name.to_string(), (alias.name.span().src(), anchor("type", &alias.name)));
+            }
+        }
+        for (_, class) in ctx.classes.iter() {
+            links.insert(class.name.to_string(), (class.name.span().src(), anchor("class", &class.name)));
+        }
+        for (_, eff) in ctx.effects.iter() {
+            links.insert(eff.name.to_string(), (eff.name.span().src(), anchor("effect", &eff.name)));
+        }
+        for (_, alias) in ctx.effects.iter_aliases() {
+            links.insert(alias.name.to_string(), (alias.name.span().src(), anchor("effect", &alias.name)));
+        }
+
+        Self { ctx, format, page_name, links }
+    }
+
+    /// Generate a page for each of the given modules (given as their source and their module-level attributes), along
+    /// with an index page. Pages are returned as (page name, contents).
+    pub fn generate(&self, modules: &[(SrcId, Vec<SrcNode<ast::Attr>>)]) -> Vec<(String, String)> {
+        let entries = self.entries();
+
+        let mut pages = Vec::new();
+        let mut index = Vec::new();
+        for (src, attrs) in modules {
+            let docs = ast::docs(attrs);
+            index.push((*src, docs
+                .as_ref()
+                .and_then(|docs| docs.lines().next())
+                .unwrap_or("")
+                .to_string()));
+
+            let mut entries = entries
+                .iter()
+                .filter(|entry| entry.span.src() == *src)
+                .collect::<Vec<_>>();
+            entries.sort_by_key(|entry| entry.span.range().start);
+            pages.push(((self.page_name)(*src), self.module_page(*src, docs.as_deref(), &entries)));
+        }
+        pages.push(("index".to_string(), self.index_page(&index)));
+
+        pages
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let ctx = self.ctx;
+        let mut entries = Vec::new();
+
+        for data_id in ctx.datas.iter_datas() {
+            let data = ctx.datas.get_data(data_id);
+            let name = format!("{}{}", *data.name, self.gen_names(data.gen_scope));
+            let cons = data.cons
+                .iter()
+                .map(|(cons, ty)| if matches!(ctx.tys.get(*ty), Ty::Record(fields, true) if fields.is_empty()) {
+                    format!("{}", **cons)
+                } else {
+                    format!("{} {}", **cons, self.ty(*ty, true))
+                })
+                .collect::<Vec<_>>();
+            let body = match (cons.as_slice(), data.cons.as_slice()) {
+                // Single-variant data types share their name with their variant
+                (_, [(cons, ty)]) if **cons == *data.name => format!(" = {}", self.ty(*ty, false)),
+                ([cons], _) => format!(" = {}", cons),
+                (cons, _) => cons
+                    .iter()
+                    .enumerate()
+                    .map(|(i, cons)| format!("\n    {} {}", if i + 1 == data.cons.len() { '\\' } else { '|' }, cons))
+                    .collect(),
+            };
+            entries.push(Entry {
+                section: "data",
+                name: data.name.to_string(),
+                span: data.name.span(),
+                sig: format!("data {}{}{}", name, self.where_clause(data.gen_scope, false), if cons.is_empty() { String::new() } else { body }),
+                docs: ast::docs(&data.attr),
+                members: self.members_where(|member| matches!(ctx.tys.get(member.member), Ty::Data(d, _) if d == data_id)),
+            });
+        }
+
+        for alias in ctx.datas.iter_aliases() {
+            if let Some(alias) = ctx.datas.get_alias(alias) {
+                entries.push(Entry {
+                    section: "type",
+                    name: alias.name.to_string(),
+                    span: alias.name.span(),
+                    sig: format!(
+                        "type {}{}{} = {}",
+                        *alias.name,
+                        self.gen_names(alias.gen_scope),
+                        self.where_clause(alias.gen_scope, false),
+                        self.ty(alias.ty, false),
+                    ),
+                    docs: ast::docs(&alias.attr),
+                    members: Vec::new(),
+                });
+            }
+        }
+
+        for (class_id, class) in ctx.classes.iter() {
+            let supers = self.bounds(class.gen_scope)
+                .into_iter()
+                .filter(|(ty, _)| matches!(ctx.tys.get(*ty), Ty::SelfType))
+                .map(|(_, bound)| bound)
+                .collect::<Vec<_>>();
+            let items = class.assoc
+                .iter()
+                .flatten()
+                .map(|assoc| format!("\n    => {}", *assoc.name))
+                .chain(class.fields
+                    .iter()
+                    .flatten()
+                    .map(|field| format!("\n    => {} : {}", *field.name, self.ty(*field.ty, false))))
+                .collect::<String>();
+            entries.push(Entry {
+                section: "class",
+                name: class.name.to_string(),
+                span: class.name.span(),
+                sig: format!(
+                    "class {}{}{}{}{}",
+                    *class.name,
+                    self.gen_names(class.gen_scope),
+                    if supers.is_empty() { String::new() } else { format!(" < {}", supers.join(" + ")) },
+                    self.where_clause(class.gen_scope, true),
+                    items,
+                ),
+                docs: ast::docs(&class.attr),
+                members: self.members_where(|member| member.class == class_id),
+            });
+        }
+
+        for (_, eff) in ctx.effects.iter() {
+            entries.push(Entry {
+                section: "effect",
+                name: eff.name.to_string(),
+                span: eff.name.span(),
+                sig: format!(
+                    "effect {}{}{} = {} => {}",
+                    *eff.name,
+                    self.gen_names(eff.gen_scope),
+                    self.where_clause(eff.gen_scope, false),
+                    eff.send.map_or("?".to_string(), |ty| self.ty(ty, false)),
+                    eff.recv.map_or("?".to_string(), |ty| self.ty(ty, false)),
+                ),
+                docs: ast::docs(&eff.attr),
+                members: Vec::new(),
+            });
+        }
+
+        for (_, alias) in ctx.effects.iter_aliases() {
+            entries.push(Entry {
+                section: "effect",
+                name: alias.name.to_string(),
+                span: alias.name.span(),
+                sig: format!(
+                    "effect {}{}{} = {}",
+                    *alias.name,
+                    self.gen_names(alias.gen_scope),
+                    self.where_clause(alias.gen_scope, false),
+                    alias.effects
+                        .iter()
+                        .flatten()
+                        .map(|(eff, args)| format!(
+                            "{}{}",
+                            *ctx.effects.get_decl(**eff).name,
+                            args.iter().map(|arg| format!(" {}", self.ty(*arg, true))).collect::<String>(),
+                        ))
+                        .collect::<Vec<_>>()
+                        .join(" + "),
+                ),
+                docs: ast::docs(&alias.attr),
+                members: Vec::new(),
+            });
+        }
+
+        for (_, def) in ctx.defs.iter() {
+            let ty = def.ty_hint.or_else(|| def.body.as_ref().map(|body| body.meta().1));
+            entries.push(Entry {
+                section: "def",
+                name: def.name.to_string(),
+                span: def.name.span(),
+                sig: format!(
+                    "def {}{}{}{}",
+                    *def.name,
+                    self.gen_names(def.gen_scope),
+                    ty.map_or(String::new(), |ty| format!(" : {}", self.ty(ty, false))),
+                    self.where_clause(def.gen_scope, false),
+                ),
+                docs: ast::docs(&def.attr),
+                members: Vec::new(),
+            });
+        }
+
+        for (_, member) in ctx.classes.iter_members() {
+            let (sig, span) = self.member(member);
+            entries.push(Entry {
+                section: "member",
+                name: sig.clone(),
+                span,
+                sig,
+                docs: ast::docs(&member.attr),
+                members: Vec::new(),
+            });
+        }
+
+        entries
+    }
+
+    fn ty(&self, ty: TyId, atomic: bool) -> String {
+        let s = self.ctx.tys.display(self.ctx, ty).to_string();
+        // Make sure that types with parameters are not ambiguous when they appear as arguments
+        let needs_parens = match self.ctx.tys.get(ty) {
+            Ty::Func(_, _) | Ty::Effect(_, _) => true,
+            Ty::Data(_, args) => !args.is_empty(),
+            _ => false,
+        };
+        if atomic && needs_parens {
+            format!("({})", s)
+        } else {
+            s
+        }
+    }
+
+    fn gen_names(&self, gen_scope: GenScopeId) -> String {
+        let gen_scope = self.ctx.tys.get_gen_scope(gen_scope);
+        let names = (0..gen_scope.len())
+            .map(|i| (*gen_scope.get(i).name).to_string())
+            .collect::<Vec<_>>();
+        if names.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", names.join(", "))
+        }
+    }
+
+    /// The class bounds of a generic scope, as (bounded type, bound).
+    fn bounds(&self, gen_scope: GenScopeId) -> Vec<(TyId, String)> {
+        self.ctx.tys
+            .get_gen_scope(gen_scope)
+            .implied_members
+            .iter()
+            .flatten()
+            .map(|implied| (*implied.member, self.class_inst(*implied.class, &implied.args)))
+            .collect()
+    }
+
+    fn class_inst(&self, class: ClassId, args: &[TyId]) -> String {
+        format!(
+            "{}{}",
+            *self.ctx.classes.get(class).name,
+            args.iter().map(|arg| format!(" {}", self.ty(*arg, true))).collect::<String>(),
+        )
+    }
+
+    fn where_clause(&self, gen_scope: GenScopeId, skip_self: bool) -> String {
+        let bounds = self.bounds(gen_scope)
+            .into_iter()
+            .filter(|(ty, _)| !skip_self || !matches!(self.ctx.tys.get(*ty), Ty::SelfType))
+            .map(|(ty, bound)| format!("{} < {}", self.ty(ty, true), bound))
+            .collect::<Vec<_>>();
+        if bounds.is_empty() {
+            String::new()
+        } else {
+            format!(" where {}", bounds.join(", "))
+        }
+    }
+
+    fn member(&self, member: &Member) -> (String, Span) {
+        let span = self.ctx.tys.get_gen_scope(member.gen_scope).item_span;
+        let sig = format!(
+            "member{} {} of {}{}",
+            self.gen_names(member.gen_scope),
+            self.ty(member.member, false),
+            self.class_inst(member.class, &member.args),
+            self.where_clause(member.gen_scope, false),
+        );
+        (sig, span)
+    }
+
+    fn members_where(&self, f: impl Fn(&Member) -> bool) -> Vec<(String, Span)> {
+        self.ctx.classes
+            .iter_members()
+            .filter(|(_, member)| f(member))
+            .map(|(_, member)| self.member(member))
+            .collect()
+    }
+
+    fn href(&self, src: SrcId, anchor: &str) -> String {
+        format!("{}.{}#{}", (self.page_name)(src), self.format.extension(), anchor)
+    }
+
+    /// The items that the given signature refers to, in the order they first appear.
+    fn refs(&self, sig: &str) -> Vec<(String, String)> {
+        let mut refs = Vec::new();
+        for word in sig.split(|c: char| !c.is_alphanumeric() && c != '_') {
+            if let Some((src, anchor)) = self.links.get(word) {
+                let link = (word.to_string(), self.href(*src, anchor));
+                if !refs.contains(&link) {
+                    refs.push(link);
+                }
+            }
+        }
+        refs
+    }
+
+    fn module_page(&self, src: SrcId, docs: Option<&str>, entries: &[&Entry]) -> String {
+        let mut page = match self.format {
+            DocFormat::Markdown => format!("# Module `{}`\n\n", src),
+            DocFormat::Html => format!(
+                "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{}</title></head>\n<body>\n<p><a href=\"index.html\">Index</a></p>\n<h1>Module <code>{}</code></h1>\n",
+                escape_html(&src.to_string()),
+                escape_html(&src.to_string()),
+            ),
+        };
+
+        if let Some(docs) = docs {
+            page += &self.doc_text(docs);
+        }
+
+        for (section, title) in SECTIONS {
+            let entries = entries
+                .iter()
+                .filter(|entry| entry.section == section)
+                .collect::<Vec<_>>();
+            if entries.is_empty() {
+                continue;
+            }
+
+            page += &match self.format {
+                DocFormat::Markdown => format!("## {}\n\n", title),
+                DocFormat::Html => format!("<h2>{}</h2>\n", title),
+            };
+            for entry in entries {
+                page += &self.entry(entry);
+            }
+        }
+
+        if self.format == DocFormat::Html {
+            page += "</body>\n</html>\n";
+        }
+
+        page
+    }
+
+    fn entry(&self, entry: &Entry) -> String {
+        let anchor = anchor(entry.section, &entry.name);
+        let mut out = match self.format {
+            DocFormat::Markdown => format!(
+                "### <a id=\"{}\"></a>`{}`\n\n```\n{}\n```\n\n",
+                anchor,
+                entry.name,
+                entry.sig,
+            ),
+            DocFormat::Html => format!(
+                "<h3 id=\"{}\"><code>{}</code></h3>\n<pre><code>{}</code></pre>\n",
+                escape_html(&anchor),
+                escape_html(&entry.name),
+                self.linked(&entry.sig),
+            ),
+        };
+
+        if let Some(docs) = &entry.docs {
+            out += &self.doc_text(docs);
+        }
+
+        // Markdown can't contain links inside code blocks, so list the items that the signature mentions instead
+        if self.format == DocFormat::Markdown {
+            let refs = self.refs(&entry.sig)
+                .into_iter()
+                .filter(|(name, _)| name != &entry.name)
+                .map(|(name, href)| format!("[`{}`]({})", name, href))
+                .collect::<Vec<_>>();
+            if !refs.is_empty() {
+                out += &format!("See also: {}\n\n", refs.join(", "));
+            }
+        }
+
+        if !entry.members.is_empty() {
+            out += &match self.format {
+                DocFormat::Markdown => "Members:\n\n".to_string(),
+                DocFormat::Html => "<p>Members:</p>\n<ul>\n".to_string(),
+            };
+            for (member, span) in &entry.members {
+                let href = self.href(span.src(), &anchor("member", member));
+                out += &match self.format {
+                    DocFormat::Markdown => format!("- [`{}`]({})\n", member, href),
+                    DocFormat::Html => format!(
+                        "<li><a href=\"{}\"><code>{}</code></a></li>\n",
+                        escape_html(&href),
+                        escape_html(member),
+                    ),
+                };
+            }
+            out += match self.format {
+                DocFormat::Markdown => "\n",
+                DocFormat::Html => "</ul>\n",
+            };
+        }
+
+        out
+    }
+
+    /// Escape a signature for HTML, linking the names of any items that it mentions.
+    fn linked(&self, sig: &str) -> String {
+        let mut out = String::new();
+        let mut word = String::new();
+        for c in sig.chars().chain(std::iter::once('\0')) {
+            if c.is_alphanumeric() || c == '_' {
+                word.push(c);
+                continue;
+            }
+            if let Some((src, anchor)) = self.links.get(&word) {
+                out += &format!("<a href=\"{}\">{}</a>", escape_html(&self.href(*src, anchor)), word);
+            } else {
+                out += &word;
+            }
+            word.clear();
+            if c != '\0' {
+                out += &escape_html(&c.to_string());
+            }
+        }
+        out
+    }
+
+    /// Doc comments are written in Markdown. When generating HTML, a small subset is supported: paragraphs, code blocks
+    /// and inline code.
+    fn doc_text(&self, docs: &str) -> String {
+        match self.format {
+            DocFormat::Markdown => format!("{}\n\n", docs.trim_end()),
+            DocFormat::Html => {
+                let mut out = String::new();
+                let mut para = Vec::new();
+                let mut code = None;
+                for line in docs.lines().chain(std::iter::once("")) {
+                    match &mut code {
+                        Some(lines) if line.trim_start().starts_with("```") => {
+                            out += &format!("<pre><code>{}</code></pre>\n", escape_html(&std::mem::take(lines).join("\n")));
+                            code = None;
+                        },
+                        Some(lines) => lines.push(line),
+                        None if line.trim_start().starts_with("```") || line.trim().is_empty() => {
+                            if !para.is_empty() {
+                                out += &format!("<p>{}</p>\n", inline_code(&escape_html(&para.join(" "))));
+                                para.clear();
+                            }
+                            if !line.trim().is_empty() {
+                                code = Some(Vec::new());
+                            }
+                        },
+                        None => para.push(line.trim()),
+                    }
+                }
+                out
+            },
+        }
+    }
+
+    fn index_page(&self, modules: &[(SrcId, String)]) -> String {
+        match self.format {
+            DocFormat::Markdown => {
+                let mut page = "# Modules\n\n".to_string();
+                for (src, summary) in modules {
+                    page += &format!("- [`{}`]({}.md) {}\n", src, (self.page_name)(*src), summary);
+                }
+                page
+            },
+            DocFormat::Html => {
+                let mut page = "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>Modules</title></head>\n<body>\n<h1>Modules</h1>\n<ul>\n".to_string();
+                for (src, summary) in modules {
+                    page += &format!(
+                        "<li><a href=\"{}.html\"><code>{}</code></a> {}</li>\n",
+                        escape_html(&(self.page_name)(*src)),
+                        escape_html(&src.to_string()),
+                        inline_code(&escape_html(summary)),
+                    );
+                }
+                page += "</ul>\n</body>\n</html>\n";
+                page
+            },
+        }
+    }
+}
+
+// Anchors may only contain a limited set of characters, so replace the rest
+fn anchor(section: &str, name: &str) -> String {
+    format!("{}.{}", section, name
+        .chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '-' })
+        .collect::<String>())
+}
+
+fn escape_html(s: &str) -> String {
+    s.chars()
+        .map(|c| match c {
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '&' => "&amp;".to_string(),
+            '"' => "&quot;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+// Turn `code` into <code>code</code>
+fn inline_code(s: &str) -> String {
+    s.split('`')
+        .enumerate()
+        .map(|(i, part)| if i % 2 == 1 { format!("<code>{}</code>", part) } else { part.to_string() })
+        .collect()
+}
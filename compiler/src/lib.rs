@@ -1,12 +1,16 @@
 mod error;
+mod doc;
 
 pub use tao_syntax::SrcId;
 pub use tao_middle::OptMode;
+pub use doc::DocFormat;
 
-use tao_syntax::{parse_module, ast, SrcNode, Error as SyntaxError};
+use tao_syntax::{parse_module, parse_type, ast, SrcNode, Error as SyntaxError};
 use tao_analysis::Context as HirContext;
-use tao_middle::Context;
-use tao_vm::{Program, exec};
+use tao_middle::{Context, OptConfig, Literal, Rng};
+pub use tao_vm::{Hosts, Value};
+
+use tao_vm::{Program, Profiler, ExecConfig, StdIo, CapturedIo, exec, exec_with_arg, litr_to_value};
 use ariadne::sources;
 use structopt::StructOpt;
 use internment::Intern;
@@ -14,76 +18,490 @@ use std::{
     str::FromStr,
     io::Write,
     collections::HashMap,
+    path::PathBuf,
+    fs::File,
     fmt,
 };
 use error::Error;
 
 #[derive(Clone, Debug, StructOpt)]
 pub struct Options {
-    /// Add a debugging layer to stdout (tokens, ast, hir, mir, bytecode)
+    /// Add a debugging layer to stdout (tokens, ast, hir, instances, mir, mir-after=<pass>, pass-stats, bytecode)
     #[structopt(long)]
     pub debug: Vec<String>,
     /// Specify an optimisation mode (none, fast, size)
     #[structopt(short, long, default_value = "none")]
     pub opt: OptMode,
+    /// Run the given comma-separated optimisation passes in order, instead of those implied by the optimisation mode
+    #[structopt(long, use_delimiter = true)]
+    pub passes: Vec<String>,
+    /// Run the optimisation passes repeatedly until the MIR stops changing
+    #[structopt(long)]
+    pub fixed_point: bool,
+    /// Share one procedure between the instantiations of a generic definition that have identical representations
+    #[structopt(long)]
+    pub share_instances: bool,
+    /// Check the MIR for consistency after lowering and after every optimisation pass, reporting the first to break it
+    #[structopt(long)]
+    pub verify_mir: bool,
+    /// Profile execution, reporting instruction counts and time per procedure along with an opcode histogram
+    #[structopt(long)]
+    pub profile: bool,
+    /// Write the execution profile to the given file as folded stacks, suitable for generating a flame graph
+    #[structopt(long, parse(from_os_str))]
+    pub profile_folded: Option<PathBuf>,
+    /// Limit the number of instructions the program may execute
+    #[structopt(long)]
+    pub fuel: Option<u64>,
+    /// Limit the depth of the call stack during execution
+    #[structopt(long)]
+    pub max_call_depth: Option<usize>,
+    /// Limit the number of effect handlers that may be active at once during execution
+    #[structopt(long)]
+    pub max_handler_depth: Option<usize>,
+    /// Limit the estimated memory usage of the program during execution, in bytes
+    #[structopt(long)]
+    pub max_memory: Option<usize>,
 }
 
-pub fn run<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
+impl Options {
+    fn opt_config(&self) -> OptConfig {
+        OptConfig {
+            mode: self.opt,
+            passes: self.passes.clone(),
+            fixed_point: self.fixed_point,
+            share_instances: self.share_instances,
+            verify: self.verify_mir,
+            dump_after: self.debug
+                .iter()
+                .filter_map(|debug| Some(debug.strip_prefix("mir-after=")?.to_string()))
+                .collect(),
+            stats: self.debug.contains(&"pass-stats".to_string()),
+            report_instances: self.debug.contains(&"instances".to_string()),
+        }
+    }
+}
+
+// TODO: Write a proper module system you lazy git
+fn resolve_imports<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
+    parent_src: SrcId,
+    module: Option<&mut ast::Module>,
+    imported: &mut HashMap<SrcId, String>,
+    import_errors: &mut Vec<Error>,
+    syntax_errors: &mut Vec<SyntaxError>,
+    get_file: &mut F,
+    make_src: &mut G,
+) {
+    if let Some(module) = module {
+        let imports = std::mem::take(&mut module.imports);
+
+        for import in imports {
+            match make_src(parent_src, import.as_str()).and_then(|src_id| Some((src_id, get_file(src_id)?))) {
+                Some((src_id, src)) => {
+                    // Check for cycles
+                    if imported.insert(src_id, src.clone()).is_none() {
+                        let (mut ast, mut new_syntax_errors) = parse_module(&src, src_id);
+                        syntax_errors.append(&mut new_syntax_errors);
+
+                        resolve_imports(src_id, ast.as_deref_mut(), imported, import_errors, syntax_errors, get_file, make_src);
+
+                        if let Some(mut ast) = ast {
+                            module.attrs.append(&mut ast.attrs);
+                            let mut old_items = std::mem::take(&mut module.items);
+                            module.items.append(&mut ast.items);
+                            module.items.append(&mut old_items);
+                        }
+                    }
+                },
+                None => import_errors.push(Error::CannotImport(import.clone())),
+            }
+        }
+    }
+}
+
+/// Format the given source code, writing any syntax errors to the writer. Returns `None` if the code could not be
+/// parsed.
+pub fn format(src: String, src_id: SrcId, mut writer: impl Write) -> Option<String> {
+    let (formatted, errors) = tao_syntax::format_module(&src, src_id);
+    let mut srcs = sources(std::iter::once((src_id, src)));
+    for e in errors {
+        e.write(&mut srcs, &mut writer);
+    }
+    formatted
+}
+
+/// Generate documentation for a program and the modules it imports, writing any errors to the writer. On success, one
+/// page is returned per module (named by `page_name`) along with an index page, as (page name, contents).
+pub fn doc<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
+    src: String,
+    src_id: SrcId,
+    format: DocFormat,
+    mut writer: impl Write,
+    mut get_file: F,
+    mut make_src: G,
+    page_name: impl Fn(SrcId) -> String,
+) -> Option<Vec<(String, String)>> {
+    let (mut ast, mut syntax_errors) = parse_module(&src, src_id);
+
+    let mut imported = HashMap::new();
+    let mut import_errors = Vec::new();
+    resolve_imports(src_id, ast.as_deref_mut(), &mut imported, &mut import_errors, &mut syntax_errors, &mut get_file, &mut make_src);
+
+    let mut srcs = sources(imported
+        .into_iter()
+        .chain(std::iter::once((src_id, src))));
+    if !import_errors.is_empty() || !syntax_errors.is_empty() {
+        for e in import_errors {
+            e.write(&mut srcs, &mut writer);
+        }
+        for e in syntax_errors {
+            e.write(&mut srcs, &mut writer);
+        }
+        return None;
+    }
+
+    let ast = ast?;
+    let (ctx, analysis_errors) = HirContext::from_module(&ast);
+    if !analysis_errors.is_empty() {
+        for e in analysis_errors {
+            e.write(&ctx, &mut srcs, src_id, &mut writer);
+        }
+        return None;
+    }
+
+    // Each source that contributed items or module documentation gets its own page
+    let mut modules = Vec::<(SrcId, Vec<SrcNode<ast::Attr>>)>::new();
+    let item_srcs = ast.items.iter().map(|item| item.span.src());
+    for src in std::iter::once(src_id).chain(item_srcs) {
+        if !modules.iter().any(|(s, _)| *s == src) {
+            modules.push((src, Vec::new()));
+        }
+    }
+    for attr in &ast.attrs {
+        let src = attr.span().src();
+        match modules.iter_mut().find(|(s, _)| *s == src) {
+            Some((_, attrs)) => attrs.push(attr.clone()),
+            None => modules.push((src, vec![attr.clone()])),
+        }
+    }
+    modules.sort_by_key(|(src, _)| page_name(*src));
+
+    Some(doc::DocGen::new(&ctx, format, &page_name).generate(&modules))
+}
+
+/// Run the definitions marked with `$[test]` in a program and the modules it imports, optionally only running those
+/// whose names contain `filter`. A report is written to the writer. Returns `true` if every test that ran passed.
+pub fn test<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
     src: String,
     src_id: SrcId,
     options: Options,
+    filter: Option<&str>,
     mut writer: impl Write,
     mut get_file: F,
     mut make_src: G,
-) {
+) -> bool {
     let (mut ast, mut syntax_errors) = parse_module(&src, src_id);
 
-    // TODO: Write a proper module system you lazy git
-    fn resolve_imports<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
-        parent_src: SrcId,
-        module: Option<&mut ast::Module>,
-        imported: &mut HashMap<SrcId, String>,
-        import_errors: &mut Vec<Error>,
-        syntax_errors: &mut Vec<SyntaxError>,
-        get_file: &mut F,
-        make_src: &mut G,
-    ) {
-        if let Some(module) = module {
-            let imports = std::mem::take(&mut module.imports);
-
-            for import in imports {
-                match make_src(parent_src, import.as_str()).and_then(|src_id| Some((src_id, get_file(src_id)?))) {
-                    Some((src_id, src)) => {
-                        // Check for cycles
-                        if imported.insert(src_id, src.clone()).is_none() {
-                            let (mut ast, mut new_syntax_errors) = parse_module(&src, src_id);
-                            syntax_errors.append(&mut new_syntax_errors);
-
-                            resolve_imports(src_id, ast.as_deref_mut(), imported, import_errors, syntax_errors, get_file, make_src);
-
-                            if let Some(mut ast) = ast {
-                                let mut old_items = std::mem::take(&mut module.items);
-                                module.items.append(&mut ast.items);
-                                module.items.append(&mut old_items);
-                            }
-                        }
+    let mut imported = HashMap::new();
+    let mut import_errors = Vec::new();
+    resolve_imports(src_id, ast.as_deref_mut(), &mut imported, &mut import_errors, &mut syntax_errors, &mut get_file, &mut make_src);
+    imported.insert(src_id, src);
+
+    // Used to report the locations of tests
+    let location = |span: tao_syntax::Span| {
+        let (line, col) = imported
+            .get(&span.src())
+            .map(|src| src
+                .chars()
+                .take(span.range().start)
+                .fold((1, 1), |(line, col), c| if c == '\n' { (line + 1, 1) } else { (line, col + 1) }))
+            .unwrap_or((0, 0));
+        format!("{}:{}:{}", span.src(), line, col)
+    };
+
+    let mut srcs = sources(imported.clone());
+    if !import_errors.is_empty() || !syntax_errors.is_empty() {
+        for e in import_errors {
+            e.write(&mut srcs, &mut writer);
+        }
+        for e in syntax_errors {
+            e.write(&mut srcs, &mut writer);
+        }
+        return false;
+    }
+
+    let ast = match ast {
+        Some(ast) => ast,
+        None => return false,
+    };
+    let (ctx, analysis_errors) = HirContext::from_module(&ast);
+    if !analysis_errors.is_empty() {
+        for e in analysis_errors {
+            e.write(&ctx, &mut srcs, src_id, &mut writer);
+        }
+        return false;
+    }
+
+    let mut tests = ctx.defs
+        .tests()
+        .map(|(id, def)| (id, def, false))
+        .chain(ctx.defs.properties().map(|(id, def)| (id, def, true)))
+        .collect::<Vec<_>>();
+    tests.sort_by_key(|(_, def, _)| (def.name.span().src().to_string(), def.name.span().range().start));
+    let total = tests.len();
+    tests.retain(|(_, def, _)| filter.map_or(true, |filter| def.name.contains(filter)));
+
+    writeln!(writer, "running {} tests", tests.len()).unwrap();
+
+    let mut failures = Vec::new();
+    for (id, def, is_property) in &tests {
+        let (concrete, con_errors) = ctx.concretize_entry(*id);
+        let result = if !con_errors.is_empty() {
+            for e in con_errors {
+                e.write(&ctx, &mut srcs, src_id, &mut writer);
+            }
+            Err((format!("test could not be compiled"), Vec::new()))
+        } else {
+            let mut mir = Context::from_concrete(&ctx, &concrete);
+
+            let cfg = ExecConfig {
+                fuel: options.fuel,
+                max_call_depth: options.max_call_depth,
+                max_handler_depth: options.max_handler_depth,
+                max_memory: options.max_memory,
+            };
+
+            if let Err(err) = mir.optimize(&options.opt_config(), &mut writer) {
+                Err((format!("{}", err), Vec::new()))
+            } else if *is_property {
+                check_property(&mir, &cfg)
+            } else {
+                let prog = Program::from_mir(&mir);
+                let mut io = CapturedIo::default();
+                let result = exec(&prog, &cfg, &mut io, &mut Hosts::default(), None);
+                match result {
+                    Ok(None) => Ok(()),
+                    Ok(Some(val)) => match prog.convert_result::<bool>(val) {
+                        Ok(true) => Ok(()),
+                        Ok(false) => Err((format!("test returned False"), io.output)),
+                        Err(err) => Err((format!("{}", err), io.output)),
                     },
-                    None => import_errors.push(Error::CannotImport(import.clone())),
+                    Err(err) => Err((format!("runtime error: {}", err), io.output)),
+                }
+            }
+        };
+
+        match result {
+            Ok(()) => writeln!(writer, "test {} ... ok", *def.name).unwrap(),
+            Err(failure) => {
+                writeln!(writer, "test {} ... FAILED", *def.name).unwrap();
+                failures.push((def, failure));
+            },
+        }
+    }
+
+    if !failures.is_empty() {
+        writeln!(writer, "\nfailures:").unwrap();
+        for (def, (reason, output)) in &failures {
+            writeln!(writer, "\n---- {} at {} ----\n{}", *def.name, location(def.name.span()), reason).unwrap();
+            if !output.is_empty() {
+                writeln!(writer, "output:").unwrap();
+                for line in output {
+                    writeln!(writer, "    {}", line).unwrap();
+                }
+            }
+        }
+    }
+
+    writeln!(
+        writer,
+        "\ntest result: {}. {} passed; {} failed; {} filtered out",
+        if failures.is_empty() { "ok" } else { "FAILED" },
+        tests.len() - failures.len(),
+        failures.len(),
+        total - tests.len(),
+    ).unwrap();
+
+    failures.is_empty()
+}
+
+// The number of generated inputs that each property is checked against
+const PROPERTY_CASES: u64 = 100;
+// The maximum number of times that a counterexample will be shrunk
+const MAX_SHRINKS: usize = 1000;
+
+fn check_property(mir: &Context, cfg: &ExecConfig) -> Result<(), (String, Vec<String>)> {
+    let prog = Program::from_mir_applied(mir);
+    let input = prog.entry_arg.clone().expect("Property must take an input");
+
+    // Returns the reason that the property failed for the input, if it did
+    let check = |input: &Literal| {
+        let arg = litr_to_value(input).expect("Generated input must have a value");
+        let mut io = CapturedIo::default();
+        match exec_with_arg(&prog, cfg, &mut io, &mut Hosts::default(), None, arg) {
+            Ok(Some(val)) => match prog.convert_result::<bool>(val) {
+                Ok(true) => None,
+                Ok(false) => Some((format!("property returned False"), io.output)),
+                Err(err) => Some((format!("{}", err), io.output)),
+            },
+            Ok(None) => None,
+            Err(err) => Some((format!("runtime error: {}", err), io.output)),
+        }
+    };
+
+    // Properties are checked deterministically so that failures can be reproduced
+    let mut rng = Rng::new(0);
+    for case in 0..PROPERTY_CASES {
+        // Start with small inputs, since they make for better counterexamples
+        let mut counterexample = match Literal::arbitrary(&input, &mir.reprs, &mut rng, case + 1) {
+            Some(input) => input,
+            None => return Err((format!("inputs for the property cannot be generated"), Vec::new())),
+        };
+        let mut failure = match check(&counterexample) {
+            Some(failure) => failure,
+            None => continue,
+        };
+
+        // Greedily shrink the counterexample until no smaller input causes the property to fail
+        let mut shrinks = 0;
+        while shrinks < MAX_SHRINKS {
+            match counterexample
+                .shrink(&input, &mir.reprs)
+                .into_iter()
+                .find_map(|smaller| Some((check(&smaller)?, smaller)))
+            {
+                Some((smaller_failure, smaller)) => {
+                    counterexample = smaller;
+                    failure = smaller_failure;
+                    shrinks += 1;
+                },
+                None => break,
+            }
+        }
+
+        let (reason, output) = failure;
+        return Err((
+            format!("{}\ncounterexample (after {} cases and {} shrinks): {}", reason, case + 1, shrinks, counterexample),
+            output,
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn run<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
+    src: String,
+    src_id: SrcId,
+    options: Options,
+    writer: impl Write,
+    get_file: F,
+    make_src: G,
+) {
+    run_with_hosts(src, src_id, options, writer, get_file, make_src, &mut Hosts::default())
+}
+
+/// Like [`run`], but makes the given host functions available to the program as intrinsics.
+pub fn run_with_hosts<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
+    src: String,
+    src_id: SrcId,
+    options: Options,
+    mut writer: impl Write,
+    get_file: F,
+    make_src: G,
+    hosts: &mut Hosts,
+) {
+    if let Some(ctx) = compile_with_hosts(src, src_id, &options, &mut writer, get_file, make_src, hosts) {
+        let prog = Program::from_mir(&ctx);
+
+        if options.debug.contains(&"bytecode".to_string()) {
+            prog.write(&mut writer);
+        }
+
+        let mut profiler = if options.profile || options.profile_folded.is_some() {
+            Some(Profiler::new())
+        } else {
+            None
+        };
+
+        let cfg = ExecConfig {
+            fuel: options.fuel,
+            max_call_depth: options.max_call_depth,
+            max_handler_depth: options.max_handler_depth,
+            max_memory: options.max_memory,
+        };
+
+        match exec(&prog, &cfg, &mut StdIo, hosts, profiler.as_mut()) {
+            Ok(Some(result)) => writeln!(writer, "{}", result).unwrap(),
+            Ok(None) => {},
+            Err(err) => writeln!(writer, "Runtime error: {}", err).unwrap(),
+        }
+
+        if let Some(profiler) = profiler {
+            if options.profile {
+                profiler.write_report(&prog, &mut writer);
+            }
+            if let Some(path) = &options.profile_folded {
+                match File::create(path) {
+                    Ok(file) => profiler.write_folded(&prog, file),
+                    Err(err) => writeln!(writer, "Failed to create profile file '{}': {}", path.display(), err).unwrap(),
                 }
             }
         }
     }
+}
+
+/// Compile a program and the modules it imports to optimised MIR, writing any errors (and requested debugging output)
+/// to the writer. Returns `None` if the program could not be compiled.
+pub fn compile<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
+    src: String,
+    src_id: SrcId,
+    options: &Options,
+    writer: impl Write,
+    get_file: F,
+    make_src: G,
+) -> Option<Context> {
+    compile_with_hosts(src, src_id, options, writer, get_file, make_src, &Hosts::default())
+}
+
+/// Like [`compile`], but makes the given host functions available to the program as intrinsics.
+pub fn compile_with_hosts<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<SrcId>>(
+    src: String,
+    src_id: SrcId,
+    options: &Options,
+    mut writer: impl Write,
+    mut get_file: F,
+    mut make_src: G,
+    hosts: &Hosts,
+) -> Option<Context> {
+    let (mut ast, mut syntax_errors) = parse_module(&src, src_id);
 
     // Resolve imports
     let mut imported = HashMap::new();
     let mut import_errors = Vec::new();
     resolve_imports(src_id, ast.as_deref_mut(), &mut imported, &mut import_errors, &mut syntax_errors, &mut get_file, &mut make_src);
-    let mut srcs = sources(imported.into_iter().chain(std::iter::once((src_id, src))));
+
+    // Parse the declared types of host functions
+    let mut host_srcs = Vec::new();
+    let mut host_tys = hashbrown::HashMap::default();
+    for (name, ty_src) in hosts.decls() {
+        let host_src = SrcId::from_path(format!("<host>/{}", name));
+        let (ty, mut errors) = parse_type(ty_src, host_src);
+        syntax_errors.append(&mut errors);
+        if let Some(ty) = ty {
+            host_tys.insert(name, ty);
+        }
+        host_srcs.push((host_src, ty_src.to_string()));
+    }
+
+    let mut srcs = sources(imported
+        .into_iter()
+        .chain(host_srcs)
+        .chain(std::iter::once((src_id, src))));
     if !import_errors.is_empty() {
         for e in import_errors {
             e.write(&mut srcs, &mut writer);
         }
-        return;
+        return None;
     }
 
     let mut syntax_error = false;
@@ -96,47 +514,43 @@ pub fn run<F: FnMut(SrcId) -> Option<String>, G: FnMut(SrcId, &str) -> Option<Sr
         writeln!(writer, "{:?}", ast).unwrap();
     }
 
-    if let Some(ast) = ast {
-        let (ctx, mut analysis_errors) = HirContext::from_module(&ast);
+    let ast = ast?;
+    let (ctx, analysis_errors) = HirContext::from_module_with_hosts(&ast, host_tys);
 
-        if options.debug.contains(&"hir".to_string()) {
-            for (_, def) in ctx.defs.iter() {
-                writeln!(writer, "{} = {:?}", *def.name, def.body).unwrap();
-            }
+    if options.debug.contains(&"hir".to_string()) {
+        for (_, def) in ctx.defs.iter() {
+            writeln!(writer, "{} = {:?}", *def.name, def.body).unwrap();
         }
+    }
 
-        if !analysis_errors.is_empty() || syntax_error {
-            for e in analysis_errors {
-                e.write(&ctx, &mut srcs, src_id, &mut writer);
-            }
-        } else {
-            let (concrete, mut con_errors) = ctx.concretize();
-
-            if !con_errors.is_empty() {
-                for e in con_errors {
-                    e.write(&ctx, &mut srcs, src_id, &mut writer);
-                }
-            } else {
-                let mut ctx = Context::from_concrete(&ctx, &concrete);
+    if !analysis_errors.is_empty() || syntax_error {
+        for e in analysis_errors {
+            e.write(&ctx, &mut srcs, src_id, &mut writer);
+        }
+        return None;
+    }
 
-                ctx.optimize(options.opt);
+    let (concrete, con_errors) = ctx.concretize();
 
-                if options.debug.contains(&"mir".to_string()) {
-                    for (id, proc) in ctx.procs.iter() {
-                        writeln!(writer, "PROCEDURE {:?}\n\n{}\n", id, proc.body.print()).unwrap();
-                    }
-                }
+    if !con_errors.is_empty() {
+        for e in con_errors {
+            e.write(&ctx, &mut srcs, src_id, &mut writer);
+        }
+        return None;
+    }
 
-                let prog = Program::from_mir(&ctx);
+    let mut ctx = Context::from_concrete(&ctx, &concrete);
 
-                if options.debug.contains(&"bytecode".to_string()) {
-                    prog.write(&mut writer);
-                }
+    if let Err(err) = ctx.optimize(&options.opt_config(), &mut writer) {
+        writeln!(writer, "{}", err).unwrap();
+        return None;
+    }
 
-                if let Some(result) = exec(&prog) {
-                    writeln!(writer, "{}", result).unwrap();
-                }
-            }
+    if options.debug.contains(&"mir".to_string()) {
+        for (id, proc) in ctx.procs.iter() {
+            writeln!(writer, "PROCEDURE {:?}\n\n{}\n", id, proc.body.print()).unwrap();
         }
     }
+
+    Some(ctx)
 }
@@ -1,6 +1,6 @@
-use tao::{Options, SrcId, run};
+use tao::{Options, SrcId, DocFormat, run, format, doc, test};
 use structopt::StructOpt;
-use std::{fs, path::PathBuf};
+use std::{fs, path::{Path, PathBuf}, process::exit};
 
 #[derive(Clone, Debug, StructOpt)]
 pub struct Args {
@@ -8,25 +8,142 @@ pub struct Args {
     pub options: Options,
     /// Specify the file to run
     #[structopt(name = "FILE", parse(from_os_str))]
-    pub file: PathBuf,
+    pub file: Option<PathBuf>,
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+}
+
+#[derive(Clone, Debug, StructOpt)]
+pub enum Command {
+    /// Format Tao source files in place
+    Fmt {
+        /// Don't write the formatted code, instead report files that are not formatted and exit with an error
+        #[structopt(long)]
+        check: bool,
+        /// Specify the files to format
+        #[structopt(name = "FILES", parse(from_os_str))]
+        files: Vec<PathBuf>,
+    },
+    /// Run the tests (definitions marked with `$[test]`) in a Tao program and the modules it imports
+    Test {
+        /// Only run tests with names that contain this string
+        #[structopt(long)]
+        filter: Option<String>,
+        /// Specify the root file of the program
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+    /// Generate documentation for a Tao program and the modules it imports
+    Doc {
+        /// Specify the format of the generated pages (html, md)
+        #[structopt(long, default_value = "html")]
+        format: DocFormat,
+        /// Specify the directory to write the generated pages to
+        #[structopt(short, long, default_value = "doc", parse(from_os_str))]
+        out: PathBuf,
+        /// Specify the root file of the program
+        #[structopt(name = "FILE", parse(from_os_str))]
+        file: PathBuf,
+    },
+}
+
+fn fmt(check: bool, files: Vec<PathBuf>) {
+    let mut failed = false;
+    for file in files {
+        let src = fs::read_to_string(&file)
+            .expect("Failed to read file");
+        let src_id = SrcId::from_path(file.clone());
+        match format(src.clone(), src_id, std::io::stdout()) {
+            Some(formatted) if formatted == src => {},
+            Some(_) if check => {
+                println!("{} is not formatted", file.display());
+                failed = true;
+            },
+            Some(formatted) => fs::write(&file, formatted).expect("Failed to write file"),
+            None => failed = true,
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
+
+fn get_file(src: SrcId) -> Option<String> {
+    fs::read_to_string(src.to_path()).ok()
+}
+
+fn make_src(parent: SrcId, rel: &str) -> Option<SrcId> {
+    let mut path = parent.to_path();
+    path.pop();
+    path.push(rel);
+    Some(SrcId::from_path(path.canonicalize().ok()?))
+}
+
+fn doc_cmd(format: DocFormat, out: PathBuf, file: PathBuf) {
+    let file = file.canonicalize().expect("Failed to find file");
+    let src = fs::read_to_string(&file)
+        .expect("Failed to read file");
+    let base = file.parent().unwrap_or(Path::new("")).to_path_buf();
+
+    // Modules are named by their path relative to the root file, such that `std/list.tao` becomes `std.list`
+    let page_name = |src: SrcId| {
+        let path = src.to_path();
+        let path = path.strip_prefix(&base).unwrap_or(path.as_path()).with_extension("");
+        path
+            .iter()
+            .map(|c| c.to_string_lossy().into_owned())
+            .filter(|c| c != "/")
+            .collect::<Vec<_>>()
+            .join(".")
+    };
+
+    match doc(src, SrcId::from_path(&file), format, std::io::stdout(), get_file, make_src, page_name) {
+        Some(pages) => {
+            fs::create_dir_all(&out).expect("Failed to create output directory");
+            for (name, page) in pages {
+                fs::write(out.join(format!("{}.{}", name, format.extension())), page)
+                    .expect("Failed to write file");
+            }
+        },
+        None => exit(1),
+    }
+}
+
+fn test_cmd(options: Options, filter: Option<String>, file: PathBuf) {
+    let src = fs::read_to_string(&file)
+        .expect("Failed to read file");
+    let src_id = SrcId::from_path(file);
+    if !test(src, src_id, options, filter.as_deref(), std::io::stdout(), get_file, make_src) {
+        exit(1);
+    }
 }
 
 fn main() {
     let args = Args::from_args();
-    let src = fs::read_to_string(&args.file)
+
+    let file = match args.cmd {
+        Some(Command::Fmt { check, files }) => return fmt(check, files),
+        Some(Command::Doc { format, out, file }) => return doc_cmd(format, out, file),
+        Some(Command::Test { filter, file }) => return test_cmd(args.options, filter, file),
+        None => match args.file {
+            Some(file) => file,
+            None => {
+                Args::clap().print_help().unwrap();
+                println!();
+                exit(1);
+            },
+        },
+    };
+
+    let src = fs::read_to_string(&file)
         .expect("Failed to read file");
-    let src_id = SrcId::from_path(args.file);
+    let src_id = SrcId::from_path(file);
     run(
         src,
         src_id,
         args.options,
         std::io::stdout(),
-        |src| fs::read_to_string(src.to_path()).ok(),
-        |parent, rel| {
-            let mut path = parent.to_path();
-            path.pop();
-            path.push(rel);
-            Some(SrcId::from_path(path.canonicalize().ok()?))
-        },
+        get_file,
+        make_src,
     );
 }
@@ -10,68 +10,113 @@ macro_rules! test {
 test!(math);
 test!(lists);
 test!(records);
+test!(patterns);
+test!(classes);
+test!(derive);
+test!(strings);
+test!(constants);
 
-use tao::{Options, OptMode, SrcId, run};
+use tao::{Options, OptMode, SrcId, DocFormat, Hosts, Value, compile, doc, run, run_with_hosts, test};
+use tao_vm::{Program, LowerConfig, ExecConfig, CapturedIo, IntoTao, FromTao, exec, exec_with_arg};
+use structopt::StructOpt;
 use std::fs;
 
+// What running a program from a test file should produce
+enum Expected {
+    Output(String),
+    // Lines that the errors reported while compiling the program must contain
+    Errors(Vec<String>),
+}
+
+// Test files contain several programs, each introduced by `# >>>> INPUT` and followed either by `# >>>> OUTPUT` and its
+// output, or by `# >>>> ERRORS` and the lines that its errors must contain
+fn programs(path: &str) -> Vec<(String, Expected)> {
+    #[derive(Debug)]
+    enum State {
+        Start,
+        Input,
+        Output,
+        Errors,
+    }
+
+    let src = fs::read_to_string(path).unwrap();
+
+    let mut programs = Vec::new();
+    let mut input = String::new();
+    let mut expected = String::new();
+    let mut state = State::Start;
+    for line in src.lines().chain(std::iter::once("# >>>> END")) {
+        match &state {
+            State::Start | State::Output | State::Errors => if line.trim() == "# >>>> INPUT" || line.trim() == "# >>>> END" {
+                match &state {
+                    State::Output => programs.push((input.clone(), Expected::Output(expected.clone()))),
+                    State::Errors => programs.push((input.clone(), Expected::Errors(expected
+                        .lines()
+                        .map(str::trim)
+                        .filter(|line| !line.is_empty())
+                        .map(str::to_string)
+                        .collect()))),
+                    _ => {},
+                }
+                input.clear();
+                expected.clear();
+                state = State::Input;
+            } else {
+                expected += line;
+                expected += "\n";
+            },
+            State::Input => if line.trim() == "# >>>> OUTPUT" {
+                state = State::Output;
+            } else if line.trim() == "# >>>> ERRORS" {
+                state = State::Errors;
+            } else {
+                input += line;
+                input += "\n";
+            },
+        }
+    }
+
+    programs
+}
+
 fn test_configs(name: &str) {
     fn test_config(name: &str, options: Options) {
         let path = format!("tests/{}.tao", name);
-        let src = fs::read_to_string(&path).unwrap();
         let src_id = SrcId::from_path(&path);
 
-        let mut input = String::new();
-        let mut expected = String::new();
-
-        #[derive(Debug)]
-        enum State {
-            Start,
-            Input,
-            Output,
-        }
-
-        let mut state = State::Start;
-        for line in src.lines().chain(std::iter::once("# >>>> END")) {
-            match &state {
-                State::Start | State::Output => if line.trim() == "# >>>> INPUT" || line.trim() == "# >>>> END" {
-                    if let State::Output = &state {
-                        let mut output = Vec::new();
-                        run(
-                            input.clone(),
-                            src_id,
-                            options.clone(),
-                            &mut output,
-                            |src| fs::read_to_string(src.to_path()).ok(),
-                            |parent, rel| {
-                                let mut path = parent.to_path();
-                                path.pop();
-                                path.push(rel);
-                                let path = path.canonicalize().ok()?;
-                                Some(SrcId::from_path(path))
-                            },
-                        );
-                        let output = String::from_utf8(output).unwrap();
-                        if output.trim() != expected.trim() {
-                            panic!("\n\n \
-                                ========[ EXPECTED OUTPUT ]========\n\n\
-                                {}\n \
-                                ========[ FOUND OUTPUT ]========\n\n\
-                                {}\n", expected, output);
-                        }
-
-                        input.clear();
-                        expected.clear();
-                    }
-                    state = State::Input;
-                } else {
-                    expected += line;
-                    expected += "\n";
+        for (input, expected) in programs(&path) {
+            let mut output = Vec::new();
+            run(
+                input,
+                src_id,
+                options.clone(),
+                &mut output,
+                |src| fs::read_to_string(src.to_path()).ok(),
+                |parent, rel| {
+                    let mut path = parent.to_path();
+                    path.pop();
+                    path.push(rel);
+                    let path = path.canonicalize().ok()?;
+                    Some(SrcId::from_path(path))
                 },
-                State::Input => if line.trim() == "# >>>> OUTPUT" {
-                    state = State::Output;
-                } else {
-                    input += line;
-                    input += "\n";
+            );
+            let output = String::from_utf8(output).unwrap();
+            match expected {
+                Expected::Output(expected) => if output.trim() != expected.trim() {
+                    panic!("\n\n \
+                        ========[ EXPECTED OUTPUT ]========\n\n\
+                        {}\n \
+                        ========[ FOUND OUTPUT ]========\n\n\
+                        {}\n", expected, output);
+                },
+                Expected::Errors(errors) => for error in errors {
+                    if !output.contains(&error) {
+                        panic!("\n\n \
+                            ========[ EXPECTED ERROR ]========\n\n\
+                            {}\n \
+                            ========[ FOUND OUTPUT ]========\n\n\
+                            {}\n", error, output);
+                    }
                 },
             }
         }
@@ -80,9 +125,575 @@ fn test_configs(name: &str) {
     let mut options = Options {
         debug: Vec::new(),
         opt: OptMode::None,
+        passes: Vec::new(),
+        fixed_point: false,
+        share_instances: false,
+        verify_mir: true,
+        profile: false,
+        profile_folded: None,
+        fuel: None,
+        max_call_depth: None,
+        max_handler_depth: None,
+        max_memory: None,
     };
     options.opt = OptMode::None;
     test_config(name, options.clone());
     options.opt = OptMode::Fast;
     test_config(name, options.clone());
+    options.fixed_point = true;
+    test_config(name, options.clone());
+    options.fixed_point = false;
+    options.share_instances = true;
+    test_config(name, options.clone());
+}
+
+#[test]
+fn test_runner() {
+    fn run_tests(filter: Option<&str>) -> (bool, String) {
+        let path = "tests/testing.tao";
+        let src = fs::read_to_string(path).unwrap();
+        let options = Options {
+            debug: Vec::new(),
+            opt: OptMode::None,
+            passes: Vec::new(),
+            fixed_point: false,
+            share_instances: false,
+            verify_mir: false,
+            profile: false,
+            profile_folded: None,
+            fuel: None,
+            max_call_depth: None,
+            max_handler_depth: None,
+            max_memory: None,
+        };
+        let mut output = Vec::new();
+        let passed = test(
+            src,
+            SrcId::from_path(path),
+            options,
+            filter,
+            &mut output,
+            |src| fs::read_to_string(src.to_path()).ok(),
+            |parent, rel| {
+                let mut path = parent.to_path();
+                path.pop();
+                path.push(rel);
+                let path = path.canonicalize().ok()?;
+                Some(SrcId::from_path(path))
+            },
+        );
+        (passed, String::from_utf8(output).unwrap())
+    }
+
+    let (passed, output) = run_tests(None);
+    assert!(!passed, "{}", output);
+    assert!(output.contains("test addition ... ok"), "{}", output);
+    assert!(output.contains("test list_len ... ok"), "{}", output);
+    assert!(output.contains("test greeting ... ok"), "{}", output);
+    assert!(output.contains("test broken ... FAILED"), "{}", output);
+    assert!(output.contains("---- broken at tests/testing.tao:13:5 ----"), "{}", output);
+    assert!(output.contains("test rev_preserves_len ... ok"), "{}", output);
+    assert!(output.contains("test small_sums ... FAILED"), "{}", output);
+    assert!(output.contains("counterexample"), "{}", output);
+
+    let (passed, output) = run_tests(Some("add"));
+    assert!(passed, "{}", output);
+    assert!(output.contains("1 passed; 0 failed; 5 filtered out"), "{}", output);
+}
+
+// Programs given inline by the tests below are compiled as though they were in `tests/`, so that they can import the
+// standard library in the same way as the test files do
+const INLINE_PATH: &str = "tests/inline.tao";
+
+fn get_file(src: SrcId) -> Option<String> {
+    fs::read_to_string(src.to_path()).ok()
+}
+
+fn make_src(parent: SrcId, rel: &str) -> Option<SrcId> {
+    let mut path = parent.to_path();
+    path.pop();
+    path.push(rel);
+    Some(SrcId::from_path(path.canonicalize().ok()?))
+}
+
+// Parse options as the command line would
+fn options(args: &[&str]) -> Options {
+    Options::from_iter(std::iter::once("tao").chain(args.iter().copied()))
+}
+
+// Compile a program given inline, returning its MIR (if it compiled) and everything written while compiling it
+fn compile_inline(src: &str, options: &Options) -> (Option<tao_middle::Context>, String) {
+    let mut output = Vec::new();
+    let ctx = compile(src.to_string(), SrcId::from_path(INLINE_PATH), options, &mut output, get_file, make_src);
+    (ctx, String::from_utf8(output).unwrap())
+}
+
+// Run a program given inline, returning everything written while compiling and running it
+fn run_inline(src: &str, options: Options) -> String {
+    let mut output = Vec::new();
+    run(src.to_string(), SrcId::from_path(INLINE_PATH), options, &mut output, get_file, make_src);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn profile() {
+    // Recurses deeply, but not in tail position
+    let src = r#"
+        import "../../lib/std.tao"
+
+        fn count : Nat -> Nat =
+            | 0 => 0
+            \ n + 1 => 1 + count(n)
+
+        $[main]
+        def main = count(20000)
+    "#;
+
+    let path = std::env::temp_dir().join("tao_profile_test.folded");
+    let output = run_inline(src, options(&["--profile", "--profile-folded", path.to_str().unwrap()]));
+    assert!(output.contains("20000i"), "{}", output);
+    assert!(output.contains("Executed"), "{}", output);
+    assert!(output.contains("procedure"), "{}", output);
+    assert!(output.contains("opcode"), "{}", output);
+
+    // Recursive calls are merged, so the number of call paths doesn't depend on the depth of the recursion
+    let folded = fs::read_to_string(&path).unwrap();
+    let _ = fs::remove_file(&path);
+    let lines = folded.lines().collect::<Vec<_>>();
+    assert!(!lines.is_empty() && lines.len() < 20, "{}", folded);
+    for line in lines {
+        let (_, count) = line.rsplit_once(' ').unwrap();
+        assert!(count.parse::<u64>().unwrap() > 0, "{}", folded);
+    }
+
+    let output = run_inline(src, options(&["--profile-folded", "no/such/directory/profile.folded"]));
+    assert!(output.contains("Failed to create profile file"), "{}", output);
+}
+
+#[test]
+fn exec_limits() {
+    let count = r#"
+        import "../../lib/std.tao"
+
+        fn count : Nat -> Nat =
+            | 0 => 0
+            \ n + 1 => 1 + count(n)
+
+        $[main]
+        def main = count(20000)
+    "#;
+    let output = run_inline(count, options(&["--fuel", "100"]));
+    assert!(output.contains("Runtime error: program ran out of fuel after 100 instructions"), "{}", output);
+    let output = run_inline(count, options(&["--max-call-depth", "100"]));
+    assert!(output.contains("Runtime error: call depth exceeded the limit of 100"), "{}", output);
+    let output = run_inline(count, options(&["--fuel", "10000000", "--max-call-depth", "50000"]));
+    assert!(output.contains("20000i"), "{}", output);
+
+    // Calls in tail position reuse the current frame, so they don't count towards the call depth
+    let count_tail = r#"
+        import "../../lib/std.tao"
+
+        fn count : Nat -> Nat -> Nat =
+            | 0, acc => acc
+            \ n + 1, acc => count(n, acc + 1)
+
+        $[main]
+        def main = count(20000, 0)
+    "#;
+    let output = run_inline(count_tail, options(&["--max-call-depth", "100"]));
+    assert!(output.contains("20000i"), "{}", output);
+
+    // Each handler asks for a name, which is found by running the program again inside the handler
+    let handlers = r#"
+        import "../../lib/std.tao"
+
+        effect get_name = () => Str
+
+        def get_name : get_name ~ Str = @{ @suspend(()) }
+
+        def hello : get_name ~ Str = @{
+            let name = get_name! in
+            "Hello, " ++ name ++ "!"
+        }
+
+        fn name_for : Nat -> Str =
+            | 0 => "you"
+            \ n + 1 => hello_to(n)
+
+        fn hello_to : Nat -> Str =
+            n => hello
+                handle get_name with () => name_for(n)
+
+        $[main]
+        def main = hello_to(5)
+    "#;
+    let output = run_inline(handlers, options(&["--max-handler-depth", "2"]));
+    assert!(output.contains("Runtime error: effect handler depth exceeded the limit of 2"), "{}", output);
+    let output = run_inline(handlers, options(&["--max-handler-depth", "10"]));
+    assert!(output.contains("Hello, Hello, Hello, Hello, Hello, Hello, you!!!!!!"), "{}", output);
+
+    let build = r#"
+        import "../../lib/std.tao"
+
+        fn build : Nat -> [Nat] =
+            | 0 => []
+            \ n + 1 => [n .. build(n)]
+
+        fn repeat_list : Nat -> [Nat] -> [[Nat]] =
+            | 0, _ => []
+            \ n + 1, xs => [xs .. repeat_list(n, xs)]
+    "#;
+    let output = run_inline(
+        &format!("{}\n$[main]\ndef main = build(100000)->len", build),
+        options(&["--max-memory", "1000000"]),
+    );
+    assert!(output.contains("Runtime error: estimated memory usage exceeded the limit of 1000000 bytes"), "{}", output);
+    // The list is shared by every item of the outer list, so it's only counted once
+    let output = run_inline(
+        &format!("{}\n$[main]\ndef main = repeat_list(1000, build(1000))->len", build),
+        options(&["--max-memory", "1000000"]),
+    );
+    assert!(output.contains("1000i"), "{}", output);
+}
+
+#[test]
+fn host_functions() {
+    let run_hosted = |src: &str| {
+        let mut hosts = Hosts::default();
+        hosts
+            .register("double", "Nat -> Nat", |args| Ok(Value::Int(args[0].clone().int() * 2)))
+            .register("join", "Str -> Str -> Str", |args| {
+                let mut s = args[0].clone().list();
+                s.append(args[1].clone().list());
+                Ok(Value::List(s))
+            });
+        let mut output = Vec::new();
+        run_with_hosts(src.to_string(), SrcId::from_path(INLINE_PATH), options(&[]), &mut output, get_file, make_src, &mut hosts);
+        String::from_utf8(output).unwrap()
+    };
+
+    let output = run_hosted(r#"
+        import "../../lib/std.tao"
+
+        $[main]
+        def main = @double(@double(5) + 11)
+    "#);
+    assert!(output.contains("42i"), "{}", output);
+
+    let output = run_hosted(r#"
+        import "../../lib/std.tao"
+
+        $[main]
+        def main = @join("Hello, ", "world!")
+    "#);
+    assert!(output.contains("Hello, world!"), "{}", output);
+
+    // Arguments must have the declared type
+    let output = run_hosted(r#"
+        import "../../lib/std.tao"
+
+        $[main]
+        def main = @double('a')
+    "#);
+    assert!(output.contains("does not coerce to"), "{}", output);
+    assert!(!output.contains("Hello"), "{}", output);
+
+    // Only registered host functions may be called
+    let output = run_hosted(r#"
+        import "../../lib/std.tao"
+
+        $[main]
+        def main = @missing(1)
+    "#);
+    assert!(output.contains("is not valid"), "{}", output);
+}
+
+// Pass a value to a Tao function of the given type, converting the result back into Rust. The value is converted back
+// and forth under every optimisation mode, since optimisation may change how values are represented.
+fn apply_inline<T: IntoTao + Clone, U: FromTao>(ty: &str, body: &str, arg: T) -> Vec<U> {
+    ["none", "fast"]
+        .iter()
+        .map(|opt| {
+            let src = format!("import \"../../lib/std.tao\"\n\n{}\n\n$[main]\ndef main : {} = {}\n", DERIVED_DATA, ty, body);
+            let (ctx, output) = compile_inline(&src, &options(&["--opt", opt]));
+            let prog = Program::from_mir_applied(&ctx.unwrap_or_else(|| panic!("{}", output)));
+            let result = exec_with_arg(&prog, &ExecConfig::default(), &mut CapturedIo::default(), &mut Hosts::default(), None, arg.clone().into_tao())
+                .unwrap()
+                .unwrap();
+            prog.convert_result::<U>(result).unwrap_or_else(|err| panic!("{} (with `--opt {}`)", err, opt))
+        })
+        .collect()
+}
+
+fn round_trip<T: IntoTao + FromTao + Clone + PartialEq + std::fmt::Debug>(ty: &str, x: T) {
+    for y in apply_inline::<T, T>(&format!("{} -> {}", ty, ty), "fn x => x", x.clone()) {
+        assert_eq!(x, y, "{}", ty);
+    }
+}
+
+const DERIVED_DATA: &str = "
+data Point = { x: Int, y: Int }
+
+data Distance = { metres: Real }
+
+data Shape =
+    | Dot
+    | Circle Nat
+    | Line (Point, Point)
+    \\ Rect { w: Nat, h: Nat }
+";
+
+#[derive(Clone, Debug, PartialEq, tao_vm::IntoTao, tao_vm::FromTao)]
+struct Point {
+    x: i64,
+    y: i64,
+}
+
+#[derive(Clone, Debug, PartialEq, tao_vm::IntoTao, tao_vm::FromTao)]
+struct Distance {
+    metres: f64,
+}
+
+#[derive(Clone, Debug, PartialEq, tao_vm::IntoTao, tao_vm::FromTao)]
+enum Shape {
+    Dot,
+    Circle(u64),
+    Line(Point, Point),
+    Rect { w: u64, h: u64 },
+}
+
+#[test]
+fn convert() {
+    round_trip("Nat", 42u64);
+    round_trip("Int", -42i64);
+    round_trip("Real", 1.5f64);
+    round_trip("Char", 'λ');
+    round_trip("Bool", true);
+    round_trip("Bool", false);
+    round_trip("()", ());
+    round_trip("Str", "Hello, world!".to_string());
+    round_trip("Str", String::new());
+    round_trip("[Nat]", vec![1u64, 2, 3]);
+    round_trip("[[Char]]", vec![vec!['a'], vec![], vec!['b', 'c']]);
+    round_trip("Maybe Int", Some(-3i64));
+    round_trip("Maybe Int", None::<i64>);
+    round_trip("Result Nat Str", Ok::<u64, String>(7));
+    round_trip("Result Nat Str", Err::<u64, String>("oops".to_string()));
+
+    round_trip("(Nat, Int)", (1u64, -2i64));
+    round_trip("(Nat, Int, Char)", (1u64, -2i64, 'c'));
+    round_trip("(Nat, Int, Char, Bool)", (1u64, -2i64, 'c', true));
+    round_trip("(Nat, Int, Char, Bool, Str)", (1u64, -2i64, 'c', true, "e".to_string()));
+    round_trip("(Nat, Int, Char, Bool, Str, [Nat])", (1u64, -2i64, 'c', true, "e".to_string(), vec![6u64]));
+    round_trip(
+        "(Nat, Int, Char, Bool, Str, [Nat], Real)",
+        (1u64, -2i64, 'c', true, "e".to_string(), vec![6u64], 7.5f64),
+    );
+    round_trip(
+        "(Nat, Int, Char, Bool, Str, [Nat], Real, Maybe Nat)",
+        (1u64, -2i64, 'c', true, "e".to_string(), vec![6u64], 7.5f64, Some(8u64)),
+    );
+
+    round_trip("Point", Point { x: 3, y: -4 });
+    round_trip("Distance", Distance { metres: 2.5 });
+    round_trip("[Shape]", vec![
+        Shape::Dot,
+        Shape::Circle(3),
+        Shape::Line(Point { x: 0, y: 1 }, Point { x: 2, y: 3 }),
+        Shape::Rect { w: 4, h: 5 },
+    ]);
+
+    // Values constructed by Tao have the layout that the derived conversions expect
+    let cases = [
+        (Shape::Circle(2), Shape::Rect { w: 2, h: 3 }),
+        (Shape::Rect { w: 3, h: 4 }, Shape::Line(Point { x: -3, y: 0 }, Point { x: 0, y: -4 })),
+        (Shape::Line(Point { x: 0, y: 0 }, Point { x: 1, y: 1 }), Shape::Dot),
+        (Shape::Dot, Shape::Circle(9)),
+    ];
+    for (shape, expected) in cases {
+        let transform = "fn
+            | Circle r => Rect { w: r, h: r + 1 }
+            | Rect { w, h } => Line (Point { x: -w, y: 0 }, Point { x: 0, y: -h })
+            | Line _ => Dot
+            \\ Dot => Circle 9";
+        for result in apply_inline::<Shape, Shape>("Shape -> Shape", transform, shape.clone()) {
+            assert_eq!(result, expected);
+        }
+    }
+    let negated = apply_inline::<Distance, Distance>("Distance -> Distance", "fn d => Distance { metres: -d.metres }", Distance { metres: 1.25 });
+    for d in negated {
+        assert_eq!(d, Distance { metres: -1.25 });
+    }
+
+    // Mismatched types are reported rather than producing garbage
+    let (ctx, output) = compile_inline("import \"../../lib/std.tao\"\n\n$[main]\ndef main : Nat -> (Nat, Char) = fn x => (x, 'a')\n", &options(&[]));
+    let prog = Program::from_mir_applied(&ctx.unwrap_or_else(|| panic!("{}", output)));
+    let result = exec_with_arg(&prog, &ExecConfig::default(), &mut CapturedIo::default(), &mut Hosts::default(), None, 1u64.into_tao())
+        .unwrap()
+        .unwrap();
+    assert!(prog.convert_result::<(u64, u64)>(result.clone()).is_err());
+    assert!(prog.convert_result::<Point>(result.clone()).is_err());
+    assert_eq!(prog.convert_result::<(u64, char)>(result).unwrap(), (1, 'a'));
+}
+
+#[test]
+fn lowering_configs() {
+    // Every way of lowering a program must produce the same result, and matches are where they differ the most
+    let path = "tests/patterns.tao";
+    let configs = [
+        LowerConfig::default(),
+        LowerConfig { decision_trees: false, ..LowerConfig::default() },
+        LowerConfig { superinstructions: false, decision_trees: false, tail_calls: false, reuse: false },
+    ];
+    for (src, expected) in programs(path) {
+        if let Expected::Output(expected) = expected {
+            let mut output = Vec::new();
+            let ctx = compile(src, SrcId::from_path(path), &options(&[]), &mut output, get_file, make_src)
+                .unwrap_or_else(|| panic!("{}", String::from_utf8_lossy(&output)));
+            for lower in &configs {
+                let prog = Program::from_mir_with(&ctx, lower);
+                let result = exec(&prog, &ExecConfig::default(), &mut CapturedIo::default(), &mut Hosts::default(), None)
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(result.to_string(), expected.trim(), "{:?}", lower);
+            }
+        }
+    }
+}
+
+#[test]
+fn docs() {
+    let src = r#"
+        #! Shapes and their areas
+        #!
+        #! More about shapes
+
+        import "../../lib/std.tao"
+
+        ## A shape with a size
+        ##
+        ## ```
+        ## Circle 5
+        ## ```
+        data Shape =
+            | Circle Nat
+            \ Square Nat
+
+        ## The area of a shape, where `x < y` needs escaping
+        fn area : Shape -> Nat =
+            | Circle r => 3 * r * r
+            \ Square s => s * s
+
+        $[doc(foo)]
+        def undocumented = 5
+    "#;
+    let generate = |format| {
+        let mut output = Vec::new();
+        let pages = doc(
+            src.to_string(),
+            SrcId::from_path(INLINE_PATH),
+            format,
+            &mut output,
+            get_file,
+            make_src,
+            |src| if src == SrcId::from_path(INLINE_PATH) { "inline".to_string() } else { src.to_string().replace('/', "_") },
+        );
+        let pages = pages.unwrap_or_else(|| panic!("{}", String::from_utf8(output).unwrap()));
+        let page = |name: &str| pages.iter().find(|(n, _)| n == name).unwrap().1.clone();
+        (page("inline"), page("index"))
+    };
+
+    let (page, index) = generate(DocFormat::Markdown);
+    assert!(page.contains("Shapes and their areas\n\nMore about shapes"), "{}", page);
+    assert!(page.contains("## Data types"), "{}", page);
+    assert!(page.contains("data Shape\n    | Circle Nat\n    \\ Square Nat"), "{}", page);
+    assert!(page.contains("A shape with a size"), "{}", page);
+    assert!(page.contains("## Definitions"), "{}", page);
+    assert!(page.contains("def area : Shape -> Nat"), "{}", page);
+    assert!(page.contains("See also: [`Shape`](inline.md#data.Shape)"), "{}", page);
+    assert!(page.contains("def undocumented"), "{}", page);
+    // Attributes written by hand are not documentation
+    assert!(!page.contains("foo"), "{}", page);
+    assert!(index.contains("(inline.md) Shapes and their areas\n"), "{}", index);
+
+    let (page, index) = generate(DocFormat::Html);
+    assert!(page.contains("<p>Shapes and their areas</p>\n<p>More about shapes</p>"), "{}", page);
+    assert!(page.contains("<pre><code>Circle 5</code></pre>"), "{}", page);
+    assert!(page.contains("<code>x &lt; y</code>"), "{}", page);
+    assert!(page.contains("id=\"def.area\""), "{}", page);
+    assert!(page.contains("<a href=\"inline.html#data.Shape\">Shape</a>"), "{}", page);
+    assert!(!page.contains("foo"), "{}", page);
+    assert!(index.contains("href=\"inline.html\""), "{}", index);
+}
+
+#[test]
+fn verify_mir() {
+    let src = r#"
+        import "../../lib/std.tao"
+
+        $[main]
+        def main = 1 + 2
+    "#;
+    let (ctx, output) = compile_inline(src, &options(&[]));
+    let mut ctx = ctx.unwrap_or_else(|| panic!("{}", output));
+    ctx.verify("lowering").unwrap();
+
+    // A procedure that is declared but never defined is inconsistent
+    let missing = tao_middle::ProcId::lifted(ctx.entry.unwrap(), usize::MAX);
+    ctx.procs.declare(missing);
+
+    let mut cfg = tao_middle::OptConfig::new(OptMode::Fast);
+    cfg.verify = true;
+    let err = ctx.optimize(&cfg, &mut Vec::<u8>::new()).unwrap_err();
+    let report = format!("{}", err);
+    assert!(matches!(err, tao_middle::OptError::Verify(_)), "{}", report);
+    assert!(report.contains("MIR verification failed after lowering"), "{}", report);
+    assert!(report.contains(&format!("in procedure {:?}", missing)), "{}", report);
+    assert!(report.contains("declared but never defined"), "{}", report);
+}
+
+#[test]
+fn unknown_pass() {
+    let src = r#"
+        import "../../lib/std.tao"
+
+        $[main]
+        def main = 1 + 2
+    "#;
+    let output = run_inline(src, options(&["--passes=const-fold,bogus"]));
+    assert!(output.contains("No optimisation pass named 'bogus'"), "{}", output);
+    assert!(output.contains("lambda-lift"), "{}", output);
+    assert!(!output.contains("3i"), "{}", output);
+}
+
+#[test]
+fn share_instances() {
+    // The program is run by `records.tao`, this checks that its instantiations really are shared
+    let (src, _) = programs("tests/records.tao")
+        .into_iter()
+        .find(|(src, _)| src.contains("fn count_a"))
+        .expect("records.tao should contain a program that defines `count_a`");
+    let src = src.as_str();
+
+    // The number of instances of the item reported by `--debug instances`
+    fn instances(output: &str, item: &str) -> usize {
+        output
+            .lines()
+            .find_map(|line| {
+                let mut columns = line.split('|').map(str::trim);
+                if columns.next()? == item { columns.next()?.parse().ok() } else { None }
+            })
+            .unwrap_or_else(|| panic!("No instances of {} were reported:\n{}", item, output))
+    }
+
+    let output = run_inline(src, options(&["--debug", "instances"]));
+    assert_eq!(instances(&output, "count_a"), 2, "{}", output);
+    assert_eq!(instances(&output, "count_b"), 2, "{}", output);
+
+    for opt in ["none", "fast"] {
+        let output = run_inline(src, options(&["--opt", opt, "--share-instances", "--debug", "instances"]));
+        assert_eq!(instances(&output, "count_a"), 1, "{}", output);
+        assert_eq!(instances(&output, "count_b"), 1, "{}", output);
+        assert!(output.contains("instances shared"), "{}", output);
+    }
 }
@@ -0,0 +1,225 @@
+//! Generation and shrinking of arbitrary values, used to check properties.
+
+use super::*;
+
+/// A small, deterministic pseudo-random number generator (xorshift64*).
+///
+/// Property tests should be reproducible, so the generator is always seeded explicitly.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift, so avoid it
+        Self(seed ^ 0x9E37_79B9_7F4A_7C15)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    /// Generate a number in the range `0..=max`.
+    pub fn below(&mut self, max: u64) -> u64 {
+        match max.checked_add(1) {
+            Some(n) => self.next_u64() % n,
+            None => self.next_u64(),
+        }
+    }
+
+    pub fn chance(&mut self, n: u64) -> bool { self.below(n.saturating_sub(1)) == 0 }
+}
+
+// Recursion below this depth only picks variants that do not recurse, so that generation terminates
+const MAX_DEPTH: usize = 8;
+
+impl Literal {
+    /// Generate an arbitrary value with the given representation.
+    ///
+    /// `size` bounds the magnitude of numbers and the length of lists. Returns `None` if the representation has no
+    /// values that can be generated (functions, for example).
+    pub fn arbitrary(repr: &Repr, reprs: &Reprs, rng: &mut Rng, size: u64) -> Option<Self> {
+        Self::arbitrary_at(repr, reprs, rng, size, 0)
+    }
+
+    fn arbitrary_at(repr: &Repr, reprs: &Reprs, rng: &mut Rng, size: u64, depth: usize) -> Option<Self> {
+        Some(match repr {
+            Repr::Prim(Prim::Nat) => Self::Nat(rng.below(size)),
+            Repr::Prim(Prim::Int) => Self::Int(rng.below(size * 2) as i64 - size as i64),
+            Repr::Prim(Prim::Real) => Self::Real((rng.below(size * 200) as f64 - (size * 100) as f64) / 100.0),
+            Repr::Prim(Prim::Char) => Self::Char(if rng.chance(8) {
+                // Occasionally pick something outside of ASCII
+                char::from_u32(rng.below(0xFFFF) as u32).unwrap_or('?')
+            } else {
+                (b' ' + rng.below((b'~' - b' ') as u64) as u8) as char
+            }),
+            Repr::Prim(Prim::Bool) => Self::Sum(rng.below(1) as usize, Box::new(Self::Tuple(Vec::new()))),
+            Repr::Prim(Prim::Universe) => return None,
+            Repr::List(item) => {
+                let len = if depth >= MAX_DEPTH { 0 } else { rng.below(size) };
+                Self::List((0..len)
+                    .map(|_| Self::arbitrary_at(item, reprs, rng, size, depth + 1))
+                    .collect::<Option<_>>()?)
+            },
+            Repr::Tuple(fields) => Self::Tuple(fields
+                .iter()
+                .map(|field| Self::arbitrary_at(field, reprs, rng, size, depth + 1))
+                .collect::<Option<_>>()?),
+            Repr::Sum(variants) => {
+                // Try variants in a random order, preferring ones that don't recurse once we're deep enough
+                let start = rng.below(variants.len().saturating_sub(1) as u64) as usize;
+                let mut order = (0..variants.len())
+                    .map(|i| (start + i) % variants.len())
+                    .collect::<Vec<_>>();
+                if depth >= MAX_DEPTH {
+                    order.sort_by_key(|i| Self::recursion(&variants[*i], reprs));
+                }
+                order
+                    .into_iter()
+                    .find_map(|i| Some(Self::Sum(i, Box::new(Self::arbitrary_at(&variants[i], reprs, rng, size, depth + 1)?))))?
+            },
+            Repr::Data(data) => {
+                let data_repr = &reprs.datas.get(data)?.as_ref()?.repr;
+                // Recursive data types get smaller as they nest
+                let size = if reprs.get(*data).is_recursive { size / 2 } else { size };
+                Self::Data(*data, Box::new(Self::arbitrary_at(data_repr, reprs, rng, size, depth + 1)?))
+            },
+            Repr::Func(_, _) | Repr::Effect(_, _) => return None,
+        })
+    }
+
+    // The number of recursive data types directly reachable from a representation, used to find base cases
+    fn recursion(repr: &Repr, reprs: &Reprs) -> usize {
+        match repr {
+            Repr::Prim(_) | Repr::Func(_, _) | Repr::Effect(_, _) => 0,
+            Repr::List(_) => 0, // Lists can always be empty
+            Repr::Tuple(fields) => fields.iter().map(|field| Self::recursion(field, reprs)).sum(),
+            Repr::Sum(variants) => variants.iter().map(|variant| Self::recursion(variant, reprs)).min().unwrap_or(0),
+            Repr::Data(data) => reprs.datas
+                .get(data)
+                .and_then(|data| data.as_ref())
+                .map_or(0, |data| data.is_recursive as usize),
+        }
+    }
+
+    /// Produce a list of values of the given representation that are 'smaller' than this one, simplest first.
+    ///
+    /// Used to find a minimal counterexample when a property fails.
+    pub fn shrink(&self, repr: &Repr, reprs: &Reprs) -> Vec<Self> {
+        match (self, repr) {
+            (_, Repr::Data(data)) => match self {
+                Self::Data(_, inner) => {
+                    // A recursive data type can shrink to any value of the same type nested within it
+                    let mut xs = Vec::new();
+                    inner.find_data(*data, &mut xs);
+                    xs.extend(inner
+                        .shrink(&reprs.get(*data).repr, reprs)
+                        .into_iter()
+                        .map(|inner| Self::Data(*data, Box::new(inner))));
+                    xs
+                },
+                _ => Vec::new(),
+            },
+            (Self::Tuple(fields), Repr::Tuple(field_reprs)) => shrink_each(fields, field_reprs, reprs)
+                .into_iter()
+                .map(Self::Tuple)
+                .collect(),
+            (Self::List(items), Repr::List(item)) => {
+                let mut xs = Vec::new();
+                if !items.is_empty() {
+                    xs.push(Vec::new());
+                }
+                if items.len() > 1 {
+                    xs.push(items[..items.len() / 2].to_vec());
+                    xs.push(items[items.len() / 2..].to_vec());
+                }
+                if items.len() > 2 {
+                    for i in 0..items.len() {
+                        let mut items = items.clone();
+                        items.remove(i);
+                        xs.push(items);
+                    }
+                }
+                let item_reprs = vec![(**item).clone(); items.len()];
+                xs.extend(shrink_each(items, &item_reprs, reprs));
+                xs.into_iter().map(Self::List).collect()
+            },
+            (Self::Sum(variant, inner), Repr::Sum(variants)) => {
+                // Variants without an inner value (`None`, `Nil`, etc.) are the simplest, so try them first
+                let mut xs = (0..variants.len())
+                    .filter(|v| v != variant && matches!(&variants[*v], Repr::Tuple(fields) if fields.is_empty()))
+                    .map(|v| Self::Sum(v, Box::new(Self::Tuple(Vec::new()))))
+                    .collect::<Vec<_>>();
+                xs.extend(inner
+                    .shrink(&variants[*variant], reprs)
+                    .into_iter()
+                    .map(|inner| Self::Sum(*variant, Box::new(inner))));
+                xs
+            },
+            (Self::Sum(variant, _), Repr::Prim(Prim::Bool)) => (0..*variant)
+                .map(|v| Self::Sum(v, Box::new(Self::Tuple(Vec::new()))))
+                .collect(),
+            _ => self.shrink_prim(),
+        }
+    }
+
+    fn shrink_prim(&self) -> Vec<Self> {
+        match self {
+            Self::Never | Self::Unknown(_) => Vec::new(),
+            Self::Nat(x) => shrink_nat(*x).into_iter().map(Self::Nat).collect(),
+            Self::Int(x) => {
+                let mut xs = shrink_nat(x.unsigned_abs()).into_iter().map(|y| y as i64 * x.signum()).collect::<Vec<_>>();
+                if *x < 0 { xs.insert(1.min(xs.len()), x.wrapping_neg()); }
+                xs.into_iter().map(Self::Int).collect()
+            },
+            Self::Real(x) => if *x == 0.0 {
+                Vec::new()
+            } else if x.fract() != 0.0 {
+                vec![Self::Real(0.0), Self::Real(x.trunc())]
+            } else {
+                shrink_nat(x.abs() as u64).into_iter().map(|y| Self::Real(y as f64 * x.signum())).collect()
+            },
+            Self::Char(c) => ['a', 'b', 'A', '0', ' ']
+                .iter()
+                .take_while(|s| *s != c)
+                .map(|c| Self::Char(*c))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn find_data(&self, data: ConDataId, found: &mut Vec<Self>) {
+        match self {
+            Self::Data(d, _) if *d == data => found.push(self.clone()),
+            Self::Data(_, inner) | Self::Sum(_, inner) => inner.find_data(data, found),
+            Self::Tuple(xs) | Self::List(xs) => xs.iter().for_each(|x| x.find_data(data, found)),
+            _ => {},
+        }
+    }
+}
+
+fn shrink_nat(x: u64) -> Vec<u64> {
+    let mut xs = Vec::new();
+    let mut diff = x;
+    while diff > 0 {
+        xs.push(x - diff);
+        diff /= 2;
+    }
+    xs
+}
+
+// Shrink one element at a time, keeping the others the same
+fn shrink_each(xs: &[Literal], reprs_of: &[Repr], reprs: &Reprs) -> Vec<Vec<Literal>> {
+    xs
+        .iter()
+        .zip(reprs_of)
+        .enumerate()
+        .flat_map(|(i, (x, repr))| x.shrink(repr, reprs).into_iter().map(move |x| {
+            let mut xs = xs.to_vec();
+            xs[i] = x;
+            xs
+        }))
+        .collect()
+}
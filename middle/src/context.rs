@@ -1,6 +1,6 @@
 use super::*;
 
-use std::{fmt, str::FromStr};
+use std::{fmt, io::Write, str::FromStr};
 
 pub struct Context {
     pub reprs: Reprs,
@@ -25,33 +25,15 @@ impl Context {
         this
     }
 
-    pub fn optimize(&mut self, opt_mode: OptMode) {
-        if matches!(opt_mode, OptMode::None) {
-            return;
-        }
-
-        opt::prepare(self);
-
-        let debug_before = false;
-        let debug = false;
-
-        if debug_before || debug {
-            println!("\nMIR before optimisation:\n\n");
-            for (id, proc) in self.procs.iter() {
-                println!("PROCEDURE {:?}\n\n{}\n", id, proc.body.print());
-            }
-            println!("\n======\n");
-        }
+    /// Check the MIR for consistency (see [`opt::check`]), attributing any inconsistencies to the given stage of
+    /// compilation.
+    pub fn verify(&self, stage: &str) -> Result<(), opt::VerifyError> {
+        opt::check(self).map_err(|errors| opt::VerifyError { stage: stage.to_string(), errors })
+    }
 
-        for _ in 0..3 {
-            opt::FlattenSingleField::default().run(self, debug);
-            opt::ConstFold {
-                inline: !matches!(opt_mode, OptMode::Size),
-            }
-                .run(self, debug);
-            opt::RemoveUnusedBindings::default().run(self, debug);
-            opt::RemoveDeadProc::default().run(self, debug);
-        }
+    /// Optimise the MIR according to the configuration. See [`opt::optimize`].
+    pub fn optimize(&mut self, cfg: &OptConfig, writer: &mut dyn Write) -> Result<(), opt::OptError> {
+        opt::optimize(self, cfg, writer)
     }
 
     fn reachable_procs_from(&self, proc: ProcId, globals: &mut BTreeSet<ProcId>) {
@@ -76,6 +58,42 @@ impl Context {
     }
 }
 
+/// Determines which optimisation passes are run over the MIR, and how.
+#[derive(Clone, Debug)]
+pub struct OptConfig {
+    pub mode: OptMode,
+    /// The passes to run, in order. If empty, the passes that the optimisation mode implies are run.
+    pub passes: Vec<String>,
+    /// Run the passes repeatedly until the MIR stops changing, rather than a fixed number of times.
+    pub fixed_point: bool,
+    /// Share one procedure between the instantiations of a generic item that have identical bodies, before any other
+    /// passes are run.
+    pub share_instances: bool,
+    /// Check the MIR for consistency after lowering and after every pass.
+    pub verify: bool,
+    /// Print the MIR after each run of the passes with these names (or after `lowering`).
+    pub dump_after: Vec<String>,
+    /// Print the time taken by each pass and its effect on the size of the MIR.
+    pub stats: bool,
+    /// Print the number of instantiations of each generic item, and their size, before optimisation.
+    pub report_instances: bool,
+}
+
+impl OptConfig {
+    pub fn new(mode: OptMode) -> Self {
+        Self {
+            mode,
+            passes: Vec::new(),
+            fixed_point: false,
+            share_instances: false,
+            verify: false,
+            dump_after: Vec::new(),
+            stats: false,
+            report_instances: false,
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub enum OptMode {
     None,
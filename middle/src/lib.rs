@@ -7,16 +7,18 @@ pub mod opt;
 pub mod repr;
 pub mod lower;
 pub mod context;
+pub mod arbitrary;
 
 pub use crate::{
     error::Error,
-    opt::Pass,
+    opt::{Pass, OptError},
     proc::{ProcId, Proc, Procs},
     mir::{MirNode, Pat, Binding, Expr, Handler, Literal, Partial, Intrinsic, Local},
     repr::{Repr, Reprs, Prim, Data},
-    context::{Context, OptMode},
+    context::{Context, OptMode, OptConfig},
+    arbitrary::Rng,
 };
-pub use tao_analysis::Ident;
+pub use tao_analysis::{Ident, ConDataId};
 
 use tao_syntax::{
     Node,
@@ -39,7 +41,6 @@ use tao_analysis::{
     ConBinding,
     ConTy,
     ConTyId,
-    ConDataId,
     ConEffectId,
 };
 use hashbrown::{HashMap, HashSet};
@@ -48,6 +48,16 @@ impl Context {
         data
     }
 
+    pub fn lower_effect(&mut self, hir: &HirContext, con: &ConContext, eff: EffectId) -> EffectId {
+        if !self.reprs.effects.contains_key(&eff) {
+            let con_eff = con.get_effect(eff);
+            let send = self.lower_ty(hir, con, con_eff.send);
+            let recv = self.lower_ty(hir, con, con_eff.recv);
+            self.reprs.effects.insert(eff, (send, recv));
+        }
+        eff
+    }
+
     pub fn lower_ty(&mut self, hir: &HirContext, con: &ConContext, ty: ConTyId) -> Repr {
         fn prim_to_mir(prim: ty::Prim) -> repr::Prim {
             match prim {
@@ -142,12 +152,13 @@ impl Context {
             hir::Expr::Match(_, pred, arms) => {
                 let arms = arms
                     .iter()
-                    .map(|(binding, arm)| {
+                    .map(|(binding, guard, arm)| {
                         let old_stack = stack.len();
                         let binding = self.lower_binding(hir, con, binding, stack);
+                        let guard = guard.as_ref().map(|guard| self.lower_expr(hir, con, guard, stack));
                         let arm = self.lower_expr(hir, con, arm, stack);
                         stack.truncate(old_stack);
-                        (binding, arm)
+                        (binding, guard, arm)
                     })
                     .collect();
                 mir::Expr::Match(self.lower_expr(hir, con, pred, stack), arms)
@@ -208,16 +219,17 @@ impl Context {
                 let mut record = self.lower_expr(hir, con, record, stack);
                 // Perform indirections for field accesses
                 for _ in 0..indirections {
-                    let (data, variant_repr) = if let Repr::Data(data) = record.meta() {
-                        if let Repr::Sum(variants) = &self.reprs.get(*data).repr {
-                            (*data, variants[0].clone())
+                    let (data, sum_repr, variant_repr) = if let Repr::Data(data) = record.meta() {
+                        let repr = &self.reprs.get(*data).repr;
+                        if let Repr::Sum(variants) = repr {
+                            (*data, repr.clone(), variants[0].clone())
                         } else {
                             unreachable!()
                         }
                     } else {
                         unreachable!()
                     };
-                    record = MirNode::new(mir::Expr::AccessData(record, data), Repr::Data(data));
+                    record = MirNode::new(mir::Expr::AccessData(record, data), sum_repr);
                     record = MirNode::new(mir::Expr::AccessVariant(record, 0), variant_repr);
                 }
 
@@ -318,6 +330,19 @@ impl Context {
                         ])
                     },
                     hir::Intrinsic::Dispatch => panic!("Type dispatching should have occurred during concretisation!"),
+                    hir::Intrinsic::Host(name) => {
+                        let args = args
+                            .iter()
+                            .map(|arg| self.lower_expr(hir, con, arg, stack))
+                            .collect::<Vec<_>>();
+                        // Host functions aren't generic, so every call has the same signature
+                        if !self.reprs.hosts.contains_key(name) {
+                            let params = args.iter().map(|arg| arg.meta().clone()).collect();
+                            let output = self.lower_ty(hir, con, *con_expr.meta());
+                            self.reprs.hosts.insert(*name, (params, output));
+                        }
+                        mir::Expr::Intrinsic(mir::Intrinsic::Host(*name), args)
+                    },
                 }
             },
             hir::Expr::Update(record, fields) => {
@@ -335,18 +360,18 @@ impl Context {
                     // Perform indirections for field accesses, unwrapping until we reach the record
                     let mut datas = Vec::new();
                     for _ in 0..indirections {
-                        let (data, variant_repr) = if let Repr::Data(data) = mir_record.meta() {
+                        let (data, sum_repr, variant_repr) = if let Repr::Data(data) = mir_record.meta() {
                             let repr = &self.reprs.get(*data).repr;
                             if let Repr::Sum(variants) = repr {
                                 datas.push((*data, repr.clone()));
-                                (*data, variants[0].clone())
+                                (*data, repr.clone(), variants[0].clone())
                             } else {
                                 unreachable!()
                             }
                         } else {
                             unreachable!()
                         };
-                        mir_record = MirNode::new(mir::Expr::AccessData(mir_record, data), Repr::Data(data));
+                        mir_record = MirNode::new(mir::Expr::AccessData(mir_record, data), sum_repr);
                         mir_record = MirNode::new(mir::Expr::AccessVariant(mir_record, 0), variant_repr);
                     }
 
@@ -364,6 +389,40 @@ impl Context {
 
                 mir_record.into_inner()
             },
+            hir::Expr::Extend(record, fields) => {
+                let record_fields = record_fields(con, *record.meta());
+                let record = self.lower_expr(hir, con, record, stack);
+                let record_repr = record.meta().clone();
+                let record_local = Local::new();
+                // Fields not explicitly given come from the original record
+                let fields = sorted_fields(con, *con_expr.meta())
+                    .into_iter()
+                    .map(|name| match fields.iter().find(|(field, _)| ***field == name) {
+                        Some((_, field)) => self.lower_expr(hir, con, field, stack),
+                        None => MirNode::new(
+                            mir::Expr::Access(MirNode::new(mir::Expr::Local(record_local), record_repr.clone()), field_index(&record_fields, name)),
+                            self.lower_ty(hir, con, record_fields[&name]),
+                        ),
+                    })
+                    .collect();
+                let output = MirNode::new(mir::Expr::Tuple(fields), self.lower_ty(hir, con, *con_expr.meta()));
+                mir::Expr::Match(record, vec![(MirNode::new(mir::Binding::wildcard(record_local), record_repr), None, output)])
+            },
+            hir::Expr::Restrict(record, _) => {
+                let record_fields = record_fields(con, *record.meta());
+                let record = self.lower_expr(hir, con, record, stack);
+                let record_repr = record.meta().clone();
+                let record_local = Local::new();
+                let fields = sorted_fields(con, *con_expr.meta())
+                    .into_iter()
+                    .map(|name| MirNode::new(
+                        mir::Expr::Access(MirNode::new(mir::Expr::Local(record_local), record_repr.clone()), field_index(&record_fields, name)),
+                        self.lower_ty(hir, con, record_fields[&name]),
+                    ))
+                    .collect();
+                let output = MirNode::new(mir::Expr::Tuple(fields), self.lower_ty(hir, con, *con_expr.meta()));
+                mir::Expr::Match(record, vec![(MirNode::new(mir::Binding::wildcard(record_local), record_repr), None, output)])
+            },
             hir::Expr::Basin(effs, inner) => mir::Expr::Basin(effs.clone(), self.lower_expr(hir, con, inner, stack)),
             hir::Expr::Handle { expr, handlers } => {
                 let expr = self.lower_expr(hir, con, expr, stack);
@@ -379,7 +438,7 @@ impl Context {
                         let state_local = Local::new();
                         is_state = state.is_some();
                         mir::Handler {
-                            eff: *eff,
+                            eff: self.lower_effect(hir, con, *eff),
                             send: MirNode::new(send_local, self.lower_ty(hir, con, *send.meta())),
                             state: state.as_ref()
                                 .map(|state| MirNode::new(state_local, self.lower_ty(hir, con, *state.meta())))
@@ -429,10 +488,10 @@ impl Context {
                             unreachable!()
                         },
                         default_state_repr.clone(),
-                    ])), 1)
+                    ])), 0)
                 }
             },
-            hir::Expr::Suspend(eff, inner) => mir::Expr::Intrinsic(mir::Intrinsic::Suspend(*eff), vec![
+            hir::Expr::Suspend(eff, inner) => mir::Expr::Intrinsic(mir::Intrinsic::Suspend(self.lower_effect(hir, con, *eff)), vec![
                 self.lower_expr(hir, con, inner, stack),
             ]),
         };
@@ -440,3 +499,23 @@ impl Context {
         MirNode::new(expr, self.lower_ty(hir, con, *con_expr.meta()))
     }
 }
+
+fn record_fields(con: &ConContext, ty: ConTyId) -> BTreeMap<Ident, ConTyId> {
+    match con.get_ty(ty) {
+        ConTy::Record(fields) => fields.clone(),
+        _ => unreachable!("Extended or restricted type must be a record"),
+    }
+}
+
+// Records are represented as tuples with their fields ordered by name
+fn sorted_fields(con: &ConContext, ty: ConTyId) -> Vec<Ident> {
+    let mut fields = record_fields(con, ty).into_keys().collect::<Vec<_>>();
+    fields.sort_by_key(|name| name.as_ref());
+    fields
+}
+
+fn field_index(fields: &BTreeMap<Ident, ConTyId>, field: Ident) -> usize {
+    let mut names = fields.keys().copied().collect::<Vec<_>>();
+    names.sort_by_key(|name| name.as_ref());
+    names.iter().position(|name| *name == field).expect("No such field")
+}
@@ -172,6 +172,8 @@ pub enum Intrinsic {
     TrimList,
     Suspend(EffectId),
     Propagate(Vec<EffectId>),
+    // A function provided by the host application
+    Host(Ident),
 }
 
 #[derive(Clone, Debug)]
@@ -188,7 +190,7 @@ pub enum Pat {
 }
 
 // Uniquely refer to locals *without* shadowing
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Local(pub usize);
 
 impl Local {
@@ -336,8 +338,9 @@ pub enum Expr {
 
     Intrinsic(Intrinsic, Vec<MirNode<Self>>),
     /// SAFETY: All possible *inhabitant* (i.e: values that can actually be generated at run-time) predicate values
-    /// must be matched by at least one arm. If this is not the case, the compiler's output is undefined.
-    Match(MirNode<Self>, Vec<(MirNode<Binding>, MirNode<Self>)>),
+    /// must be matched by at least one arm. If this is not the case, the compiler's output is undefined. An arm with a
+    /// guard only matches if the guard, evaluated with the arm's bindings in scope, produces `True`.
+    Match(MirNode<Self>, Vec<(MirNode<Binding>, Option<MirNode<Self>>, MirNode<Self>)>),
 
     Func(MirNode<Local>, MirNode<Self>),
     Apply(MirNode<Self>, MirNode<Self>),
@@ -384,6 +387,13 @@ impl Expr {
         self.for_children(|expr| expr.required_globals_inner(globals));
     }
 
+    /// The number of expressions that make up this expression, including itself.
+    pub fn size(&self) -> usize {
+        let mut size = 1;
+        self.for_children(|expr| size += expr.size());
+        size
+    }
+
     pub fn refresh_locals(&mut self) {
         let required = self.required_locals(None);
         // debug_assert_eq!(required.len(), 0, "Cannot refresh locals for an expression\n\n{}\n\nthat captures (required = {:?})", self.print(), required);
@@ -399,11 +409,14 @@ impl Expr {
             },
             Expr::Match(pred, arms) => {
                 pred.refresh_locals_inner(stack);
-                for (binding, arm) in arms {
+                for (binding, guard, arm) in arms {
                     let old_stack = stack.len();
                     binding.visit_bindings(&mut |name, _| stack.push((name, Local::new())));
 
                     binding.refresh_locals_inner(stack);
+                    if let Some(guard) = guard {
+                        guard.refresh_locals_inner(stack);
+                    }
                     arm.refresh_locals_inner(stack);
                     stack.truncate(old_stack);
                 }
@@ -458,10 +471,13 @@ impl Expr {
                 .for_each(|arg| arg.required_locals_inner(stack, required)),
             Expr::Match(pred, arms) => {
                 pred.required_locals_inner(stack, required);
-                for (arm, body) in arms {
+                for (arm, guard, body) in arms {
                     let old_stack = stack.len();
                     stack.append(&mut arm.binding_names());
 
+                    if let Some(guard) = guard {
+                        guard.required_locals_inner(stack, required);
+                    }
                     body.required_locals_inner(stack, required);
 
                     stack.truncate(old_stack);
@@ -521,12 +537,13 @@ impl Expr {
             Expr::Local(local) => false,
             Expr::Global(_, _) => false,
             Expr::Intrinsic(Intrinsic::Propagate(_), _) => true,
+            Expr::Intrinsic(Intrinsic::Host(_), _) => true, // We can't know what the host does
             Expr::Intrinsic(_, args) => args
                 .iter()
                 .any(|arg| arg.may_have_effect()),
             Expr::Match(pred, arms) => pred.may_have_effect() || arms
                 .iter()
-                .any(|(_, body)| body.may_have_effect()),
+                .any(|(_, guard, body)| guard.as_ref().map_or(false, |guard| guard.may_have_effect()) || body.may_have_effect()),
             Expr::Func(_, _) => false,
             Expr::Go(next, body, init) => init.may_have_effect() || body.may_have_effect(),
             Expr::Apply(f, arg) => f.may_have_effect() || arg.may_have_effect(),
@@ -619,15 +636,20 @@ impl Expr {
                     Expr::Intrinsic(TrimList, args) => write!(f, "@trim_list({}, {})", DisplayExpr(&args[0], self.1, false), DisplayExpr(&args[1], self.1, false)),
                     Expr::Intrinsic(Suspend(_), args) => write!(f, "@suspend({})", DisplayExpr(&args[0], self.1, false)),
                     Expr::Intrinsic(Propagate(_), args) => write!(f, "{}!", DisplayExpr(&args[0], self.1, false)),
-                    Expr::Match(pred, arms) if arms.len() == 1 => {
-                        let (arm, body) = &arms[0];
+                    Expr::Intrinsic(Host(name), args) => write!(f, "@{}({})", name, args.iter().map(|arg| format!("{}", DisplayExpr(arg, self.1, false))).collect::<Vec<_>>().join(", ")),
+                    Expr::Match(pred, arms) if arms.len() == 1 && arms[0].1.is_none() => {
+                        let (arm, _, body) = &arms[0];
                         write!(f, "let {} = {} in\n{}", DisplayBinding(arm, self.1 + 1), DisplayExpr(pred, self.1, false), DisplayExpr(body, self.1, true))
                     },
                     Expr::Match(pred, arms) => {
                         write!(f, "match {} in", DisplayExpr(pred, self.1 + 1, false))?;
-                        for (i, (arm, body)) in arms.iter().enumerate() {
+                        for (i, (arm, guard, body)) in arms.iter().enumerate() {
                             let start = if i + 1 == arms.len() { '\\' } else { '|' };
-                            write!(f, "\n{}{} {} => {}", "    ".repeat(self.1 + 1), start, DisplayBinding(arm, self.1 + 1), DisplayExpr(body, self.1 + 1, false))?;
+                            write!(f, "\n{}{} {}", "    ".repeat(self.1 + 1), start, DisplayBinding(arm, self.1 + 1))?;
+                            if let Some(guard) = guard {
+                                write!(f, " if {}", DisplayExpr(guard, self.1 + 1, false))?;
+                            }
+                            write!(f, " => {}", DisplayExpr(body, self.1 + 1, false))?;
                         }
                         if arms.len() == 0 {
                             write!(f, " (no arms)")?;
@@ -0,0 +1,392 @@
+use super::*;
+use std::fmt;
+
+/// An inconsistency in the MIR of a procedure: a local used outside of its scope, or an expression or binding whose
+/// representation doesn't agree with its contents.
+#[derive(Debug)]
+pub struct MirError {
+    pub proc: ProcId,
+    pub msg: String,
+    /// The offending expression or binding, printed.
+    pub culprit: String,
+}
+
+/// The inconsistencies found in the MIR after a stage of compilation (lowering, or an optimisation pass).
+#[derive(Debug)]
+pub struct VerifyError {
+    pub stage: String,
+    pub errors: Vec<MirError>,
+}
+
+impl fmt::Display for VerifyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "MIR verification failed after {} ({} inconsistent procedures)", self.stage, self.errors.len())?;
+        for error in &self.errors {
+            write!(f, "\n\nin procedure {:?}: {}\n", error.proc, error.msg)?;
+            for line in error.culprit.lines() {
+                write!(f, "\n    {}", line)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+struct Failure {
+    msg: String,
+    culprit: String,
+}
+
+// Effect sets may be widened as effect objects are passed around, so only the outputs of effect objects are compared
+fn same_repr(a: &Repr, b: &Repr) -> bool {
+    match (a, b) {
+        (Repr::Effect(_, a), Repr::Effect(_, b)) => same_repr(a, b),
+        (Repr::List(a), Repr::List(b)) => same_repr(a, b),
+        (Repr::Tuple(xs), Repr::Tuple(ys))
+        | (Repr::Sum(xs), Repr::Sum(ys)) => xs.len() == ys.len() && xs
+            .iter()
+            .zip(ys)
+            .all(|(x, y)| same_repr(x, y)),
+        (Repr::Func(ai, ao), Repr::Func(bi, bo)) => same_repr(ai, bi) && same_repr(ao, bo),
+        (a, b) => a == b,
+    }
+}
+
+fn expect_repr(found: &Repr, expected: &Repr, what: &str) -> Result<(), String> {
+    if same_repr(found, expected) {
+        Ok(())
+    } else {
+        Err(format!("{} has representation {:?}, but {:?} was expected", what, found, expected))
+    }
+}
+
+struct Checker<'a> {
+    ctx: &'a Context,
+    // Locals in scope, along with their representations
+    stack: Vec<(Local, Repr)>,
+}
+
+impl<'a> Checker<'a> {
+    fn data_repr(&self, data: ConDataId) -> Result<&'a Repr, String> {
+        match self.ctx.reprs.datas.get(&data) {
+            Some(Some(data)) => Ok(&data.repr),
+            _ => Err(format!("data type {:?} is not defined", data)),
+        }
+    }
+
+    fn effect_reprs(&self, eff: EffectId) -> Result<&'a (Repr, Repr), String> {
+        self.ctx.reprs.effects
+            .get(&eff)
+            .ok_or_else(|| format!("effect {:?} is not defined", eff))
+    }
+
+    fn bool_repr(&self) -> Option<Repr> {
+        self.ctx.reprs.r#bool.map(Repr::Data)
+    }
+
+    fn check_litr(&self, litr: &Literal, repr: &Repr) -> Result<(), String> {
+        match (litr, repr) {
+            (Literal::Never, _) => Ok(()),
+            (Literal::Unknown(x), _) => *x,
+            (Literal::Nat(_), Repr::Prim(Prim::Nat))
+            | (Literal::Int(_), Repr::Prim(Prim::Int))
+            | (Literal::Real(_), Repr::Prim(Prim::Real))
+            | (Literal::Char(_), Repr::Prim(Prim::Char)) => Ok(()),
+            (Literal::Tuple(fields), Repr::Tuple(reprs)) if fields.len() == reprs.len() => fields
+                .iter()
+                .zip(reprs)
+                .try_for_each(|(field, repr)| self.check_litr(field, repr)),
+            (Literal::List(items), Repr::List(item_repr)) => items
+                .iter()
+                .try_for_each(|item| self.check_litr(item, item_repr)),
+            (Literal::Sum(variant, inner), Repr::Sum(variants)) if *variant < variants.len() => self.check_litr(inner, &variants[*variant]),
+            (Literal::Data(a, inner), Repr::Data(b)) if a == b => self.check_litr(inner, self.data_repr(*a)?),
+            (litr, repr) => Err(format!("literal {} cannot have representation {:?}", litr, repr)),
+        }
+    }
+
+    fn check_binding(&mut self, binding: &MirNode<Binding>, repr: &Repr) -> Result<(), Failure> {
+        let fail = |msg| Failure { msg, culprit: format!("{:?}", binding.pat) };
+
+        expect_repr(binding.meta(), repr, "binding").map_err(fail)?;
+
+        match (&binding.pat, repr) {
+            (Pat::Wildcard, _) => Ok(()),
+            (Pat::Literal(litr), _) => self.check_litr(litr, repr).map_err(fail),
+            (Pat::Single(inner), _) => self.check_binding(inner, repr),
+            (Pat::Add(lhs, _), Repr::Prim(Prim::Nat)) => self.check_binding(lhs, repr),
+            (Pat::Tuple(fields), Repr::Tuple(reprs)) if fields.len() == reprs.len() => fields
+                .iter()
+                .zip(reprs)
+                .try_for_each(|(field, repr)| self.check_binding(field, repr)),
+            (Pat::ListExact(items), Repr::List(item_repr)) => items
+                .iter()
+                .try_for_each(|item| self.check_binding(item, item_repr)),
+            (Pat::ListFront(items, tail), Repr::List(item_repr)) => {
+                items
+                    .iter()
+                    .try_for_each(|item| self.check_binding(item, item_repr))?;
+                tail
+                    .iter()
+                    .try_for_each(|tail| self.check_binding(tail, repr))
+            },
+            (Pat::Variant(variant, inner), Repr::Sum(variants)) if *variant < variants.len() => self.check_binding(inner, &variants[*variant]),
+            (Pat::Data(a, inner), Repr::Data(b)) if a == b => {
+                let data_repr = self.data_repr(*a).map_err(fail)?;
+                self.check_binding(inner, data_repr)
+            },
+            (_, repr) => Err(fail(format!("pattern cannot match values with representation {:?}", repr))),
+        }
+    }
+
+    fn check_intrinsic(&self, intrinsic: &Intrinsic, args: &[MirNode<Expr>], repr: &Repr) -> Result<(), String> {
+        use Intrinsic::*;
+
+        let nat = Repr::Prim(Prim::Nat);
+        let int = Repr::Prim(Prim::Int);
+        let real = Repr::Prim(Prim::Real);
+        let chr = Repr::Prim(Prim::Char);
+        let universe = Repr::Prim(Prim::Universe);
+        let string = Repr::List(Box::new(chr.clone()));
+        let first = args.first().map(|arg| arg.meta());
+
+        let (params, output) = match intrinsic {
+            NegNat => (vec![nat], Some(int)),
+            NegInt => (vec![int.clone()], Some(int)),
+            NegReal => (vec![real.clone()], Some(real)),
+            DisplayInt => (vec![int], Some(string)),
+            CodepointChar => (vec![chr], Some(nat)),
+            AddNat | MulNat => (vec![nat.clone(), nat.clone()], Some(nat)),
+            SubNat => (vec![nat.clone(), nat], Some(int)),
+            AddInt | SubInt | MulInt => (vec![int.clone(), int.clone()], Some(int)),
+            EqNat | NotEqNat | LessNat | MoreNat | LessEqNat | MoreEqNat => (vec![nat.clone(), nat], self.bool_repr()),
+            EqInt | NotEqInt | LessInt | MoreInt | LessEqInt | MoreEqInt => (vec![int.clone(), int], self.bool_repr()),
+            EqChar | NotEqChar => (vec![chr.clone(), chr], self.bool_repr()),
+            Join(item) => {
+                let list = Repr::List(Box::new(item.clone()));
+                (vec![list.clone(), list.clone()], Some(list))
+            },
+            MakeList(item) => (vec![item.clone(); args.len()], Some(Repr::List(Box::new(item.clone())))),
+            Print => (vec![universe.clone(), string], Some(universe)),
+            Input => (vec![universe.clone()], Some(Repr::Tuple(vec![universe, string]))),
+            UpdateField(idx) => match first {
+                Some(Repr::Tuple(fields)) if *idx < fields.len() => (vec![Repr::Tuple(fields.clone()), fields[*idx].clone()], Some(Repr::Tuple(fields.clone()))),
+                _ => return Err(format!("field {} of a value with representation {:?} cannot be updated", idx, first)),
+            },
+            LenList => match first {
+                Some(list @ Repr::List(_)) => (vec![list.clone()], Some(nat)),
+                _ => return Err(format!("the length of a value with representation {:?} cannot be taken", first)),
+            },
+            SkipList | TrimList => match first {
+                Some(list @ Repr::List(_)) => (vec![list.clone(), nat], Some(list.clone())),
+                _ => return Err(format!("a value with representation {:?} cannot be sliced", first)),
+            },
+            Propagate(_) => match first {
+                Some(eff @ Repr::Effect(_, out)) => (vec![eff.clone()], Some((**out).clone())),
+                _ => return Err(format!("a value with representation {:?} cannot be propagated", first)),
+            },
+            // Breaks into the debugger, leaving its argument as it is
+            Debug => match first {
+                Some(x) => (vec![x.clone()], Some(x.clone())),
+                None => return Err(format!("intrinsic {:?} takes 1 argument, but was given 0", intrinsic)),
+            },
+            Suspend(eff) => {
+                let (send, recv) = self.effect_reprs(*eff)?;
+                (vec![send.clone()], Some(recv.clone()))
+            },
+            Host(name) => match self.ctx.reprs.hosts.get(name) {
+                Some((params, output)) => (params.clone(), Some(output.clone())),
+                None => return Err(format!("host function {} is not declared", name)),
+            },
+        };
+
+        if args.len() != params.len() {
+            return Err(format!("intrinsic {:?} takes {} arguments, but was given {}", intrinsic, params.len(), args.len()));
+        }
+        for (i, (arg, param)) in args.iter().zip(&params).enumerate() {
+            expect_repr(arg.meta(), param, &format!("argument {} of intrinsic {:?}", i, intrinsic))?;
+        }
+        match output {
+            Some(output) => expect_repr(repr, &output, &format!("output of intrinsic {:?}", intrinsic)),
+            None => Ok(()),
+        }
+    }
+
+    fn check_expr(&mut self, expr: &MirNode<Expr>) -> Result<(), Failure> {
+        let repr = expr.meta();
+        let fail = |msg| Failure { msg, culprit: expr.print().to_string() };
+
+        match &**expr {
+            Expr::Undefined => Ok(()),
+            Expr::Literal(litr) => self.check_litr(litr, repr).map_err(fail),
+            Expr::Local(local) => match self.stack.iter().rev().find(|(name, _)| name == local) {
+                Some((_, local_repr)) => expect_repr(repr, local_repr, &format!("local ${}", local.0)).map_err(fail),
+                None => Err(fail(format!("local ${} is not in scope", local.0))),
+            },
+            Expr::Global(proc, _) => match self.ctx.procs.get(*proc) {
+                Some(proc_def) => expect_repr(repr, proc_def.body.meta(), &format!("reference to {:?}", proc)).map_err(fail),
+                None => Err(fail(format!("procedure {:?} does not exist", proc))),
+            },
+            Expr::Intrinsic(intrinsic, args) => {
+                args
+                    .iter()
+                    .try_for_each(|arg| self.check_expr(arg))?;
+                self.check_intrinsic(intrinsic, args, repr).map_err(fail)
+            },
+            Expr::Match(pred, arms) => {
+                self.check_expr(pred)?;
+                for (binding, guard, body) in arms {
+                    self.check_binding(binding, pred.meta())?;
+                    let old_len = self.stack.len();
+                    self.stack.append(&mut binding.bindings());
+                    if let Some(guard) = guard {
+                        self.check_expr(guard)?;
+                        if let Some(bool_repr) = self.bool_repr() {
+                            expect_repr(guard.meta(), &bool_repr, "guard").map_err(fail)?;
+                        }
+                    }
+                    self.check_expr(body)?;
+                    expect_repr(body.meta(), repr, "arm").map_err(fail)?;
+                    self.stack.truncate(old_len);
+                }
+                Ok(())
+            },
+            Expr::Func(arg, body) => {
+                let Repr::Func(i, o) = repr else {
+                    return Err(fail(format!("function has representation {:?}", repr)));
+                };
+                expect_repr(arg.meta(), i, "parameter").map_err(fail)?;
+                expect_repr(body.meta(), o, "function body").map_err(fail)?;
+                self.stack.push((**arg, arg.meta().clone()));
+                self.check_expr(body)?;
+                self.stack.pop();
+                Ok(())
+            },
+            Expr::Apply(f, arg) => {
+                self.check_expr(f)?;
+                self.check_expr(arg)?;
+                let Repr::Func(i, o) = f.meta() else {
+                    return Err(fail(format!("applied value has representation {:?}", f.meta())));
+                };
+                expect_repr(arg.meta(), i, "argument").map_err(fail)?;
+                expect_repr(repr, o, "application").map_err(fail)
+            },
+            Expr::Go(next, body, init) => {
+                self.check_expr(init)?;
+                expect_repr(next.meta(), init.meta(), "loop state").map_err(fail)?;
+                self.stack.push((**next, next.meta().clone()));
+                self.check_expr(body)?;
+                self.stack.pop();
+                Ok(())
+            },
+            Expr::Tuple(fields) => {
+                fields
+                    .iter()
+                    .try_for_each(|field| self.check_expr(field))?;
+                expect_repr(repr, &Repr::Tuple(fields.iter().map(|field| field.meta().clone()).collect()), "tuple")
+                    .map_err(fail)
+            },
+            Expr::Access(tuple, field) => {
+                self.check_expr(tuple)?;
+                match tuple.meta() {
+                    Repr::Tuple(fields) if *field < fields.len() => expect_repr(repr, &fields[*field], &format!("field {}", field))
+                        .map_err(fail),
+                    tuple_repr => Err(fail(format!("field {} of a value with representation {:?} cannot be accessed", field, tuple_repr))),
+                }
+            },
+            Expr::List(items) => {
+                let Repr::List(item_repr) = repr else {
+                    return Err(fail(format!("list has representation {:?}", repr)));
+                };
+                items
+                    .iter()
+                    .try_for_each(|item| {
+                        self.check_expr(item)?;
+                        expect_repr(item.meta(), item_repr, "list item").map_err(fail)
+                    })
+            },
+            Expr::Variant(variant, inner) => {
+                self.check_expr(inner)?;
+                match repr {
+                    Repr::Sum(variants) if *variant < variants.len() => expect_repr(inner.meta(), &variants[*variant], &format!("variant {}", variant))
+                        .map_err(fail),
+                    _ => Err(fail(format!("variant {} cannot have representation {:?}", variant, repr))),
+                }
+            },
+            Expr::AccessVariant(inner, variant) => {
+                self.check_expr(inner)?;
+                match inner.meta() {
+                    Repr::Sum(variants) if *variant < variants.len() => expect_repr(repr, &variants[*variant], &format!("variant {}", variant))
+                        .map_err(fail),
+                    inner_repr => Err(fail(format!("variant {} of a value with representation {:?} cannot be accessed", variant, inner_repr))),
+                }
+            },
+            Expr::Data(data, inner) => {
+                self.check_expr(inner)?;
+                expect_repr(repr, &Repr::Data(*data), "data").map_err(fail)?;
+                expect_repr(inner.meta(), self.data_repr(*data).map_err(fail)?, "inner value of data").map_err(fail)
+            },
+            Expr::AccessData(inner, data) => {
+                self.check_expr(inner)?;
+                expect_repr(inner.meta(), &Repr::Data(*data), "unwrapped data").map_err(fail)?;
+                expect_repr(repr, self.data_repr(*data).map_err(fail)?, "inner value of data").map_err(fail)
+            },
+            Expr::Basin(_, inner) => {
+                let Repr::Effect(_, out) = repr else {
+                    return Err(fail(format!("effect object has representation {:?}", repr)));
+                };
+                self.check_expr(inner)?;
+                expect_repr(inner.meta(), out, "output of effect object").map_err(fail)
+            },
+            Expr::Handle { expr: handled, handlers } => {
+                self.check_expr(handled)?;
+                // The handled expression pairs an effect object with the initial state of the handlers, and handling it
+                // pairs the output of the effect object with the final state
+                let (out, state_repr) = match handled.meta() {
+                    Repr::Tuple(fields) => match fields.as_slice() {
+                        [Repr::Effect(_, out), state] => (out, state),
+                        _ => return Err(fail(format!("handled value has representation {:?}", handled.meta()))),
+                    },
+                    handled_repr => return Err(fail(format!("handled value has representation {:?}", handled_repr))),
+                };
+                expect_repr(repr, &Repr::Tuple(vec![(**out).clone(), state_repr.clone()]), "output of handler")
+                    .map_err(fail)?;
+                for Handler { eff, send, state, recv } in handlers {
+                    // Each handler is given the value that was sent and the current state, and pairs the value to
+                    // resume with the new state
+                    let (send_repr, recv_repr) = self.effect_reprs(*eff).map_err(fail)?;
+                    expect_repr(send.meta(), send_repr, "sent value").map_err(fail)?;
+                    expect_repr(state.meta(), state_repr, "handler state").map_err(fail)?;
+                    let old_len = self.stack.len();
+                    self.stack.push((**send, send.meta().clone()));
+                    self.stack.push((**state, state.meta().clone()));
+                    self.check_expr(recv)?;
+                    expect_repr(recv.meta(), &Repr::Tuple(vec![recv_repr.clone(), state_repr.clone()]), "handler")
+                        .map_err(fail)?;
+                    self.stack.truncate(old_len);
+                }
+                Ok(())
+            },
+        }
+    }
+}
+
+/// Check the self-consistency of the MIR: that every local is used within its scope, and that the representation of
+/// every expression and binding agrees with its contents and with the representations of its neighbours.
+pub fn check(ctx: &Context) -> Result<(), Vec<MirError>> {
+    let errors = ctx.procs.procs
+        .iter()
+        .filter_map(|(id, proc)| {
+            let failure = match proc {
+                Some(proc) => Checker { ctx, stack: Vec::new() }.check_expr(&proc.body).err()?,
+                None => Failure { msg: format!("procedure was declared but never defined"), culprit: String::new() },
+            };
+            Some(MirError { proc: *id, msg: failure.msg, culprit: failure.culprit })
+        })
+        .collect::<Vec<_>>();
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
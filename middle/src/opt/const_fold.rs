@@ -120,15 +120,38 @@ impl ConstFold {
                 let mut output = Partial::Never;
                 arms
                     // Remove arms that cannot possibly match
-                    .drain_filter(|(binding, arm)| {
+                    .drain_filter(|(binding, guard, arm)| {
                         let old_locals = locals.len();
                         let cull = if self.extract(ctx, binding, &pred, locals) {
-                            let arm_output = self.eval(ctx, arm, locals);
+                            let guard_holds = match guard {
+                                Some(guard) => match self.eval(ctx, guard, locals) {
+                                    Partial::Data(_, inner) => match *inner {
+                                        Partial::Sum(tag, _) => Some(tag > 0),
+                                        _ => None,
+                                    },
+                                    Partial::Sum(tag, _) => Some(tag > 0),
+                                    _ => None,
+                                },
+                                None => Some(true),
+                            };
 
-                            // Combine outputs together in an attempt to unify their values
-                            output = std::mem::replace(&mut output, Partial::Unknown(None)).or(arm_output);
+                            if guard_holds == Some(false) {
+                                // Guard can never succeed, so the arm can be culled. Guards that have effects must
+                                // still be evaluated, but the arm body can never be reached so it adds no output.
+                                guard.as_ref().map_or(true, |guard| !guard.may_have_effect())
+                            } else {
+                                // Guards that always succeed can be removed entirely
+                                if guard_holds == Some(true) && guard.as_ref().map_or(false, |guard| !guard.may_have_effect()) {
+                                    *guard = None;
+                                }
 
-                            false
+                                let arm_output = self.eval(ctx, arm, locals);
+
+                                // Combine outputs together in an attempt to unify their values
+                                output = std::mem::replace(&mut output, Partial::Unknown(None)).or(arm_output);
+
+                                false
+                            }
                         } else {
                             // Arm could not possibly match, cull it
                             true
@@ -176,7 +199,7 @@ impl ConstFold {
                 if let Expr::Func(param, body) = &mut **f {
                     *expr = Expr::Match(
                         arg.clone(),
-                        vec![(MirNode::new(Binding::wildcard(**param), arg.meta().clone()), body.clone())],
+                        vec![(MirNode::new(Binding::wildcard(**param), arg.meta().clone()), None, body.clone())],
                     );
                     return self.eval(ctx, expr, locals)
                 } else {
@@ -291,26 +314,49 @@ impl Intrinsic {
         );
 
         match self {
-            Intrinsic::NegNat => op!(Nat(x) => Int(-(*x as i64))),
-            Intrinsic::NegInt => op!(Int(x) => Int(-*x)),
+            Intrinsic::Debug => Partial::Unknown(None),
+            Intrinsic::MakeList(_) => List(args.to_vec()),
+            // Arithmetic that overflows is left until run-time
+            Intrinsic::NegNat => op!(Nat(x) => i64::try_from(*x).ok().and_then(i64::checked_neg).map_or(Unknown(None), Int)),
+            Intrinsic::NegInt => op!(Int(x) => x.checked_neg().map_or(Unknown(None), Int)),
+            Intrinsic::NegReal => op!(Real(x) => Real(-*x)),
             Intrinsic::DisplayInt => op!(Int(x) => List(x.to_string().chars().map(Const::Char).collect())),
             Intrinsic::CodepointChar => op!(Char(c) => Nat(*c as u64)),
-            Intrinsic::AddNat => op!(Nat(x), Nat(y) => Nat(x + y)),
-            Intrinsic::SubNat => op!(Nat(x), Nat(y) => Int(*x as i64 - *y as i64)),
-            Intrinsic::MulNat => op!(Nat(x), Nat(y) => Nat(x * y)),
+            Intrinsic::AddNat => op!(Nat(x), Nat(y) => x.checked_add(*y).map_or(Unknown(None), Nat)),
+            Intrinsic::SubNat => op!(Nat(x), Nat(y) => i64::try_from(*x)
+                .ok()
+                .zip(i64::try_from(*y).ok())
+                .and_then(|(x, y)| x.checked_sub(y))
+                .map_or(Unknown(None), Int)),
+            Intrinsic::MulNat => op!(Nat(x), Nat(y) => x.checked_mul(*y).map_or(Unknown(None), Nat)),
             Intrinsic::LessNat => op!(Nat(x), Nat(y) => r#bool(x < y)),
             Intrinsic::MoreNat => op!(Nat(x), Nat(y) => r#bool(x > y)),
             Intrinsic::MoreEqNat => op!(Nat(x), Nat(y) => r#bool(x >= y)),
-            Intrinsic::AddInt => op!(Int(x), Int(y) => Int(x + y)),
-            Intrinsic::SubInt => op!(Int(x), Int(y) => Int(x - y)),
-            Intrinsic::MulInt => op!(Int(x), Int(y) => Int(x * y)),
+            Intrinsic::AddInt => op!(Int(x), Int(y) => x.checked_add(*y).map_or(Unknown(None), Int)),
+            Intrinsic::SubInt => op!(Int(x), Int(y) => x.checked_sub(*y).map_or(Unknown(None), Int)),
+            Intrinsic::MulInt => op!(Int(x), Int(y) => x.checked_mul(*y).map_or(Unknown(None), Int)),
+            Intrinsic::LessInt => op!(Int(x), Int(y) => r#bool(x < y)),
+            Intrinsic::MoreInt => op!(Int(x), Int(y) => r#bool(x > y)),
+            Intrinsic::LessEqNat => op!(Nat(x), Nat(y) => r#bool(x <= y)),
+            Intrinsic::LessEqInt => op!(Int(x), Int(y) => r#bool(x <= y)),
+            Intrinsic::MoreEqInt => op!(Int(x), Int(y) => r#bool(x >= y)),
             Intrinsic::EqNat => op!(Nat(x), Nat(y) => r#bool(x == y)),
+            Intrinsic::EqInt => op!(Int(x), Int(y) => r#bool(x == y)),
             Intrinsic::EqChar => op!(Char(x), Char(y) => r#bool(x == y)),
-            Intrinsic::EqNat => op!(Nat(x), Nat(y) => r#bool(x == y)),
+            Intrinsic::NotEqNat => op!(Nat(x), Nat(y) => r#bool(x != y)),
+            Intrinsic::NotEqInt => op!(Int(x), Int(y) => r#bool(x != y)),
+            Intrinsic::NotEqChar => op!(Char(x), Char(y) => r#bool(x != y)),
             Intrinsic::Join(_) => op!(List(xs), List(ys) => List(xs.iter().chain(ys).cloned().collect())),
             Intrinsic::Print => Partial::Unknown(None),
             Intrinsic::Input => Partial::Unknown(None),
-            Intrinsic::UpdateField(idx) => Partial::Unknown(None), // TODO
+            Intrinsic::UpdateField(idx) => match &args[0] {
+                Tuple(fields) => {
+                    let mut fields = fields.clone();
+                    fields[*idx] = args[1].clone();
+                    Tuple(fields)
+                },
+                _ => Unknown(None),
+            },
             Intrinsic::LenList => op!(List(xs) => Nat(xs.len() as u64)),
             Intrinsic::SkipList => op!(List(xs), Nat(i) => List(xs.clone().split_off((*i as usize).min(xs.len())))),
             Intrinsic::TrimList => op!(List(xs), Nat(i) => List({
@@ -320,12 +366,14 @@ impl Intrinsic {
             })),
             Intrinsic::Suspend(_) => Partial::Unknown(None),
             Intrinsic::Propagate(_) => Partial::Unknown(None),
-            i => todo!("{:?}", i),
+            Intrinsic::Host(_) => Partial::Unknown(None),
         }
     }
 }
 
 impl Pass for ConstFold {
+    fn name(&self) -> &'static str { "const-fold" }
+
     fn apply(&mut self, ctx: &mut Context) {
         let proc_bodies = ctx.procs
             .iter()
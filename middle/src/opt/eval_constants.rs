@@ -0,0 +1,327 @@
+use super::*;
+use crate::mir::Const;
+use std::rc::Rc;
+
+/// Evaluates `def`s whose values aren't functions (lookup tables, for example) at compile-time, replacing their bodies
+/// with the literals that they produce.
+///
+/// Evaluation is performed by a small MIR interpreter. A definition is only replaced if evaluating it couldn't change
+/// the behaviour of the program, so the interpreter gives up on definitions that perform effects, that evaluate
+/// undefined expressions, that take too long (or recurse too deeply) to evaluate, or that produce values too large to
+/// be worth storing in the program.
+#[derive(Default)]
+pub struct EvalConstants;
+
+// The number of expressions that may be evaluated to find the value of a definition before giving up, so that
+// compilation still finishes for definitions that never do
+const FUEL: usize = 100_000;
+// The maximum depth of nested expressions (including those of the functions being applied) that may be evaluated, so
+// that deep recursion gives up rather than overflowing the stack
+const MAX_DEPTH: usize = 2048;
+// The number of parts (see `size`) of the values that may be copied to find the value of a definition. Copying a value
+// is the only way to produce one larger than the expressions that construct it (joining a list to itself doubles its
+// size, for example), so this bounds the size of the values produced.
+const MAX_SIZE: usize = 100_000;
+
+// A function value: its parameter, body, and the values of the locals that it captures
+struct Closure<'a> {
+    arg: Local,
+    body: &'a MirNode<Expr>,
+    captures: Vec<(Local, Value<'a>)>,
+}
+
+// Values are the same as those that constant folding infers, except that functions are known too
+type Value<'a> = Const<Rc<Closure<'a>>>;
+
+fn to_partial(value: &Value) -> Partial {
+    match value {
+        Const::Never => Partial::Never,
+        Const::Unknown(_) => Partial::Unknown(None),
+        Const::Nat(x) => Partial::Nat(*x),
+        Const::Int(x) => Partial::Int(*x),
+        Const::Real(x) => Partial::Real(*x),
+        Const::Char(c) => Partial::Char(*c),
+        Const::Tuple(fields) => Partial::Tuple(fields.iter().map(to_partial).collect()),
+        Const::List(items) => Partial::List(items.iter().map(to_partial).collect()),
+        Const::Sum(variant, inner) => Partial::Sum(*variant, Box::new(to_partial(inner))),
+        Const::Data(data, inner) => Partial::Data(*data, Box::new(to_partial(inner))),
+    }
+}
+
+// Fails if any part of the partial value is unknown
+fn from_partial<'a>(partial: &Partial) -> Option<Value<'a>> {
+    Some(match partial {
+        Partial::Never => Const::Never,
+        Partial::Unknown(_) => return None,
+        Partial::Nat(x) => Const::Nat(*x),
+        Partial::Int(x) => Const::Int(*x),
+        Partial::Real(x) => Const::Real(*x),
+        Partial::Char(c) => Const::Char(*c),
+        Partial::Tuple(fields) => Const::Tuple(fields.iter().map(from_partial).collect::<Option<_>>()?),
+        Partial::List(items) => Const::List(items.iter().map(from_partial).collect::<Option<_>>()?),
+        Partial::Sum(variant, inner) => Const::Sum(*variant, Box::new(from_partial(inner)?)),
+        Partial::Data(data, inner) => Const::Data(*data, Box::new(from_partial(inner)?)),
+    })
+}
+
+// The number of parts that make up the value, or some number greater than the limit if there are more than that
+fn size<U>(value: &Const<U>, limit: usize) -> usize {
+    match value {
+        Const::Tuple(xs) | Const::List(xs) => xs
+            .iter()
+            .try_fold(1, |n, x| {
+                let n = n + size(x, limit);
+                if n > limit { Err(n) } else { Ok(n) }
+            })
+            .unwrap_or_else(|n| n),
+        Const::Sum(_, inner) | Const::Data(_, inner) => 1 + size(inner, limit),
+        _ => 1,
+    }
+}
+
+fn lookup<'a>(locals: &[(Local, Value<'a>)], local: Local) -> Value<'a> {
+    locals
+        .iter()
+        .rev()
+        .find(|(name, _)| *name == local)
+        .expect("local was not found on stack")
+        .1
+        .clone()
+}
+
+// Returns whether the value matches the binding, adding the locals that it binds if so. `None` is returned if the
+// value doesn't have the shape that the binding expects.
+fn bind<'a>(binding: &MirNode<Binding>, value: &Value<'a>, locals: &mut Vec<(Local, Value<'a>)>) -> Option<bool> {
+    if let Some(name) = binding.name {
+        locals.push((name, value.clone()));
+    }
+
+    Some(match (&binding.pat, value) {
+        (Pat::Wildcard, _) => true,
+        (Pat::Literal(litr), value) => to_partial(value) == litr.to_partial(),
+        (Pat::Single(inner), value) => bind(inner, value, locals)?,
+        (Pat::Add(inner, n), Const::Nat(x)) => *x >= *n && bind(inner, &Const::Nat(*x - *n), locals)?,
+        (Pat::Tuple(xs), Const::Tuple(ys)) => bind_all(xs, ys, locals)?,
+        (Pat::ListExact(xs), Const::List(ys)) => xs.len() == ys.len() && bind_all(xs, ys, locals)?,
+        (Pat::ListFront(xs, tail), Const::List(ys)) => ys.len() >= xs.len()
+            && bind_all(xs, ys, locals)?
+            && match tail {
+                Some(tail) => bind(tail, &Const::List(ys[xs.len()..].to_vec()), locals)?,
+                None => true,
+            },
+        (Pat::Variant(variant, inner), Const::Sum(tag, y)) => variant == tag && bind(inner, y, locals)?,
+        (Pat::Data(a, inner), Const::Data(b, y)) => {
+            debug_assert_eq!(a, b);
+            bind(inner, y, locals)?
+        },
+        _ => return None,
+    })
+}
+
+fn bind_all<'a>(bindings: &[MirNode<Binding>], values: &[Value<'a>], locals: &mut Vec<(Local, Value<'a>)>) -> Option<bool> {
+    debug_assert_eq!(bindings.len(), values.len());
+    for (binding, value) in bindings.iter().zip(values) {
+        if !bind(binding, value, locals)? {
+            return Some(false);
+        }
+    }
+    Some(true)
+}
+
+struct Interpreter<'a> {
+    ctx: &'a Context,
+    // The values of the procedures evaluated so far
+    globals: HashMap<ProcId, Value<'a>>,
+    fuel: usize,
+    depth: usize,
+    // The number of parts of values that may still be copied
+    size: usize,
+}
+
+impl<'a> Interpreter<'a> {
+    // Account for a copy of the value, failing if too much has been copied already
+    fn copy(&mut self, value: Value<'a>) -> Option<Value<'a>> {
+        self.size = self.size.checked_sub(size(&value, self.size))?;
+        Some(value)
+    }
+
+    fn global(&mut self, proc: ProcId) -> Option<Value<'a>> {
+        if let Some(value) = self.globals.get(&proc) {
+            return Some(value.clone());
+        }
+
+        let ctx = self.ctx;
+        let value = self.eval(&ctx.procs.get(proc).unwrap().body, &mut Vec::new())?;
+        self.globals.insert(proc, value.clone());
+        Some(value)
+    }
+
+    fn apply(&mut self, f: Value<'a>, arg: Value<'a>) -> Option<Value<'a>> {
+        let Const::Unknown(closure) = f else { return None };
+        let mut locals = closure.captures.clone();
+        locals.push((closure.arg, arg));
+        self.eval(closure.body, &mut locals)
+    }
+
+    // Returns `None` if the expression can't be evaluated at compile-time
+    fn eval(&mut self, expr: &'a MirNode<Expr>, locals: &mut Vec<(Local, Value<'a>)>) -> Option<Value<'a>> {
+        if self.fuel == 0 || self.depth == MAX_DEPTH {
+            return None;
+        }
+        self.fuel -= 1;
+        self.depth += 1;
+        let value = self.eval_inner(expr, locals);
+        self.depth -= 1;
+        value
+    }
+
+    fn eval_inner(&mut self, expr: &'a MirNode<Expr>, locals: &mut Vec<(Local, Value<'a>)>) -> Option<Value<'a>> {
+        Some(match &**expr {
+            Expr::Undefined => return None,
+            Expr::Literal(litr) => from_partial(&litr.to_partial())?,
+            Expr::Local(local) => self.copy(lookup(locals, *local))?,
+            Expr::Global(proc, _) => {
+                let value = self.global(*proc)?;
+                self.copy(value)?
+            },
+            Expr::Intrinsic(intrinsic, args) => {
+                match intrinsic {
+                    // Intrinsics that interact with the outside world must be left until run-time
+                    Intrinsic::Debug
+                    | Intrinsic::Print
+                    | Intrinsic::Input
+                    | Intrinsic::Suspend(_)
+                    | Intrinsic::Propagate(_)
+                    | Intrinsic::Host(_) => return None,
+                    _ => {},
+                }
+                let args = args
+                    .iter()
+                    .map(|arg| self.eval(arg, locals).map(|arg| to_partial(&arg)))
+                    .collect::<Option<Vec<_>>>()?;
+                self.copy(from_partial(&intrinsic.eval(self.ctx, &args))?)?
+            },
+            Expr::Match(pred, arms) => {
+                let pred = self.eval(pred, locals)?;
+                for (binding, guard, body) in arms {
+                    let old_len = locals.len();
+                    let matches = bind(binding, &pred, locals)? && match guard {
+                        Some(guard) => match self.eval(guard, locals)? {
+                            Const::Data(_, inner) => match *inner {
+                                Const::Sum(tag, _) => tag > 0,
+                                _ => return None,
+                            },
+                            Const::Sum(tag, _) => tag > 0,
+                            _ => return None,
+                        },
+                        None => true,
+                    };
+                    if matches {
+                        let output = self.eval(body, locals);
+                        locals.truncate(old_len);
+                        return output;
+                    }
+                    locals.truncate(old_len);
+                }
+                // No arm matched, so evaluation is undefined
+                return None;
+            },
+            Expr::Func(arg, body) => Const::Unknown(Rc::new(Closure {
+                arg: **arg,
+                body,
+                captures: body
+                    .required_locals(Some(**arg))
+                    .into_iter()
+                    .map(|local| (local, lookup(locals, local)))
+                    .collect(),
+            })),
+            Expr::Apply(f, arg) => {
+                let f = self.eval(f, locals)?;
+                let arg = self.eval(arg, locals)?;
+                self.apply(f, arg)?
+            },
+            Expr::Go(next, body, init) => {
+                let mut value = self.eval(init, locals)?;
+                loop {
+                    locals.push((**next, value));
+                    let output = self.eval(body, locals);
+                    locals.pop();
+                    // The body produces the next value of the loop's argument, or the loop's result
+                    let output = match output? {
+                        Const::Data(_, inner) => *inner,
+                        output => output,
+                    };
+                    match output {
+                        Const::Sum(0, arg) => value = *arg,
+                        Const::Sum(1, done) => break *done,
+                        _ => return None,
+                    }
+                }
+            },
+            Expr::Tuple(fields) => Const::Tuple(fields
+                .iter()
+                .map(|field| self.eval(field, locals))
+                .collect::<Option<_>>()?),
+            Expr::Access(tuple, field) => match self.eval(tuple, locals)? {
+                Const::Tuple(mut fields) => fields.swap_remove(*field),
+                _ => return None,
+            },
+            Expr::List(items) => Const::List(items
+                .iter()
+                .map(|item| self.eval(item, locals))
+                .collect::<Option<_>>()?),
+            Expr::Variant(variant, inner) => Const::Sum(*variant, Box::new(self.eval(inner, locals)?)),
+            Expr::AccessVariant(inner, variant) => match self.eval(inner, locals)? {
+                Const::Sum(tag, inner) if tag == *variant => *inner,
+                _ => return None,
+            },
+            Expr::Data(data, inner) => Const::Data(*data, Box::new(self.eval(inner, locals)?)),
+            Expr::AccessData(inner, _) => match self.eval(inner, locals)? {
+                Const::Data(_, inner) => *inner,
+                _ => return None,
+            },
+            // Effect objects and handlers only make sense at run-time
+            Expr::Basin(_, _) | Expr::Handle { .. } => return None,
+        })
+    }
+}
+
+// Only definitions that aren't functions are evaluated: a function's body can't be evaluated without its argument.
+// Procedures are always closed, so no other definitions need be excluded.
+fn is_constant(id: ProcId, body: &MirNode<Expr>) -> bool {
+    matches!(id.proc(), ConProc::Def(_, _))
+        && !matches!(body.meta(), Repr::Func(_, _))
+        && !matches!(&**body, Expr::Literal(_))
+        && !body.may_have_effect()
+}
+
+impl Pass for EvalConstants {
+    fn name(&self) -> &'static str { "eval-constants" }
+
+    fn apply(&mut self, ctx: &mut Context) {
+        let constants = {
+            let mut interp = Interpreter {
+                ctx: &*ctx,
+                globals: HashMap::new(),
+                fuel: 0,
+                depth: 0,
+                size: 0,
+            };
+            // Definitions that nothing refers to will be removed, so there's no need to spend time evaluating them
+            ctx.reachable_procs()
+                .into_iter()
+                .filter(|id| is_constant(*id, &ctx.procs.get(*id).unwrap().body))
+                .filter_map(|id| {
+                    interp.fuel = FUEL;
+                    interp.size = MAX_SIZE;
+                    // Values that contain functions have no literal
+                    Some((id, to_partial(&interp.global(id)?).to_literal()?))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        for (id, litr) in constants {
+            *ctx.procs.get_mut(id).unwrap().body = Expr::Literal(litr);
+        }
+    }
+}
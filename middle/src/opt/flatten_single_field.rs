@@ -5,6 +5,8 @@ use super::*;
 pub struct FlattenSingleField;
 
 impl Pass for FlattenSingleField {
+    fn name(&self) -> &'static str { "flatten-single-field" }
+
     fn apply(&mut self, ctx: &mut Context) {
         // TODO: Implement
         /*
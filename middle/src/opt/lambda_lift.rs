@@ -0,0 +1,177 @@
+use super::*;
+use std::cell::Cell;
+
+// Specialising a function can lift new lambdas out of its body, which can in turn permit further specialisation, so
+// the number of specialisations created by each run of the pass is limited.
+const MAX_SPECIALIZATIONS: usize = 256;
+
+/// Lifts lambdas that capture nothing out of the calls that they're passed to, turning them into procedures of their
+/// own, and specialises the functions being called to the procedures that they're passed.
+///
+/// Higher-order functions (like `map` or `fold`) are usually recursive, so they can't be inlined. Every call to one
+/// must therefore allocate a closure for its function argument, and every call that it makes to that argument is an
+/// indirect one. Once specialised to a known procedure, a function calls the procedure directly, and the call may
+/// then be inlined by [`ConstFold`].
+#[derive(Default)]
+pub struct LambdaLift;
+
+// The number of parameters that a curried function takes
+fn params(expr: &Expr) -> usize {
+    match expr {
+        Expr::Func(_, body) => 1 + params(body),
+        _ => 0,
+    }
+}
+
+// The function at the head of a chain of applications, and the number of arguments that it is applied to
+fn head_of(expr: &Expr) -> (&Expr, usize) {
+    match expr {
+        Expr::Apply(f, _) => {
+            let (head, args) = head_of(f);
+            (head, args + 1)
+        },
+        _ => (expr, 0),
+    }
+}
+
+// Remove the Nth parameter from the type of a curried function
+fn remove_param_repr(repr: &mut Repr, idx: usize) {
+    if let Repr::Func(_, o) = repr {
+        if idx == 0 {
+            *repr = (**o).clone();
+        } else {
+            remove_param_repr(o, idx - 1);
+        }
+    }
+}
+
+// Substitute an argument for the Nth parameter of a curried function, removing the parameter
+fn remove_param(func: &mut MirNode<Expr>, idx: usize, arg: &Expr) {
+    remove_param_repr(func.meta_mut(), idx);
+    match &mut **func {
+        Expr::Func(_, body) if idx > 0 => remove_param(body, idx - 1, arg),
+        Expr::Func(param, body) => {
+            body.inline_local(**param, arg);
+            let body = body.clone();
+            *func = body;
+        },
+        _ => unreachable!("Function has too few parameters"),
+    }
+}
+
+// Replace the function at the head of a chain of applications with one that doesn't take the Nth parameter, where N
+// is counted from the first argument not yet applied by the outermost application
+fn remove_arg(expr: &mut MirNode<Expr>, idx: usize, head: Expr) {
+    remove_param_repr(expr.meta_mut(), idx);
+    match &mut **expr {
+        Expr::Apply(f, _) => remove_arg(f, idx + 1, head),
+        inner => *inner = head,
+    }
+}
+
+// A function argument that refers to a known procedure
+enum Known<'a> {
+    Global(ProcId, mir::GlobalFlags),
+    // A lambda that captures nothing, which becomes a procedure once lifted
+    Lambda(&'a MirNode<Expr>),
+}
+
+impl LambdaLift {
+    // Determine whether the argument is a known function (a global that is a function, or a lambda that captures
+    // nothing).
+    fn known_func<'a>(ctx: &Context, arg: &'a MirNode<Expr>) -> Option<Known<'a>> {
+        match &**arg {
+            Expr::Global(proc, flags) => match ctx.procs.get(*proc) {
+                Some(proc_def) if params(&proc_def.body) > 0 => Some(Known::Global(*proc, flags.get())),
+                _ => None,
+            },
+            Expr::Func(_, _) if arg.required_locals(None).is_empty() => Some(Known::Lambda(arg)),
+            _ => None,
+        }
+    }
+
+    // Find the procedure that the function procedure `func` becomes when its Nth parameter is specialised to the
+    // argument, creating it if it doesn't already exist.
+    fn specialize(
+        &mut self,
+        ctx: &mut Context,
+        parent: ProcId,
+        func: ProcId,
+        idx: usize,
+        arg: &MirNode<Expr>,
+        todo: &mut Vec<ProcId>,
+        budget: &mut usize,
+    ) -> Option<ProcId> {
+        if ctx.procs.get(func).map_or(true, |proc| params(&proc.body) <= idx) {
+            return None;
+        }
+
+        let (known, known_flags) = match Self::known_func(ctx, arg)? {
+            Known::Global(proc, flags) => (proc, flags),
+            // Lifted lambdas always have new identifiers, so always need a new specialisation. Lifting a lambda
+            // without one would leave behind a procedure that nothing refers to, and the lambda would just be lifted
+            // again by the next run of the pass.
+            Known::Lambda(_) if *budget == 0 => return None,
+            Known::Lambda(lambda) => {
+                let lifted = ctx.procs.new_lifted(parent);
+                ctx.procs.declare(lifted);
+                ctx.procs.define(lifted, Proc { body: lambda.clone() });
+                (lifted, mir::GlobalFlags::default())
+            },
+        };
+        let specialized = ProcId::specialized(func, idx, known);
+
+        if !ctx.procs.is_declared(specialized) {
+            if *budget == 0 {
+                return None;
+            }
+            *budget -= 1;
+
+            let mut body = ctx.procs.get(func).unwrap().body.clone();
+            body.refresh_locals();
+            remove_param(&mut body, idx, &Expr::Global(known, Cell::new(known_flags)));
+
+            ctx.procs.declare(specialized);
+            ctx.procs.define(specialized, Proc { body });
+            // The body of the specialised function may pass the argument on to other functions in turn
+            todo.push(specialized);
+        }
+
+        Some(specialized)
+    }
+
+    fn visit(&mut self, ctx: &mut Context, parent: ProcId, expr: &mut Expr, todo: &mut Vec<ProcId>, budget: &mut usize) {
+        // Inner applications are visited first, so that each argument of a curried function is specialised in turn
+        expr.for_children_mut(|expr| self.visit(ctx, parent, expr, todo, budget));
+
+        if let Expr::Apply(f, arg) = expr {
+            if let (Expr::Global(func, flags), idx) = head_of(f) {
+                let flags = flags.get();
+                if let Some(specialized) = self.specialize(ctx, parent, *func, idx, arg, todo, budget) {
+                    let Expr::Apply(mut f, _) = std::mem::replace(expr, Expr::Undefined) else { unreachable!() };
+                    // A specialisation of a recursive function is recursive too, so it inherits the function's flags
+                    remove_arg(&mut f, 0, Expr::Global(specialized, Cell::new(flags)));
+                    *expr = f.into_inner();
+                }
+            }
+        }
+    }
+}
+
+impl Pass for LambdaLift {
+    fn name(&self) -> &'static str { "lambda-lift" }
+
+    fn apply(&mut self, ctx: &mut Context) {
+        let mut todo = ctx.procs
+            .iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        let mut budget = MAX_SPECIALIZATIONS;
+
+        while let Some(id) = todo.pop() {
+            let mut body = ctx.procs.get(id).unwrap().body.clone();
+            self.visit(ctx, id, &mut body, &mut todo, &mut budget);
+            ctx.procs.get_mut(id).unwrap().body = body;
+        }
+    }
+}
@@ -1,32 +1,33 @@
 use super::*;
-use std::any::{Any, type_name};
+use std::any::Any;
 
+mod check;
 mod const_fold;
+mod eval_constants;
 mod flatten_single_field;
+mod lambda_lift;
+mod pipeline;
 mod remove_dead_proc;
 mod remove_unused_bindings;
+mod share_instances;
 
 pub use {
+    check::{check, MirError, VerifyError},
     const_fold::ConstFold,
+    eval_constants::EvalConstants,
     flatten_single_field::FlattenSingleField,
+    lambda_lift::LambdaLift,
+    pipeline::{optimize, OptError},
     remove_dead_proc::RemoveDeadProc,
     remove_unused_bindings::RemoveUnusedBindings,
+    share_instances::{ShareInstances, origin},
 };
 
 pub trait Pass: Any {
-    fn apply(&mut self, ctx: &mut Context);
-
-    fn run(&mut self, ctx: &mut Context, debug: bool) {
-        self.apply(ctx);
+    /// The name of the pass, by which it may be given to `--passes` or `--debug mir-after=<pass>`.
+    fn name(&self) -> &'static str;
 
-        if debug {
-            println!("\nMIR after {}:\n\n", type_name::<Self>());
-            for (id, proc) in ctx.procs.iter() {
-                println!("PROCEDURE {:?}\n\n{}\n", id, proc.body.print());
-            }
-            opt::check(ctx);
-        }
-    }
+    fn apply(&mut self, ctx: &mut Context);
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -212,7 +213,10 @@ impl Expr {
                 .for_each(|item| f(item)),
             Expr::Match(pred, arms) => {
                 f(pred);
-                for (_, body) in arms {
+                for (_, guard, body) in arms {
+                    if let Some(guard) = guard {
+                        f(guard);
+                    }
                     f(body);
                 }
             },
@@ -254,7 +258,10 @@ impl Expr {
                 .for_each(|item| f(item)),
             Expr::Match(pred, arms) => {
                 f(pred);
-                for (_, body) in arms {
+                for (_, guard, body) in arms {
+                    if let Some(guard) = guard {
+                        f(guard);
+                    }
                     f(body);
                 }
             },
@@ -287,8 +294,11 @@ impl Expr {
             Expr::Local(local) if *local == name => *self = local_expr.clone(),
             Expr::Match(pred, arms) => {
                 pred.inline_local(name, local_expr);
-                for (arm, body) in arms {
+                for (arm, guard, body) in arms {
                     if !arm.binding_names().contains(&name) {
+                        if let Some(guard) = guard {
+                            guard.inline_local(name, local_expr);
+                        }
                         body.inline_local(name, local_expr);
                     }
                 }
@@ -337,7 +347,7 @@ impl Expr {
                 .for_each(|item| item.visit_inner(order, repr, binding, expr)),
             Expr::Match(pred, arms) => {
                 pred.visit_inner(order, repr, binding, expr);
-                for (b, body) in arms {
+                for (b, _, body) in arms {
                     b.visit_inner(order, repr, binding, expr);
                     body.visit_inner(order, repr, binding, expr);
                 }
@@ -390,98 +400,3 @@ pub fn prepare(ctx: &mut Context) {
         mark_loop_breakers(ctx, &proc.body, &mut vec![id]);
     }
 }
-
-/// Check the self-consistency of the MIR
-pub fn check(ctx: &Context) {
-    fn check_binding(ctx: &Context, binding: &Binding, repr: &Repr, stack: &mut Vec<(Local, Repr)>) {
-        match (&binding.pat, repr) {
-            (Pat::Data(a, inner), Repr::Data(b)) if a == b => {}, // TODO: Check inner
-            (Pat::Wildcard, _) => {},
-            (Pat::Tuple(a), Repr::Tuple(b)) if a.len() == b.len() => {},
-            (Pat::Variant(idx, inner), Repr::Sum(variants)) if *idx < variants.len() => check_binding(ctx, inner, &variants[*idx], stack),
-            (Pat::Single(inner), _) => check_binding(ctx, inner, repr, stack),
-            (Pat::ListExact(_), Repr::List(_)) => {},
-            (Pat::ListFront(_, _), Repr::List(_)) => {},
-            (_, repr) => panic!("Inconsistency between binding\n\n {:?}\n\nand repr {:?}", binding, repr),
-        }
-
-        binding.for_children(|binding| check_binding(ctx, binding, binding.meta(), stack));
-    }
-
-    fn check_expr(ctx: &Context, expr: &Expr, repr: &Repr, stack: &mut Vec<(Local, Repr)>) {
-        match (expr, repr) {
-            (Expr::Data(a, inner), Repr::Data(b)) if a == b => {}, // TODO: Check inner
-            // TODO: Check literals elsewhere
-            (Expr::Literal(Literal::Nat(_)), Repr::Prim(Prim::Nat)) => {},
-            (Expr::Literal(Literal::List(_)), Repr::List(_)) => {},
-            (Expr::Literal(Literal::Tuple(_)), Repr::Tuple(_)) => {},
-            (Expr::Literal(Literal::Sum(_, _)), _) => {},
-            (Expr::Literal(Literal::Data(a, _)), Repr::Data(b)) if a == b => {},
-            (Expr::Global(_, _), _) => {}, // TODO
-            (Expr::Local(local), repr) if &stack
-                .iter()
-                .rev()
-                .find(|(name, _)| name == local)
-                .unwrap_or_else(|| panic!("Failed to find local ${} in scope", local.0)).1 == repr => {},
-            (Expr::Func(i, body), Repr::Func(i_repr, _)) => {
-                stack.push((**i, (**i_repr).clone()));
-                visit_expr(ctx, body, stack);
-                stack.pop();
-            },
-            (Expr::Go(_, body, init), _) => {
-                // TODO: Validate return body and return type
-            },
-            (Expr::Apply(f, arg), _) => {
-                assert!(matches!(f.meta(), Repr::Func(_, _)));
-            },
-            (Expr::Tuple(a), Repr::Tuple(b)) if a.len() == b.len() => {
-                expr.for_children(|expr| visit_expr(ctx, expr, stack));
-            },
-            (Expr::List(items), Repr::List(b)) => {
-                for item in items {
-                    check_expr(ctx, item, b, stack);
-                }
-            },
-            (Expr::Match(pred, arms), repr) => {
-                for (arm, body) in arms {
-                    // TODO: visit binding
-                    check_binding(ctx, arm.inner(), pred.meta(), stack);
-                    let old_stack = stack.len();
-                    stack.append(&mut arm.bindings());
-                    check_expr(ctx, body, body.meta(), stack);
-                    stack.truncate(old_stack);
-                }
-            },
-            (Expr::Variant(idx, inner), Repr::Sum(variants)) if *idx < variants.len() => {
-                check_expr(ctx, inner, &variants[*idx], stack);
-                expr.for_children(|expr| visit_expr(ctx, expr, stack));
-            },
-            (expr, Repr::Data(data)) => {
-                check_expr(ctx, expr, &ctx.reprs.get(*data).repr, stack);
-            },
-            (Expr::Variant(idx, inner), Repr::Data(_)) => {}, // TODO
-            (Expr::Intrinsic(_, _), _) => {}, // TODO
-            (Expr::Access(_, _), _) => {}, // TODO
-            (Expr::Basin(_, inner), Repr::Effect(_, o)) => {
-                check_expr(ctx, inner, o, stack);
-            }, // TODO
-            (Expr::Handle { expr, handlers }, r) if matches!(expr.meta(), Repr::Effect(_, _)) => {
-                // TODO: Revisit this, might not be correct with subtyping
-                // check_expr(ctx, expr, &Repr::Effect(vec![*eff], Box::new(r.clone())), stack);
-            },
-            // (Expr::Data(_, _, _), Repr::Func(_, _)) => {},
-            (expr, repr) => panic!("Inconsistency between expression\n\n {:?}\n\nand repr {:?}", expr, repr),
-        }
-    }
-
-    fn visit_expr(ctx: &Context, expr: &MirNode<Expr>, stack: &mut Vec<(Local, Repr)>) {
-        check_expr(ctx, expr.inner(), expr.meta(), stack);
-    }
-
-    for (id, proc) in ctx.procs.iter() {
-        println!("Checking {:?}", id);
-        println!("{}", proc.body.print());
-        assert_eq!(proc.body.required_locals(None).len(), 0, "Procedure requires locals");
-        visit_expr(ctx, &proc.body, &mut Vec::new());
-    }
-}
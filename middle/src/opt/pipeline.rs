@@ -0,0 +1,249 @@
+use super::*;
+use std::{
+    fmt,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+// The number of times the passes are run, unless iterating to a fixed point
+const DEFAULT_ROUNDS: usize = 3;
+// Passes may undo one-another's work, so the MIR isn't guaranteed to ever stop changing. This bounds the effort spent
+// searching for a fixed point.
+const MAX_ROUNDS: usize = 64;
+
+// Every pass, configured for the optimisation mode
+fn all_passes(mode: OptMode) -> Vec<Box<dyn Pass>> {
+    vec![
+        Box::new(FlattenSingleField::default()),
+        Box::new(LambdaLift::default()),
+        Box::new(EvalConstants::default()),
+        Box::new(ConstFold {
+            inline: !matches!(mode, OptMode::Size),
+        }),
+        Box::new(RemoveUnusedBindings::default()),
+        Box::new(RemoveDeadProc::default()),
+        Box::new(ShareInstances::default()),
+    ]
+}
+
+// The passes run by an optimisation mode when none are given explicitly, in order
+fn default_passes(mode: OptMode) -> &'static [&'static str] {
+    match mode {
+        OptMode::None => &[],
+        // Specialisation trades code size for speed
+        OptMode::Fast => &["flatten-single-field", "lambda-lift", "eval-constants", "const-fold", "remove-unused-bindings", "remove-dead-proc"],
+        OptMode::Size => &["flatten-single-field", "eval-constants", "const-fold", "remove-unused-bindings", "remove-dead-proc"],
+    }
+}
+
+fn pass_by_name(name: &str, mode: OptMode) -> Option<Box<dyn Pass>> {
+    all_passes(mode)
+        .into_iter()
+        .find(|pass| pass.name() == name)
+}
+
+#[derive(Debug)]
+pub enum OptError {
+    UnknownPass(String),
+    Verify(VerifyError),
+}
+
+impl From<VerifyError> for OptError {
+    fn from(err: VerifyError) -> Self { OptError::Verify(err) }
+}
+
+impl fmt::Display for OptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OptError::UnknownPass(name) => write!(
+                f,
+                "No optimisation pass named '{}' (expected one of {})",
+                name,
+                all_passes(OptMode::None)
+                    .iter()
+                    .map(|pass| pass.name())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+            OptError::Verify(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+// The size of the MIR, as a number of procedures and a number of expressions
+#[derive(Copy, Clone)]
+struct Size {
+    procs: usize,
+    exprs: usize,
+}
+
+impl Size {
+    fn of(ctx: &Context) -> Self {
+        Self {
+            procs: ctx.procs.iter().count(),
+            exprs: ctx.procs
+                .iter()
+                .map(|(_, proc)| proc.body.size())
+                .sum(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct PassStats {
+    runs: usize,
+    time: Duration,
+    procs: isize,
+    exprs: isize,
+}
+
+impl PassStats {
+    fn record(&mut self, time: Duration, before: Size, after: Size) {
+        self.runs += 1;
+        self.time += time;
+        self.procs += after.procs as isize - before.procs as isize;
+        self.exprs += after.exprs as isize - before.exprs as isize;
+    }
+}
+
+// Lists the instantiations of each generic item, largest first
+fn report_instances(ctx: &Context, shared: Option<usize>, writer: &mut dyn Write) {
+    let mut origins = BTreeMap::<String, (usize, usize)>::new();
+    for (id, proc) in ctx.procs.iter() {
+        let (instances, exprs) = origins.entry(origin(id)).or_default();
+        *instances += 1;
+        *exprs += proc.body.size();
+    }
+    let mut origins = origins.into_iter().collect::<Vec<_>>();
+    origins.sort_by_key(|(_, (_, exprs))| std::cmp::Reverse(*exprs));
+
+    writeln!(writer, "{:<40} | {:>9} | {:>8}", "item", "instances", "exprs").unwrap();
+    for (origin, (instances, exprs)) in &origins {
+        writeln!(writer, "{:<40} | {:>9} | {:>8}", origin, instances, exprs).unwrap();
+    }
+    let size = Size::of(ctx);
+    write!(writer, "{} items, {} procs, {} exprs", origins.len(), size.procs, size.exprs).unwrap();
+    match shared {
+        Some(shared) => writeln!(writer, " ({} instances shared)", shared).unwrap(),
+        None => writeln!(writer).unwrap(),
+    }
+}
+
+// A representation of the MIR that changes whenever the MIR does, used to detect a fixed point
+fn snapshot(ctx: &Context) -> Vec<String> {
+    ctx.procs
+        .iter()
+        .map(|(id, proc)| format!("{:?} = {:?}", id, proc.body))
+        .collect()
+}
+
+// Called after lowering and after every run of a pass, to dump or verify the MIR as requested
+fn after_stage(ctx: &Context, cfg: &OptConfig, name: &str, stage: &str, writer: &mut dyn Write) -> Result<(), OptError> {
+    if cfg.dump_after.iter().any(|dump| dump == name) {
+        writeln!(writer, "MIR after {}:\n", stage).unwrap();
+        for (id, proc) in ctx.procs.iter() {
+            writeln!(writer, "PROCEDURE {:?}\n\n{}\n", id, proc.body.print()).unwrap();
+        }
+    }
+
+    if cfg.verify {
+        ctx.verify(stage)?;
+    }
+
+    Ok(())
+}
+
+/// Run the optimisation pipeline described by the configuration over the MIR, writing any requested MIR dumps and
+/// statistics to the writer.
+pub fn optimize(ctx: &mut Context, cfg: &OptConfig, writer: &mut dyn Write) -> Result<(), OptError> {
+    let mut passes = if cfg.passes.is_empty() {
+        default_passes(cfg.mode)
+            .iter()
+            .map(|name| pass_by_name(name, cfg.mode).unwrap())
+            .collect::<Vec<_>>()
+    } else {
+        cfg.passes
+            .iter()
+            .map(|name| pass_by_name(name, cfg.mode).ok_or_else(|| OptError::UnknownPass(name.clone())))
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    after_stage(ctx, cfg, "lowering", "lowering", writer)?;
+
+    let shared = if cfg.share_instances {
+        let before = Size::of(ctx);
+        ShareInstances.apply(ctx);
+        after_stage(ctx, cfg, "share-instances", "share-instances", writer)?;
+        Some(before.procs - Size::of(ctx).procs)
+    } else {
+        None
+    };
+
+    if cfg.report_instances {
+        report_instances(ctx, shared, writer);
+    }
+
+    if passes.is_empty() {
+        return Ok(());
+    }
+
+    prepare(ctx);
+
+    let initial_size = Size::of(ctx);
+    let mut stats = passes
+        .iter()
+        .map(|_| PassStats::default())
+        .collect::<Vec<_>>();
+    let mut rounds = 0;
+    let mut fixed_point = false;
+
+    while rounds < if cfg.fixed_point { MAX_ROUNDS } else { DEFAULT_ROUNDS } {
+        rounds += 1;
+        let before_round = if cfg.fixed_point { Some(snapshot(ctx)) } else { None };
+
+        for (pass, stats) in passes.iter_mut().zip(stats.iter_mut()) {
+            let before = if cfg.stats { Some(Size::of(ctx)) } else { None };
+            let start = Instant::now();
+            pass.apply(ctx);
+            let time = start.elapsed();
+            if let Some(before) = before {
+                stats.record(time, before, Size::of(ctx));
+            }
+
+            after_stage(ctx, cfg, pass.name(), &format!("{} (round {})", pass.name(), rounds), writer)?;
+        }
+
+        if before_round.map_or(false, |before_round| before_round == snapshot(ctx)) {
+            fixed_point = true;
+            break;
+        }
+    }
+
+    if cfg.stats {
+        let final_size = Size::of(ctx);
+        writeln!(writer, "{:<24} | {:>6} | {:>10} | {:>8} | {:>8}", "pass", "runs", "time (us)", "exprs", "procs").unwrap();
+        for (pass, stats) in passes.iter().zip(&stats) {
+            writeln!(
+                writer,
+                "{:<24} | {:>6} | {:>10} | {:>+8} | {:>+8}",
+                pass.name(),
+                stats.runs,
+                stats.time.as_micros(),
+                stats.exprs,
+                stats.procs,
+            ).unwrap();
+        }
+        writeln!(
+            writer,
+            "{} rounds{}, {} -> {} exprs, {} -> {} procs",
+            rounds,
+            if fixed_point { " (reached a fixed point)" } else { "" },
+            initial_size.exprs,
+            final_size.exprs,
+            initial_size.procs,
+            final_size.procs,
+        ).unwrap();
+    }
+
+    Ok(())
+}
@@ -4,6 +4,8 @@ use super::*;
 pub struct RemoveDeadProc;
 
 impl Pass for RemoveDeadProc {
+    fn name(&self) -> &'static str { "remove-dead-proc" }
+
     fn apply(&mut self, ctx: &mut Context) {
         let reachable = ctx.reachable_procs();
 
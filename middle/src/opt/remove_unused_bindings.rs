@@ -1,11 +1,13 @@
 use super::*;
 
 /// Remove bindings that are never used. Also, matches with a single arm that do not bind are flattened and arms that
-/// follow irrefutable arms are removed.
+/// follow irrefutable unguarded arms are removed.
 #[derive(Default)]
 pub struct RemoveUnusedBindings;
 
 impl Pass for RemoveUnusedBindings {
+    fn name(&self) -> &'static str { "remove-unused-bindings" }
+
     fn apply(&mut self, ctx: &mut Context) {
         fn visit(
             mir: &Context,
@@ -23,9 +25,9 @@ impl Pass for RemoveUnusedBindings {
                 Expr::Match(pred, arms) => {
                     visit(mir, pred, stack, proc_stack);
 
-                    // Remove any arms that follow an irrefutable arm
+                    // Remove any arms that follow an irrefutable arm without a guard
                     for i in 0..arms.len() {
-                        if !arms[i].0.is_refutable() {
+                        if !arms[i].0.is_refutable() && arms[i].1.is_none() {
                             arms.truncate(i + 1);
                             break;
                         }
@@ -33,10 +35,13 @@ impl Pass for RemoveUnusedBindings {
 
                     arms
                         .iter_mut()
-                        .for_each(|(arm, body)| {
+                        .for_each(|(arm, guard, body)| {
                             let old_stack = stack.len();
 
                             stack.extend(arm.binding_names().into_iter().map(|name| (name, 0)));
+                            if let Some(guard) = guard {
+                                visit(mir, guard, stack, proc_stack);
+                            }
                             visit(mir, body, stack, proc_stack);
 
                             fn remove_unused(binding: &mut Binding, stack: &mut Vec<(Local, u64)>) {
@@ -79,13 +84,13 @@ impl Pass for RemoveUnusedBindings {
                     visit(mir, pred, stack, proc_stack);
 
                     // Flatten matches with a single arm where the arm does not bind
-                    if arms.len() == 1 && !arms.first().unwrap().0.binds() {
+                    if arms.len() == 1 && !arms.first().unwrap().0.binds() && arms[0].1.is_none() {
                         if !pred.may_have_effect() {
-                            *expr = arms.remove(0).1.into_inner();
+                            *expr = arms.remove(0).2.into_inner();
                         }
-                    } else if arms.get(0).map_or(false, |(b, _)| matches!(&b.pat, Pat::Wildcard)) {
+                    } else if arms.get(0).map_or(false, |(b, guard, _)| matches!(&b.pat, Pat::Wildcard) && guard.is_none()) {
                         if !pred.may_have_effect() {
-                            let (arm, mut body) = arms.remove(0);
+                            let (arm, _, mut body) = arms.remove(0);
                             if let Some(name) = arm.name {
                                 body.inline_local(name, pred);
                             }
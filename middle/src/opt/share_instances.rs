@@ -0,0 +1,119 @@
+use super::*;
+
+/// Merges procedures that instantiate the same generic item with identical bodies, such that the instantiations share
+/// one procedure. This happens when the type arguments of the instantiations have identical representations, or when
+/// the body of the item doesn't depend on them.
+///
+/// Instantiations are compared by partition refinement: every instantiation of an item is first assumed to be
+/// shareable, and groups are then split apart until the instantiations in each group have bodies that are identical
+/// up to references to procedures in the same group. Unlike merging procedures with identical bodies one at a time,
+/// this also shares mutually recursive instantiations, each of which differs from the others only in which of the
+/// others it refers to.
+#[derive(Default)]
+pub struct ShareInstances;
+
+/// The generic item (definition or class member) that a procedure is an instantiation of, by name.
+pub fn origin(proc: ProcId) -> String {
+    match proc.proc() {
+        ConProc::Def(def, _) => format!("{:?}", def),
+        // Every implementation of a class member is an instantiation of the member, whatever type it is for
+        ConProc::Field(_, member, _, field) => format!("{:?}.{}", member, field),
+        ConProc::Harness(_, _) => format!("{:?}", proc),
+        // Procedures created during optimisation are attributed to the item that they came from
+        ConProc::Lifted(parent, _) | ConProc::Specialized(parent, _, _) => origin(*parent),
+    }
+}
+
+fn canonical_local(local: &mut Local, names: &mut HashMap<Local, Local>) {
+    let next = Local(names.len());
+    *local = *names.entry(*local).or_insert(next);
+}
+
+fn canonicalize_binding(binding: &mut Binding, names: &mut HashMap<Local, Local>) {
+    if let Some(name) = &mut binding.name {
+        canonical_local(name, names);
+    }
+    binding.for_children_mut(|binding| canonicalize_binding(binding, names));
+}
+
+// Renames locals in the order that they appear
+fn canonicalize(expr: &mut Expr, names: &mut HashMap<Local, Local>) {
+    match expr {
+        Expr::Local(local) => canonical_local(local, names),
+        Expr::Match(_, arms) => for (binding, _, _) in arms.iter_mut() {
+            canonicalize_binding(binding, names);
+        },
+        Expr::Func(arg, _) | Expr::Go(arg, _, _) => canonical_local(arg, names),
+        Expr::Handle { handlers, .. } => for Handler { send, state, .. } in handlers.iter_mut() {
+            canonical_local(send, names);
+            canonical_local(state, names);
+        },
+        _ => {},
+    }
+
+    expr.for_children_mut(|expr| canonicalize(expr, names));
+}
+
+// Two procedures with the same canonical form (including the representations of every expression within them) behave
+// identically, provided that the procedures they refer to do too. References to procedures are replaced with
+// references to the procedures that represent their groups.
+fn canonical_form(body: &MirNode<Expr>, groups: &HashMap<ProcId, ProcId>) -> String {
+    let mut body = body.clone();
+    canonicalize(&mut body, &mut HashMap::new());
+    redirect(&mut body, groups);
+    format!("{:?}", body)
+}
+
+fn redirect(expr: &mut Expr, shared: &HashMap<ProcId, ProcId>) {
+    if let Expr::Global(proc, _) = expr {
+        if let Some(shared) = shared.get(proc) {
+            *proc = *shared;
+        }
+    }
+
+    expr.for_children_mut(|expr| redirect(expr, shared));
+}
+
+impl Pass for ShareInstances {
+    fn name(&self) -> &'static str { "share-instances" }
+
+    fn apply(&mut self, ctx: &mut Context) {
+        // Each procedure maps to the procedure that represents its group, the first in the group
+        let mut groups = HashMap::new();
+        let mut items = HashMap::new();
+        for (id, _) in ctx.procs.iter() {
+            groups.insert(id, *items.entry(origin(id)).or_insert(id));
+        }
+
+        // Groups are only ever split, so the grouping is stable once their number stops changing
+        loop {
+            let mut forms = HashMap::new();
+            let refined = ctx.procs
+                .iter()
+                .map(|(id, proc)| {
+                    let form = (groups[&id], canonical_form(&proc.body, &groups));
+                    (id, *forms.entry(form).or_insert(id))
+                })
+                .collect::<HashMap<_, _>>();
+
+            let stable = forms.len() == groups.iter().filter(|(id, group)| id == group).count();
+            groups = refined;
+            if stable {
+                break;
+            }
+        }
+
+        let shared = groups
+            .into_iter()
+            .filter(|(id, group)| id != group)
+            .collect::<HashMap<_, _>>();
+
+        for (_, proc) in ctx.procs.iter_mut() {
+            redirect(&mut proc.body, &shared);
+        }
+        ctx.procs.procs.retain(|id, _| !shared.contains_key(id));
+        if let Some(entry) = &mut ctx.entry {
+            *entry = shared.get(entry).copied().unwrap_or(*entry);
+        }
+    }
+}
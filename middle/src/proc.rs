@@ -9,6 +9,8 @@ pub struct Proc {
 #[derive(Default)]
 pub struct Procs {
     pub procs: BTreeMap<ProcId, Option<Proc>>,
+    // The number of lambdas lifted into procedures of their own so far
+    lifted: usize,
 }
 
 impl Procs {
@@ -36,6 +38,16 @@ impl Procs {
         self.procs.iter().filter_map(|(id, proc)| Some((*id, proc.as_ref()?)))
     }
 
+    /// A new identifier for a lambda lifted out of the given procedure.
+    ///
+    /// Identifiers are never reused, even once the procedure given one has been removed: procedures derived from a
+    /// lifted procedure (such as specialisations of functions to it) are found by its identifier, so reusing one would
+    /// give a new lambda the derived procedures of an old one.
+    pub fn new_lifted(&mut self, parent: ProcId) -> ProcId {
+        self.lifted += 1;
+        ProcId::lifted(parent, self.lifted - 1)
+    }
+
     pub fn declare(&mut self, id: ProcId) {
         assert!(self.procs.insert(id, None).is_none(), "Proc declared twice");
     }
@@ -23,15 +23,20 @@ pub enum Repr {
     Effect(Vec<EffectId>, Box<Repr>),
 }
 
+#[derive(Clone, Debug)]
 pub struct Data {
     pub is_recursive: bool,
     pub repr: Repr,
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Reprs {
     pub datas: BTreeMap<ConDataId, Option<Data>>,
     pub r#bool: Option<ConDataId>,
+    /// The representations of the values sent to and received from each effect.
+    pub effects: HashMap<EffectId, (Repr, Repr)>,
+    /// The representations of the arguments and output of each host function that is called.
+    pub hosts: HashMap<Ident, (Vec<Repr>, Repr)>,
 }
 
 impl Reprs {
@@ -123,6 +123,8 @@ pub enum Type {
     List(SrcNode<Self>),
     Tuple(Vec<SrcNode<Self>>),
     Record(Vec<(SrcNode<Ident>, SrcNode<Self>)>),
+    // An extensible record, like `{ name: Str .. r }`, containing the given fields plus those of the tail
+    Row(Vec<(SrcNode<Ident>, SrcNode<Self>)>, SrcNode<Self>),
     Func(SrcNode<Self>, SrcNode<Self>),
     // TODO: Replace name with `Item` when ready
     Data(SrcNode<Ident>, Vec<SrcNode<Self>>),
@@ -142,6 +144,9 @@ impl Type {
             Self::Record(fields) => fields
                 .iter()
                 .all(|(_, field)| field.is_fully_specified()),
+            Self::Row(fields, tail) => fields
+                .iter()
+                .all(|(_, field)| field.is_fully_specified()) && tail.is_fully_specified(),
             Self::Func(i, o) => i.is_fully_specified() && o.is_fully_specified(),
             Self::Data(_, args) => args
                 .iter()
@@ -206,19 +211,34 @@ pub enum Expr {
     Unary(SrcNode<UnaryOp>, SrcNode<Self>),
     Binary(SrcNode<BinaryOp>, SrcNode<Self>, SrcNode<Self>),
     Let(Vec<(SrcNode<Binding>, SrcNode<Self>)>, SrcNode<Self>),
-    Match(SrcNode<Vec<SrcNode<Self>>>, Vec<(SrcNode<Vec<SrcNode<Binding>>>, SrcNode<Self>)>),
+    // (bindings, guard, body)
+    Match(SrcNode<Vec<SrcNode<Self>>>, Vec<(SrcNode<Vec<SrcNode<Binding>>>, Option<SrcNode<Self>>, SrcNode<Self>)>),
     If(SrcNode<Self>, SrcNode<Self>, SrcNode<Self>),
-    Func(SrcNode<Vec<(SrcNode<Vec<SrcNode<Binding>>>, SrcNode<Self>)>>),
+    // (bindings, guard, body)
+    Func(SrcNode<Vec<(SrcNode<Vec<SrcNode<Binding>>>, Option<SrcNode<Self>>, SrcNode<Self>)>>),
     Apply(SrcNode<Self>, SrcNode<Self>),
     Cons(SrcNode<Ident>, SrcNode<Self>),
     ClassAccess(SrcNode<Type>, SrcNode<Ident>),
     Intrinsic(SrcNode<Ident>, Vec<SrcNode<Self>>),
     Update(SrcNode<Self>, Vec<(SrcNode<Ident>, SrcNode<Self>)>),
+    // Extend a record with extra fields, like `{ x: 1 .. record }`
+    Extend(SrcNode<Self>, Vec<(SrcNode<Ident>, SrcNode<Self>)>),
+    // Remove fields from a record, like `record without { x }`
+    Restrict(SrcNode<Self>, Vec<SrcNode<Ident>>),
     Block(Vec<(Option<SrcNode<Binding>>, SrcNode<Self>)>, SrcNode<Self>),
     Handle {
         expr: SrcNode<Self>,
         handlers: Vec<Handler>,
     },
+    // An interpolated string, like `"x = {x}, y = {y:?}"`
+    Interp(Vec<InterpPart>),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum InterpPart {
+    Str(SrcNode<Intern<String>>),
+    // (expr, is_debug), displayed with `Show` or, when followed by `:?`, `Debug`
+    Expr(SrcNode<Expr>, bool),
 }
 
 #[derive(Debug, PartialEq)]
@@ -293,6 +313,7 @@ pub enum ClassItem {
     Value {
         name: SrcNode<Ident>,
         ty: SrcNode<Type>,
+        default: Option<SrcNode<Expr>>,
     },
     Type {
         name: SrcNode<Ident>,
@@ -357,16 +378,44 @@ pub enum ItemKind {
 pub struct Attr {
     pub name: SrcNode<Ident>,
     pub args: Option<Vec<SrcNode<Self>>>,
+    /// The text of a doc comment (`## ...` for items, `#! ...` for modules). Doc comments are kept as `doc` attributes
+    /// so that they travel alongside the other attributes of an item, but attributes written by hand never have text.
+    pub doc: Option<String>,
+}
+
+impl Attr {
+    pub fn doc(text: String, span: Span) -> Self {
+        Self {
+            name: SrcNode::new(Ident::new("doc"), span),
+            args: None,
+            doc: Some(text),
+        }
+    }
+}
+
+/// Get the documentation text from a list of attributes, if any.
+pub fn docs(attrs: &[SrcNode<Attr>]) -> Option<String> {
+    let docs = attrs
+        .iter()
+        .filter_map(|attr| attr.doc.as_deref())
+        .collect::<Vec<_>>();
+    if docs.is_empty() {
+        None
+    } else {
+        Some(docs.join("\n"))
+    }
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Item {
     pub kind: ItemKind,
     pub attrs: Vec<SrcNode<Attr>>,
+    pub span: Span,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct Module {
+    pub attrs: Vec<SrcNode<Attr>>,
     pub imports: Vec<SrcNode<Intern<String>>>,
     pub items: Vec<Item>,
 }
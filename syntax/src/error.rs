@@ -10,6 +10,8 @@ pub enum ErrorKind {
     Unexpected(Pattern),
     Unclosed { start: Pattern, before_span: Span, before: Option<Pattern> },
     NoEndBranch,
+    // A malformed escape sequence or string literal, with a description of the problem
+    InvalidLiteral(String),
 }
 
 #[derive(Debug)]
@@ -27,6 +29,8 @@ impl Error {
         self
     }
 
+    pub fn kind(&self) -> &ErrorKind { &self.kind }
+
     pub fn while_parsing(mut self, span: Span, structure: &'static str) -> Self {
         self.while_parsing = self.while_parsing.or_else(|| Some((span, structure)));
         self
@@ -44,6 +48,10 @@ impl Error {
         }
     }
 
+    pub fn invalid_literal(span: Span, reason: impl ToString) -> Self {
+        Self::new(ErrorKind::InvalidLiteral(reason.to_string()), span)
+    }
+
     pub fn merge(mut self, other: Self) -> Self {
         // TODO: Use HashSet
         for expected in other.expected.into_iter() {
@@ -55,25 +63,29 @@ impl Error {
     pub fn write<C: ariadne::Cache<SrcId>>(self, cache: C, writer: impl Write) {
         use ariadne::{Report, ReportKind, Label, Color, Fmt};
 
-        let msg = format!(
-            "{}{}, expected {}",
-            match &self.kind {
-                ErrorKind::UnexpectedEnd => "Unexpected end of input".to_string(),
-                ErrorKind::Unexpected(pat) => format!("Unexpected {}", pat.fg(Color::Red)),
-                ErrorKind::Unclosed { start, .. } => format!("Unclosed delimiter {}", start.fg(Color::Red)),
-                ErrorKind::NoEndBranch => format!("No end branch"),
-            },
-            if let Some(label) = self.label {
-                format!(" while parsing {}", label.fg(Color::Cyan))
-            } else {
-                "".to_string()
-            },
-            match self.expected.len() {
-                0 => "something else".to_string(),
-                1 => format!("{}", self.expected.into_iter().next().unwrap().fg(Color::Yellow)),
-                _ => format!("one of {}", self.expected.into_iter().map(|x| x.fg(Color::Yellow).to_string()).collect::<Vec<_>>().join(", ")),
-            },
-        );
+        let msg = match &self.kind {
+            ErrorKind::InvalidLiteral(reason) => format!("Invalid literal: {}", reason),
+            kind => format!(
+                "{}{}, expected {}",
+                match kind {
+                    ErrorKind::UnexpectedEnd => "Unexpected end of input".to_string(),
+                    ErrorKind::Unexpected(pat) => format!("Unexpected {}", pat.fg(Color::Red)),
+                    ErrorKind::Unclosed { start, .. } => format!("Unclosed delimiter {}", start.fg(Color::Red)),
+                    ErrorKind::NoEndBranch => format!("No end branch"),
+                    ErrorKind::InvalidLiteral(_) => unreachable!(),
+                },
+                if let Some(label) = self.label {
+                    format!(" while parsing {}", label.fg(Color::Cyan))
+                } else {
+                    "".to_string()
+                },
+                match self.expected.len() {
+                    0 => "something else".to_string(),
+                    1 => format!("{}", self.expected.into_iter().next().unwrap().fg(Color::Yellow)),
+                    _ => format!("one of {}", self.expected.into_iter().map(|x| x.fg(Color::Yellow).to_string()).collect::<Vec<_>>().join(", ")),
+                },
+            ),
+        };
 
         let report = Report::build(ReportKind::Error, self.span.src(), self.span.start())
             .with_code(3)
@@ -84,6 +96,7 @@ impl Error {
                     ErrorKind::Unexpected(pat) => format!("Unexpected {}", pat.fg(Color::Red)),
                     ErrorKind::Unclosed { start, .. } => format!("Delimiter {} is never closed", start.fg(Color::Red)),
                     ErrorKind::NoEndBranch => format!("Requires a {} branch", "\\ ... => ...".fg(Color::Blue)),
+                    ErrorKind::InvalidLiteral(_) => format!("Invalid literal"),
                 })
                 .with_color(Color::Red));
 
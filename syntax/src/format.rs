@@ -0,0 +1,1036 @@
+//! A formatter that pretty-prints Tao source code in a canonical style.
+//!
+//! Formatting is driven by the AST, so the output does not depend on how the input was laid out. Comments are not
+//! part of the AST: they are found separately (see [`token::comments`]) and are emitted before the first node that
+//! begins on a new line after them.
+
+use super::*;
+use std::{
+    collections::VecDeque,
+    ops::Range,
+};
+
+const INDENT: &str = "    ";
+
+// Expression precedence, from loosest to tightest. Expressions at `OPEN` extend as far to the right as they can.
+const OPEN: u8 = 0;
+const WITH: u8 = 1;
+const LOGICAL: u8 = 2;
+const COMPARISON: u8 = 3;
+const JOIN: u8 = 4;
+const SUM: u8 = 5;
+const PRODUCT: u8 = 6;
+const UNARY: u8 = 7;
+const PROPAGATE: u8 = 8;
+const CHAIN: u8 = 9;
+const ATOM: u8 = 10;
+
+// Type precedence, from loosest to tightest
+const TY_FUNC: u8 = 0;
+const TY_EFFECT: u8 = 1;
+const TY_DATA: u8 = 2;
+const TY_ATOM: u8 = 3;
+
+/// Format the given source code. If the code contains syntax errors, no output is produced.
+pub fn format_module(code: &str, src: SrcId) -> (Option<String>, Vec<Error>) {
+    let (module, errors) = parse_module(code, src);
+    match module {
+        Some(module) if errors.is_empty() => (Some(Formatter::new(code).module(&module)), errors),
+        _ => (None, errors),
+    }
+}
+
+struct Formatter {
+    chars: Vec<char>,
+    comments: VecDeque<(Range<usize>, String)>,
+}
+
+fn start<T>(node: &SrcNode<T>) -> usize { node.span().range().start }
+
+fn is_unit(expr: &ast::Expr) -> bool { matches!(expr, ast::Expr::Tuple(xs) if xs.is_empty()) }
+
+fn escape(c: char, quote: char) -> String {
+    match c {
+        '\\' => "\\\\".to_string(),
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\x08' => "\\b".to_string(),
+        '\x0C' => "\\f".to_string(),
+        '\0' => "\\0".to_string(),
+        c if c == quote => format!("\\{}", c),
+        // Braces delimit embedded expressions in strings
+        '{' | '}' if quote == '"' => format!("\\{}", c),
+        c if c.is_control() => format!("\\u{{{:X}}}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+fn literal(litr: &ast::Literal) -> String {
+    match litr {
+        ast::Literal::Nat(x) => format!("{}", x),
+        ast::Literal::Int(x) => format!("{}i", x),
+        ast::Literal::Real(x) => {
+            let s = format!("{}", x);
+            if s.contains('.') { s } else { format!("{}.0", s) }
+        },
+        ast::Literal::Char(c) => format!("'{}'", escape(*c, '\'')),
+        ast::Literal::Str(s) => format!("\"{}\"", s.chars().map(|c| escape(c, '"')).collect::<String>()),
+    }
+}
+
+impl Formatter {
+    fn new(code: &str) -> Self {
+        Self {
+            chars: code.chars().collect(),
+            comments: token::comments(code).into_iter().collect(),
+        }
+    }
+
+    /// Raw and multi-line strings are kept as they were written, since their contents don't say how they were written.
+    fn verbatim_string(&self, expr: &SrcNode<ast::Expr>) -> Option<String> {
+        let src = self.chars.get(expr.span().range())?;
+        if src.starts_with(&['r']) || src.starts_with(&['"'; 3]) {
+            Some(src.iter().collect())
+        } else {
+            None
+        }
+    }
+
+    /// Whether the source contains an empty line between two positions.
+    fn blank_between(&self, from: usize, to: usize) -> bool {
+        self.chars
+            .get(from..to)
+            .map_or(false, |s| s.iter().filter(|c| **c == '\n').count() > 1)
+    }
+
+    fn comment_before(&self, pos: usize) -> bool {
+        self.comments.front().map_or(false, |(r, _)| r.start < pos)
+    }
+
+    /// Begin a new line, first emitting any comments that appear before the given position.
+    fn line(&mut self, pos: usize, indent: usize) -> String {
+        let indent = INDENT.repeat(indent);
+        let mut s = format!("\n{}", indent);
+        while self.comment_before(pos) {
+            let (_, text) = self.comments.pop_front().unwrap();
+            s += &text;
+            s += "\n";
+            s += &indent;
+        }
+        s
+    }
+
+    /// Emit the top-level comments that appear before the given position, preserving empty lines that follow them.
+    fn top_comments(&mut self, pos: usize, out: &mut String) {
+        while self.comment_before(pos) {
+            let (range, text) = self.comments.pop_front().unwrap();
+            *out += &text;
+            *out += "\n";
+            let next = self.comments.front().map_or(pos, |(r, _)| r.start.min(pos));
+            if self.blank_between(range.end, next) {
+                *out += "\n";
+            }
+        }
+    }
+
+    fn module(&mut self, module: &ast::Module) -> String {
+        let mut out = String::new();
+
+        for import in &module.imports {
+            self.top_comments(start(import), &mut out);
+            out += &format!("import {}\n", literal(&ast::Literal::Str(**import)));
+        }
+
+        for item in &module.items {
+            if !out.is_empty() {
+                out += "\n";
+            }
+            self.top_comments(item.span.range().start, &mut out);
+            out += &self.item(item);
+            out += "\n";
+        }
+
+        if !self.comments.is_empty() {
+            if !out.is_empty() {
+                out += "\n";
+            }
+            self.top_comments(usize::MAX, &mut out);
+        }
+
+        out
+    }
+
+    fn attr(&mut self, attr: &ast::Attr) -> String {
+        match &attr.args {
+            Some(args) => format!(
+                "{}({})",
+                *attr.name,
+                args.iter().map(|arg| self.attr(arg)).collect::<Vec<_>>().join(", "),
+            ),
+            None => format!("{}", *attr.name),
+        }
+    }
+
+    fn item(&mut self, item: &ast::Item) -> String {
+        let mut out = String::new();
+        // Doc comments are printed along with the other comments
+        let attrs = item.attrs
+            .iter()
+            .filter(|attr| attr.doc.is_none())
+            .collect::<Vec<_>>();
+        for attr in attrs {
+            out += &format!("$[{}]\n", self.attr(attr));
+        }
+
+        out += &match &item.kind {
+            ast::ItemKind::Data(data) => self.data(data),
+            ast::ItemKind::Alias(alias) => format!(
+                "type {}{}{} = {}",
+                *alias.name,
+                self.generics(&alias.generics),
+                self.where_clause(&alias.generics, &[]),
+                self.ty(&alias.ty, TY_FUNC),
+            ),
+            ast::ItemKind::Def(def) => self.def(def),
+            ast::ItemKind::Class(class) => self.class(class),
+            ast::ItemKind::Member(member) => self.member(member),
+            ast::ItemKind::Effect(eff) => format!(
+                "effect {}{}{} = {} => {}",
+                *eff.name,
+                self.generics(&eff.generics),
+                self.where_clause(&eff.generics, &[]),
+                self.ty(&eff.send, TY_FUNC),
+                self.ty(&eff.recv, TY_FUNC),
+            ),
+            ast::ItemKind::EffectAlias(alias) => format!(
+                "effect {}{}{} = {}",
+                *alias.name,
+                self.generics(&alias.generics),
+                self.where_clause(&alias.generics, &[]),
+                alias.effects
+                    .iter()
+                    .map(|(name, args)| format!("{}{}", **name, self.ty_args(args)))
+                    .collect::<Vec<_>>()
+                    .join(" + "),
+            ),
+        };
+
+        out
+    }
+
+    fn data(&mut self, data: &ast::Data) -> String {
+        let head = format!(
+            "data {}{}{}",
+            *data.name,
+            self.generics(&data.generics),
+            self.where_clause(&data.generics, &[]),
+        );
+        match &data.variants[..] {
+            [] => format!("{} = |", head),
+            [(name, ty)] if **name == *data.name => if is_unit_ty(ty) {
+                head
+            } else {
+                format!("{} = {}", head, self.ty(ty, TY_FUNC))
+            },
+            variants => {
+                let mut out = format!("{} =", head);
+                for (i, (name, ty)) in variants.iter().enumerate() {
+                    out += &self.line(start(name), 1);
+                    out += if i + 1 == variants.len() { "\\ " } else { "| " };
+                    out += &format!("{}", **name);
+                    if !is_unit_ty(ty) {
+                        out += &format!(" {}", self.ty(ty, TY_FUNC));
+                    }
+                }
+                out
+            },
+        }
+    }
+
+    fn def(&mut self, def: &ast::Def) -> String {
+        let ty_hint = match &*def.ty_hint {
+            ast::Type::Unknown => String::new(),
+            ty => format!(" : {}", self.ty_inner(ty, TY_FUNC)),
+        };
+        let head = format!(
+            "{}{}{}",
+            self.generics(&def.generics),
+            ty_hint,
+            self.where_clause(&def.generics, &[]),
+        );
+        match &*def.body {
+            // Definitions whose body is a function with arms are written as `fn` items
+            ast::Expr::Func(arms) if !arms.is_empty() => format!(
+                "fn {}{} ={}",
+                *def.name,
+                head,
+                self.arms(arms, 0),
+            ),
+            _ => format!("def {}{} = {}", *def.name, head, self.expr(&def.body, OPEN, 0)),
+        }
+    }
+
+    fn class(&mut self, class: &ast::Class) -> String {
+        let is_obligation = |implied: &ast::ImpliedMember| matches!(
+            &*implied.member,
+            ast::Type::Data(name, args) if args.is_empty() && name.as_str() == "Self"
+        );
+        let obligations = class.generics.implied_members
+            .iter()
+            .filter(|implied| is_obligation(implied))
+            .map(|implied| self.implied(implied))
+            .collect::<Vec<_>>();
+        let where_clause = self.where_clause(&class.generics, &[ast::Ident::new("Self")]);
+
+        let mut out = format!(
+            "class {}{}{}{}",
+            *class.name,
+            if obligations.is_empty() { String::new() } else { format!(" < {}", obligations.join(" + ")) },
+            self.generics(&class.generics),
+            where_clause,
+        );
+        if !class.items.is_empty() {
+            out += " =";
+        }
+        for item in &class.items {
+            match item {
+                ast::ClassItem::Value { name, ty, default } => {
+                    out += &self.line(start(name), 1);
+                    out += &format!("=> {}", **name);
+                    if !matches!(&**ty, ast::Type::Unknown) {
+                        out += &format!(" : {}", self.ty(ty, TY_FUNC));
+                    }
+                    if let Some(default) = default {
+                        out += &format!(" = {}", self.expr(default, OPEN, 1));
+                    }
+                },
+                ast::ClassItem::Type { name, obligations } => {
+                    out += &self.line(start(name), 1);
+                    out += &format!("=> {}", **name);
+                    if !obligations.is_empty() {
+                        out += &format!(" < {}", obligations
+                            .iter()
+                            .map(|inst| self.class_inst(inst))
+                            .collect::<Vec<_>>()
+                            .join(" + "));
+                    }
+                },
+            }
+        }
+        out
+    }
+
+    fn member(&mut self, member: &ast::Member) -> String {
+        let generics = self.generics(&member.generics);
+        let mut out = format!(
+            "{}member {} of {}{}",
+            if generics.is_empty() { String::new() } else { format!("for{} ", generics) },
+            self.ty(&member.member, TY_FUNC),
+            self.class_inst(&member.class),
+            self.where_clause(&member.generics, &[]),
+        );
+        if !member.items.is_empty() {
+            out += " =";
+        }
+        for item in &member.items {
+            match item {
+                ast::MemberItem::Value { name, val } => {
+                    out += &self.line(start(name), 1);
+                    out += &format!("=> {} = {}", **name, self.expr(val, OPEN, 1));
+                },
+                ast::MemberItem::Type { name, ty } => {
+                    out += &self.line(start(name), 1);
+                    out += &format!("=> {} = {}", **name, self.ty(ty, TY_FUNC));
+                },
+            }
+        }
+        out
+    }
+
+    /// Whether an implied member is written alongside the generic parameter it constrains.
+    fn is_inline(generics: &ast::Generics, implied: &ast::ImpliedMember) -> bool {
+        match &*implied.member {
+            ast::Type::Data(name, args) => args.is_empty() && generics.tys.iter().any(|ty| *ty.name == **name),
+            _ => false,
+        }
+    }
+
+    /// Generic parameters, including a leading space if there are any.
+    fn generics(&mut self, generics: &ast::Generics) -> String {
+        generics.tys
+            .iter()
+            .map(|ty| {
+                let implied = generics.implied_members
+                    .iter()
+                    .filter(|implied| matches!(&*implied.member, ast::Type::Data(name, args) if args.is_empty() && **name == *ty.name))
+                    .map(|implied| self.implied(implied))
+                    .collect::<Vec<_>>();
+                if implied.is_empty() {
+                    format!(" {}", *ty.name)
+                } else {
+                    format!(" {} < {}", *ty.name, implied.join(" + "))
+                }
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// A `where` clause for the implied members that are not written inline, including a leading space if there are
+    /// any. Members whose type is one of `skip` are omitted.
+    fn where_clause(&mut self, generics: &ast::Generics, skip: &[ast::Ident]) -> String {
+        let mut clauses: Vec<(&SrcNode<ast::Type>, Vec<String>)> = Vec::new();
+        for implied in &generics.implied_members {
+            let skipped = matches!(&*implied.member, ast::Type::Data(name, args) if args.is_empty() && skip.contains(&**name));
+            if Self::is_inline(generics, implied) || skipped {
+                continue;
+            }
+            let inst = self.implied(implied);
+            match clauses.last_mut() {
+                Some((ty, insts)) if **ty == implied.member => insts.push(inst),
+                _ => clauses.push((&implied.member, vec![inst])),
+            }
+        }
+
+        if clauses.is_empty() {
+            String::new()
+        } else {
+            format!(" where {}", clauses
+                .into_iter()
+                .map(|(ty, insts)| format!("{} < {}", self.ty(ty, TY_EFFECT), insts.join(" + ")))
+                .collect::<Vec<_>>()
+                .join(", "))
+        }
+    }
+
+    fn implied(&mut self, implied: &ast::ImpliedMember) -> String {
+        let inst = self.class_inst(&implied.class);
+        if implied.assoc.is_empty() {
+            inst
+        } else {
+            format!("{} with {{ {} }}", inst, implied.assoc
+                .iter()
+                .map(|(name, ty)| format!("{} = {}", **name, self.ty(ty, TY_FUNC)))
+                .collect::<Vec<_>>()
+                .join(", "))
+        }
+    }
+
+    fn class_inst(&mut self, inst: &ast::ClassInst) -> String {
+        format!("{}{}", *inst.name, self.ty_args(&inst.params))
+    }
+
+    /// Type arguments that are each parsed as a complete type, including a leading space if there are any.
+    fn ty_args(&mut self, args: &[SrcNode<ast::Type>]) -> String {
+        args
+            .iter()
+            .enumerate()
+            .map(|(i, arg)| {
+                // A bare data type would consume the arguments that follow it
+                let absorbs = matches!(&**arg, ast::Type::Data(_, xs) if xs.is_empty()) && i + 1 < args.len();
+                if absorbs {
+                    format!(" ({})", self.ty(arg, TY_FUNC))
+                } else {
+                    format!(" {}", self.ty(arg, TY_ATOM))
+                }
+            })
+            .collect()
+    }
+
+    fn ty(&mut self, ty: &SrcNode<ast::Type>, prec: u8) -> String {
+        self.ty_inner(ty, prec)
+    }
+
+    fn ty_inner(&mut self, ty: &ast::Type, prec: u8) -> String {
+        let (s, ty_prec) = match ty {
+            ast::Type::Error => unreachable!("Cannot format erroneous syntax"),
+            ast::Type::Universe => ("@".to_string(), TY_ATOM),
+            ast::Type::Unknown => ("?".to_string(), TY_ATOM),
+            ast::Type::List(item) => (format!("[{}]", self.ty(item, TY_FUNC)), TY_ATOM),
+            ast::Type::Tuple(fields) if fields.len() == 1 => (format!("({},)", self.ty(&fields[0], TY_FUNC)), TY_ATOM),
+            ast::Type::Tuple(fields) => (format!("({})", fields
+                .iter()
+                .map(|field| self.ty(field, TY_FUNC))
+                .collect::<Vec<_>>()
+                .join(", ")), TY_ATOM),
+            ast::Type::Record(fields) if fields.is_empty() => ("{}".to_string(), TY_ATOM),
+            ast::Type::Record(fields) => (format!("{{ {} }}", fields
+                .iter()
+                .map(|(name, field)| format!("{}: {}", **name, self.ty(field, TY_FUNC)))
+                .collect::<Vec<_>>()
+                .join(", ")), TY_ATOM),
+            ast::Type::Row(fields, tail) => {
+                let fields = fields
+                    .iter()
+                    .map(|(name, field)| format!("{}: {}", **name, self.ty(field, TY_FUNC)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let fields = if fields.is_empty() { fields } else { format!("{} ", fields) };
+                (format!("{{ {}.. {} }}", fields, self.ty(tail, TY_FUNC)), TY_ATOM)
+            },
+            ast::Type::Func(i, o) => (format!("{} -> {}", self.ty(i, TY_EFFECT), self.ty(o, TY_FUNC)), TY_FUNC),
+            ast::Type::Data(name, args) if args.is_empty() => (format!("{}", **name), TY_ATOM),
+            // Arguments of data types are never parsed as complete types, so they cannot consume one-another
+            ast::Type::Data(name, args) => (format!("{}{}", **name, args
+                .iter()
+                .map(|arg| format!(" {}", self.ty(arg, TY_ATOM)))
+                .collect::<String>()), TY_DATA),
+            ast::Type::Assoc(inner, class, name) => {
+                let inner = match &**inner {
+                    ast::Type::Assoc(..) => self.ty(inner, TY_ATOM),
+                    ast::Type::Data(_, args) if !args.is_empty() => format!("({})", self.ty(inner, TY_FUNC)),
+                    _ => self.ty(inner, TY_ATOM),
+                };
+                let class = match class {
+                    Some(class) => format!(".<{}>", self.class_inst(class)),
+                    None => String::new(),
+                };
+                (format!("{}{}.{}", inner, class, **name), TY_ATOM)
+            },
+            ast::Type::Effect(effs, out) => {
+                let effs = effs
+                    .iter()
+                    .map(|(name, args)| format!("{}{}", **name, self.ty_args(args)))
+                    .collect::<Vec<_>>()
+                    .join(" + ");
+                (format!("{} ~ {}", effs, self.ty(out, TY_DATA)), TY_EFFECT)
+            },
+        };
+
+        if ty_prec < prec {
+            format!("({})", s)
+        } else {
+            s
+        }
+    }
+
+    /// A binding. Type hints are only permitted at the top level of a binding without parentheses.
+    fn binding(&mut self, binding: &ast::Binding, top: bool) -> String {
+        let core = self.binding_core(binding);
+        match &binding.ty {
+            Some(ty) if top => format!("{} : {}", core, self.ty(ty, TY_FUNC)),
+            Some(ty) => format!("({} : {})", core, self.ty(ty, TY_FUNC)),
+            None => core,
+        }
+    }
+
+    fn binding_core(&mut self, binding: &ast::Binding) -> String {
+        match (&binding.name, &*binding.pat) {
+            (None, ast::Pat::Wildcard) => "_".to_string(),
+            (Some(name), ast::Pat::Wildcard) => format!("{}", **name),
+            // `x ~ pat` is parsed as a named binding wrapping the pattern
+            (Some(name), ast::Pat::Single(inner)) if inner.name.is_none() && inner.ty.is_none() => {
+                format!("{} ~ {}", **name, self.pat(&inner.pat))
+            },
+            (Some(name), pat) => format!("{} ~ {}", **name, self.pat(pat)),
+            (None, pat) => self.pat(pat),
+        }
+    }
+
+    fn pat(&mut self, pat: &ast::Pat) -> String {
+        match pat {
+            ast::Pat::Error => unreachable!("Cannot format erroneous syntax"),
+            ast::Pat::Wildcard => "_".to_string(),
+            ast::Pat::Literal(litr) => literal(litr),
+            ast::Pat::Single(inner) => format!("({})", self.binding(inner, true)),
+            ast::Pat::Binary(op, lhs, rhs) => {
+                let lhs = match (&lhs.name, &*lhs.pat) {
+                    (_, ast::Pat::Wildcard) | (None, _) if lhs.ty.is_none() => self.binding_core(lhs),
+                    _ => format!("({})", self.binding(lhs, true)),
+                };
+                format!("{} {} {}", lhs, **op, literal(rhs))
+            },
+            ast::Pat::Tuple(fields) if fields.len() == 1 => format!("({},)", self.binding(&fields[0], false)),
+            ast::Pat::Tuple(fields) => format!("({})", fields
+                .iter()
+                .map(|field| self.binding(field, false))
+                .collect::<Vec<_>>()
+                .join(", ")),
+            ast::Pat::Record(fields) if fields.is_empty() => "{}".to_string(),
+            ast::Pat::Record(fields) => format!("{{ {} }}", fields
+                .iter()
+                .map(|(name, binding)| match (&binding.name, &*binding.pat) {
+                    // Punned fields
+                    (Some(binding_name), ast::Pat::Wildcard) if **binding_name == **name => match &binding.ty {
+                        Some(ty) => format!("{} : {}", **name, self.ty(ty, TY_FUNC)),
+                        None => format!("{}", **name),
+                    },
+                    _ => format!("{} ~ {}", **name, self.binding(binding, false)),
+                })
+                .collect::<Vec<_>>()
+                .join(", ")),
+            ast::Pat::ListExact(items) => format!("[{}]", items
+                .iter()
+                .map(|item| self.binding(item, false))
+                .collect::<Vec<_>>()
+                .join(", ")),
+            ast::Pat::ListFront(items, tail) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.binding(item, false))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let items = if items.is_empty() { items } else { format!("{} ", items) };
+                match tail {
+                    Some(tail) => format!("[{}.. {}]", items, self.binding(tail, false)),
+                    None => format!("[{}..]", items),
+                }
+            },
+            ast::Pat::Deconstruct(name, inner) => match (&inner.name, &*inner.pat, &inner.ty) {
+                (None, ast::Pat::Tuple(fields), None) if fields.is_empty() => format!("{}", **name),
+                _ => format!("{} {}", **name, self.binding(inner, false)),
+            },
+        }
+    }
+
+    /// Branches of a function or match, including the leading space or newline.
+    fn arms(
+        &mut self,
+        arms: &[(SrcNode<Vec<SrcNode<ast::Binding>>>, Option<SrcNode<ast::Expr>>, SrcNode<ast::Expr>)],
+        indent: usize,
+    ) -> String {
+        let arm = |this: &mut Self, (bindings, guard, body): &(SrcNode<Vec<SrcNode<ast::Binding>>>, Option<SrcNode<ast::Expr>>, SrcNode<ast::Expr>), indent| {
+            let bindings = bindings
+                .iter()
+                .map(|binding| this.binding(binding, true))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let guard = match guard {
+                Some(guard) => format!(" if {}", this.expr(guard, OPEN, indent)),
+                None => String::new(),
+            };
+            format!("{}{} => {}", bindings, guard, this.expr(body, OPEN, indent))
+        };
+
+        match arms {
+            [] => " |".to_string(),
+            [single] => format!(" {}", arm(self, single, indent)),
+            arms => {
+                let mut out = String::new();
+                for (i, a) in arms.iter().enumerate() {
+                    out += &self.line(start(&a.0), indent + 1);
+                    out += if i + 1 == arms.len() { "\\ " } else { "| " };
+                    out += &arm(self, a, indent + 1);
+                }
+                out
+            },
+        }
+    }
+
+    /// A comma-separated list of expressions between delimiters. If comments appear within the list, each element is
+    /// placed on its own line so that the comments can stay with the element that follows them.
+    fn list(&mut self, open: &str, items: &[&SrcNode<ast::Expr>], close: &str, indent: usize) -> String {
+        match items.last() {
+            Some(last) if self.comment_before(last.span().range().end) => {
+                let mut out = open.to_string();
+                for item in items {
+                    out += &self.line(start(*item), indent + 1);
+                    out += &self.expr(*item, OPEN, indent + 1);
+                    out += ",";
+                }
+                out += &format!("\n{}{}", INDENT.repeat(indent), close);
+                out
+            },
+            _ => format!("{}{}{}", open, items
+                .iter()
+                .map(|item| self.expr(*item, OPEN, indent))
+                .collect::<Vec<_>>()
+                .join(", "), close),
+        }
+    }
+
+    fn field(&mut self, (name, val): &(SrcNode<ast::Ident>, SrcNode<ast::Expr>), indent: usize) -> String {
+        match &**val {
+            // Punned fields
+            ast::Expr::Local(local) if *local == **name => format!("{}", **name),
+            _ => format!("{}: {}", **name, self.expr(val, OPEN, indent)),
+        }
+    }
+
+    fn fields(&mut self, fields: &[(SrcNode<ast::Ident>, SrcNode<ast::Expr>)], indent: usize) -> String {
+        let field = |this: &mut Self, f, indent| this.field(f, indent);
+
+        match fields.last() {
+            None => "{}".to_string(),
+            Some((_, last)) if self.comment_before(last.span().range().end) => {
+                let mut out = "{".to_string();
+                for f in fields {
+                    out += &self.line(start(&f.0), indent + 1);
+                    out += &field(self, f, indent + 1);
+                    out += ",";
+                }
+                out += &format!("\n{}}}", INDENT.repeat(indent));
+                out
+            },
+            Some(_) => format!("{{ {} }}", fields
+                .iter()
+                .map(|f| field(self, f, indent))
+                .collect::<Vec<_>>()
+                .join(", ")),
+        }
+    }
+
+    /// An expression that may be used as the target of `x -> f`.
+    fn direct(&mut self, expr: &SrcNode<ast::Expr>, indent: usize) -> String {
+        let mut args = Vec::new();
+        let mut head = expr;
+        while let ast::Expr::Apply(f, arg) = &**head {
+            if start(arg) < start(f) {
+                break;
+            }
+            args.push(arg);
+            head = f;
+        }
+
+        let head_str = self.expr(head, ATOM, indent);
+        if args.is_empty() {
+            head_str
+        } else {
+            args.reverse();
+            format!("{}{}", head_str, self.list("(", &args, ")", indent))
+        }
+    }
+
+    fn expr(&mut self, expr: &SrcNode<ast::Expr>, prec: u8, indent: usize) -> String {
+        let (s, expr_prec) = self.expr_inner(expr, indent);
+        if expr_prec < prec {
+            format!("({})", s)
+        } else {
+            s
+        }
+    }
+
+    fn expr_inner(&mut self, expr: &SrcNode<ast::Expr>, indent: usize) -> (String, u8) {
+        match &**expr {
+            ast::Expr::Error => unreachable!("Cannot format erroneous syntax"),
+            ast::Expr::Literal(ast::Literal::Str(_)) | ast::Expr::Interp(_) if self.verbatim_string(expr).is_some() => {
+                (self.verbatim_string(expr).unwrap(), ATOM)
+            },
+            ast::Expr::Literal(litr) => (literal(litr), ATOM),
+            ast::Expr::Local(local) => (format!("{}", local), ATOM),
+            ast::Expr::LangDef(_) => unreachable!("Language definitions only appear in `do` notation"),
+            ast::Expr::Tuple(items) if items.len() == 1 => (format!("({},)", self.expr(&items[0], OPEN, indent)), ATOM),
+            ast::Expr::Tuple(items) => (self.list("(", &items.iter().collect::<Vec<_>>(), ")", indent), ATOM),
+            ast::Expr::List(items, tails) if tails.is_empty() => {
+                (self.list("[", &items.iter().collect::<Vec<_>>(), "]", indent), ATOM)
+            },
+            ast::Expr::List(items, tails) => {
+                let items = items
+                    .iter()
+                    .map(|item| self.expr(item, OPEN, indent))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let items = if items.is_empty() { items } else { format!("{} ", items) };
+                let tails = tails
+                    .iter()
+                    .map(|tail| self.expr(tail, OPEN, indent))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (format!("[{}.. {}]", items, tails), ATOM)
+            },
+            ast::Expr::Record(fields) => (self.fields(fields, indent), ATOM),
+            ast::Expr::Access(record, field) => (format!("{}.{}", self.expr(record, CHAIN, indent), **field), CHAIN),
+            ast::Expr::Unary(op, inner) => match **op {
+                ast::UnaryOp::Neg => (format!("-{}", self.expr(inner, UNARY, indent)), UNARY),
+                ast::UnaryOp::Not => (format!("!{}", self.expr(inner, UNARY, indent)), UNARY),
+                ast::UnaryOp::Propagate => (format!("{}!", self.expr(inner, PROPAGATE, indent)), PROPAGATE),
+            },
+            ast::Expr::Binary(op, a, b) => {
+                let prec = match **op {
+                    ast::BinaryOp::Mul | ast::BinaryOp::Div | ast::BinaryOp::Rem => PRODUCT,
+                    ast::BinaryOp::Add | ast::BinaryOp::Sub => SUM,
+                    ast::BinaryOp::Join => JOIN,
+                    ast::BinaryOp::Eq
+                    | ast::BinaryOp::NotEq
+                    | ast::BinaryOp::Less
+                    | ast::BinaryOp::LessEq
+                    | ast::BinaryOp::More
+                    | ast::BinaryOp::MoreEq => COMPARISON,
+                    ast::BinaryOp::And | ast::BinaryOp::Or | ast::BinaryOp::Xor => LOGICAL,
+                };
+                // Binary operators are left-associative
+                (format!("{} {} {}", self.expr(a, prec, indent), **op, self.expr(b, prec + 1, indent)), prec)
+            },
+            ast::Expr::Let(bindings, body) => {
+                let bindings = bindings
+                    .iter()
+                    .map(|(binding, val)| format!("{} = {}", self.binding(binding, true), self.expr(val, OPEN, indent)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (format!("let {} in {}", bindings, self.expr(body, OPEN, indent)), OPEN)
+            },
+            ast::Expr::Match(inputs, arms) => {
+                let inputs = inputs
+                    .iter()
+                    .map(|input| self.expr(input, OPEN, indent))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (format!("match {} in{}", inputs, self.arms(arms, indent)), OPEN)
+            },
+            ast::Expr::If(..) => (self.if_(expr, indent), OPEN),
+            ast::Expr::Func(arms) => (format!("fn{}", self.arms(arms, indent)), OPEN),
+            ast::Expr::Apply(f, arg) => match (&**f, &**arg) {
+                (ast::Expr::LangDef(ast::LangDef::IoUnit), _) => (format!("return {}", self.expr(arg, OPEN, indent)), OPEN),
+                (ast::Expr::Apply(bind, _), ast::Expr::Func(_)) if matches!(&**bind, ast::Expr::LangDef(ast::LangDef::IoBind)) => {
+                    (self.do_(expr, indent), ATOM)
+                },
+                // `x -> f`
+                _ if start(arg) < start(f) => {
+                    (format!("{} -> {}", self.expr(arg, CHAIN, indent), self.direct(f, indent)), CHAIN)
+                },
+                // `f(x, y)`
+                _ => {
+                    let mut args = vec![arg];
+                    let mut head = f;
+                    while let ast::Expr::Apply(f, arg) = &**head {
+                        if start(arg) < start(f) || matches!(&**f, ast::Expr::LangDef(_)) {
+                            break;
+                        }
+                        args.push(arg);
+                        head = f;
+                    }
+                    args.reverse();
+                    let head = self.expr(head, CHAIN, indent);
+                    (format!("{}{}", head, self.list("(", &args, ")", indent)), CHAIN)
+                },
+            },
+            ast::Expr::Cons(name, inner) if is_unit(inner) => (format!("{}", **name), ATOM),
+            ast::Expr::Cons(name, inner) => (format!("{} {}", **name, self.expr(inner, OPEN, indent)), OPEN),
+            ast::Expr::ClassAccess(ty, field) => match &**ty {
+                ast::Type::Data(name, args) if args.is_empty() => (format!("{}.{}", **name, **field), ATOM),
+                _ => (format!("<{}>.{}", self.ty(ty, TY_FUNC), **field), ATOM),
+            },
+            ast::Expr::Intrinsic(name, args) if args.is_empty() => (format!("@{}", **name), ATOM),
+            ast::Expr::Intrinsic(name, args) => (format!("@{}{}", **name, self.list("(", &args.iter().collect::<Vec<_>>(), ")", indent)), ATOM),
+            ast::Expr::Update(record, fields) => {
+                (format!("{} with {}", self.expr(record, LOGICAL, indent), self.fields(fields, indent)), WITH)
+            },
+            ast::Expr::Extend(record, fields) => {
+                let fields = fields
+                    .iter()
+                    .map(|f| self.field(f, indent))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let fields = if fields.is_empty() { fields } else { format!("{} ", fields) };
+                (format!("{{ {}.. {} }}", fields, self.expr(record, OPEN, indent)), ATOM)
+            },
+            ast::Expr::Restrict(record, names) => (format!("{} without {{ {} }}", self.expr(record, LOGICAL, indent), names
+                .iter()
+                .map(|name| format!("{}", **name))
+                .collect::<Vec<_>>()
+                .join(", ")), WITH),
+            ast::Expr::Block(stmts, tail) if stmts.is_empty() => (format!("@{{ {} }}", self.expr(tail, OPEN, indent)), ATOM),
+            ast::Expr::Block(stmts, tail) => {
+                let mut out = "@{".to_string();
+                for (binding, val) in stmts {
+                    out += &self.line(binding.as_ref().map_or(start(val), start), indent + 1);
+                    out += &match binding {
+                        Some(binding) => format!("let {} = {};", self.binding(binding, true), self.expr(val, OPEN, indent + 1)),
+                        None => format!("{};", self.expr(val, OPEN, indent + 1)),
+                    };
+                }
+                out += &self.line(start(tail), indent + 1);
+                out += &self.expr(tail, OPEN, indent + 1);
+                out += &format!("\n{}}}", INDENT.repeat(indent));
+                (out, ATOM)
+            },
+            ast::Expr::Handle { expr, handlers } => {
+                let handler = |this: &mut Self, h: &ast::Handler, indent| {
+                    let state = match &h.state {
+                        Some(state) => format!(", {}", this.binding(state, true)),
+                        None => String::new(),
+                    };
+                    format!(
+                        "{}{} with {}{} => {}",
+                        *h.eff_name,
+                        this.ty_args(&h.eff_args),
+                        this.binding(&h.send, true),
+                        state,
+                        this.expr(&h.recv, OPEN, indent),
+                    )
+                };
+
+                let mut out = format!("{} handle", self.expr(expr, WITH, indent));
+                match &handlers[..] {
+                    [single] => out += &format!(" {}", handler(self, single, indent)),
+                    handlers => for (i, h) in handlers.iter().enumerate() {
+                        out += &self.line(start(&h.eff_name), indent + 1);
+                        out += if i + 1 == handlers.len() { "\\ " } else { "| " };
+                        out += &handler(self, h, indent + 1);
+                    },
+                }
+                (out, OPEN)
+            },
+            ast::Expr::Interp(parts) => {
+                let mut out = "\"".to_string();
+                for part in parts {
+                    match part {
+                        ast::InterpPart::Str(s) => out.extend(s.chars().map(|c| escape(c, '"'))),
+                        ast::InterpPart::Expr(expr, is_debug) => {
+                            let expr = self.expr(expr, OPEN, indent);
+                            out += &format!("{{{}{}}}", expr, if *is_debug { ":?" } else { "" });
+                        },
+                    }
+                }
+                out += "\"";
+                (out, ATOM)
+            },
+        }
+    }
+
+    fn if_(&mut self, expr: &SrcNode<ast::Expr>, indent: usize) -> String {
+        // Flatten `else if` chains
+        let mut conds = Vec::new();
+        let mut tail = expr;
+        while let ast::Expr::If(pred, a, b) = &**tail {
+            conds.push((self.expr(pred, OPEN, indent + 1), self.expr(a, OPEN, indent + 1)));
+            tail = b;
+        }
+        let tail = self.expr(tail, OPEN, indent + 1);
+
+        let len = conds.iter().map(|(p, a)| p.len() + a.len()).sum::<usize>() + tail.len();
+        let multiline = conds.iter().any(|(p, a)| p.contains('\n') || a.contains('\n')) || tail.contains('\n');
+        if !multiline && len < 80 {
+            conds
+                .into_iter()
+                .map(|(pred, a)| format!("if {} then {} else ", pred, a))
+                .collect::<String>() + &tail
+        } else {
+            let line = format!("\n{}", INDENT.repeat(indent + 1));
+            conds
+                .into_iter()
+                .enumerate()
+                .map(|(i, (pred, a))| format!(
+                    "{}if {}{}then {}",
+                    if i == 0 { String::new() } else { format!("{}else ", line) },
+                    pred,
+                    line,
+                    a,
+                ))
+                .collect::<String>() + &format!("{}else {}", line, tail)
+        }
+    }
+
+    fn do_(&mut self, expr: &SrcNode<ast::Expr>, indent: usize) -> String {
+        let mut out = "do {".to_string();
+        let mut tail = expr;
+        loop {
+            match &**tail {
+                ast::Expr::Apply(bind, next) => match (&**bind, &**next) {
+                    (ast::Expr::Apply(lang, rhs), ast::Expr::Func(arms))
+                        if matches!(&**lang, ast::Expr::LangDef(ast::LangDef::IoBind))
+                            && arms.len() == 1
+                            && arms[0].0.len() == 1
+                            && arms[0].1.is_none() =>
+                    {
+                        let binding = &arms[0].0[0];
+                        out += &self.line(start(binding).min(start(rhs)), indent + 1);
+                        out += &match (&binding.name, &*binding.pat, &binding.ty) {
+                            (None, ast::Pat::Wildcard, None) => format!("{};", self.expr(rhs, OPEN, indent + 1)),
+                            _ => format!("let {} <- {};", self.binding(binding, true), self.expr(rhs, OPEN, indent + 1)),
+                        };
+                        tail = &arms[0].2;
+                        continue;
+                    },
+                    // A `do` block without a final expression
+                    (ast::Expr::LangDef(ast::LangDef::IoUnit), unit) if is_unit(unit) => break,
+                    _ => {},
+                },
+                _ => {},
+            }
+            out += &self.line(start(tail), indent + 1);
+            out += &self.expr(tail, OPEN, indent + 1);
+            break;
+        }
+        out += &format!("\n{}}}", INDENT.repeat(indent));
+        out
+    }
+}
+
+fn is_unit_ty(ty: &ast::Type) -> bool { matches!(ty, ast::Type::Tuple(xs) if xs.is_empty()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(code: &str) -> String {
+        let (output, errors) = format_module(code, SrcId::empty());
+        assert!(errors.is_empty(), "{:?}", errors);
+        output.unwrap()
+    }
+
+    #[test]
+    fn idempotent() {
+        let code = r#"
+#! A module
+
+import "std.tao"
+
+# Maybe
+$[lang(maybe)]
+data Maybe A = | Just A \ None
+
+data Unit
+data Point = { x: Nat, y: Nat }
+
+class Eq < Foo =
+    => eq : Self -> Self -> Bool
+    => ne : Self -> Self -> Bool = fn x, y => !Self.eq(x, y)
+
+for A < Eq member [A] of Eq =
+    => eq = fn
+        | [], [] => True
+        | [x .. xs], [y .. ys] => x = y and xs = ys
+        \ _, _ => False
+
+## Sum the elements of a list
+fn sum A < Add A with { Output = A } : [A] -> A =
+    | [x] => x
+    # Recursive case
+    | [x .. xs] if x > 0 => x + sum(xs)
+    \ xs => xs->fold(0, fn a, b => a + b)
+
+def main = do {
+    let x <- input;
+    print(x);
+    return (let y = 5 in y * (2 + 3))
+}
+
+def nested = if x then Just 5 else if y then { a: 1, b } else @{ let a = 1; a }
+
+def greeting = "Hello, {name}! \{ {x + 1:?} \}"
+
+def rename A : { name: Str .. A } -> { title: Str .. A } = fn p => { title: p.name .. p without {name} }
+"#;
+        let first = format(code);
+        assert_eq!(first, format(&first));
+        assert!(first.contains("# Recursive case"));
+        assert!(first.contains("#! A module"));
+        assert!(first.contains("\\ xs => xs -> fold(0, fn a, b => a + b)"));
+        assert_eq!(first.matches("## Sum the elements of a list").count(), 1);
+        assert!(first.contains(r#""Hello, {name}! \{ {x + 1:?} \}""#));
+        assert!(first.contains("{ title: p.name .. p without { name } }"));
+        assert!(first.contains("{ name: Str .. A } -> { title: Str .. A }"));
+    }
+
+    #[test]
+    fn doc_comments() {
+        let code = "#! A module\n\n## Adds one\n##\n## Really\n$[inline]\ndef inc : Nat -> Nat = fn x => x + 1\n\n## Dangling\n# def foo = 5\n\ndef bar = 4\n";
+        let (module, errors) = parse_module(code, SrcId::empty());
+        assert!(errors.is_empty(), "{:?}", errors);
+        let module = module.unwrap();
+        assert_eq!(ast::docs(&module.attrs).as_deref(), Some("A module"));
+        assert_eq!(ast::docs(&module.items[0].attrs).as_deref(), Some("Adds one\n\nReally"));
+        assert_eq!(module.items[0].attrs.len(), 2);
+        assert_eq!(ast::docs(&module.items[1].attrs), None);
+        assert_eq!(format(code).matches("## Adds one").count(), 1);
+
+        // Attributes written by hand are not mistaken for documentation
+        let code = "$[doc(foo)]\ndef baz = 3\n";
+        let (module, errors) = parse_module(code, SrcId::empty());
+        assert!(errors.is_empty(), "{:?}", errors);
+        let module = module.unwrap();
+        assert_eq!(ast::docs(&module.items[0].attrs), None);
+        assert!(format(code).contains("$[doc(foo)]"));
+    }
+}
@@ -1,4 +1,4 @@
-#![feature(option_zip, trait_alias)]
+#![feature(option_zip, trait_alias, let_else)]
 
 pub mod error;
 pub mod token;
@@ -7,6 +7,7 @@ pub mod src;
 pub mod ast;
 pub mod node;
 pub mod parse;
+pub mod format;
 
 pub use crate::{
     error::{Error, ErrorKind, Pattern},
@@ -14,9 +15,10 @@ pub use crate::{
     node::{Node, SrcNode},
     src::SrcId,
     token::{Token, Op, Delimiter},
+    format::format_module,
 };
 
-use std::fmt;
+use std::{fmt, ops::Range};
 use chumsky::prelude::*;
 
 fn parse<T>(parser: impl parse::Parser<T>, code: &str, src: SrcId) -> (Option<T>, Vec<Error>) {
@@ -57,11 +59,77 @@ pub fn parse_expr(code: &str, src: SrcId) -> (Option<SrcNode<ast::Expr>>, Vec<Er
     )
 }
 
-pub fn parse_module(code: &str, src: SrcId) -> (Option<SrcNode<ast::Module>>, Vec<Error>) {
+pub fn parse_type(code: &str, src: SrcId) -> (Option<SrcNode<ast::Type>>, Vec<Error>) {
     parse(
-        parse::module_parser()
+        parse::type_parser()
+            .then_ignore(end())
             .map_with_span(SrcNode::new),
         code,
         src,
     )
 }
+
+pub fn parse_module(code: &str, src: SrcId) -> (Option<SrcNode<ast::Module>>, Vec<Error>) {
+    let (mut module, errors) = parse(
+        parse::module_parser()
+            .map_with_span(SrcNode::new),
+        code,
+        src,
+    );
+    if let Some(module) = module.as_mut() {
+        attach_docs(code, src, module);
+    }
+    (module, errors)
+}
+
+/// The lexer discards comments, so doc comments are found separately and attached to the module (`#!`) or to the item
+/// that immediately follows them (`##`) as `doc` attributes.
+fn attach_docs(code: &str, src: SrcId, module: &mut ast::Module) {
+    let chars = code.chars().collect::<Vec<_>>();
+    let is_gap = |range: Range<usize>| chars[range].iter().all(|c| c.is_whitespace());
+    let doc_line = |comment: &str, prefix: &str| {
+        let text = &comment[prefix.len()..];
+        text.strip_prefix(' ').unwrap_or(text).to_string()
+    };
+
+    let comments = token::comments(code);
+
+    let module_docs = comments
+        .iter()
+        .filter(|(_, comment)| comment.starts_with("#!"))
+        .collect::<Vec<_>>();
+    if let (Some(first), Some(last)) = (module_docs.first(), module_docs.last()) {
+        let span = Span::new(src, first.0.start..last.0.end);
+        let text = module_docs
+            .iter()
+            .map(|(_, comment)| doc_line(comment, "#!"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        module.attrs.push(SrcNode::new(ast::Attr::doc(text, span), span));
+    }
+
+    let item_docs = comments
+        .iter()
+        .filter(|(_, comment)| comment.starts_with("##"))
+        .collect::<Vec<_>>();
+    for item in module.items.iter_mut() {
+        let start = item.span.range().start;
+        // Find the run of doc comments that ends just before the item
+        let Some(last) = item_docs.iter().rposition(|(range, _)| range.end <= start) else { continue };
+        if !is_gap(item_docs[last].0.end..start) {
+            continue;
+        }
+        let mut first = last;
+        while first > 0 && is_gap(item_docs[first - 1].0.end..item_docs[first].0.start) {
+            first -= 1;
+        }
+
+        let span = Span::new(src, item_docs[first].0.start..item_docs[last].0.end);
+        let text = item_docs[first..=last]
+            .iter()
+            .map(|(_, comment)| doc_line(comment, "##"))
+            .collect::<Vec<_>>()
+            .join("\n");
+        item.attrs.insert(0, SrcNode::new(ast::Attr::doc(text, span), span));
+    }
+}
@@ -73,12 +73,20 @@ pub fn type_parser() -> impl Parser<ast::Type> {
                 .separated_by(just(Token::Comma))
                 .allow_trailing()
                 .then_ignore(just(Token::Comma).or_not())
+                .then(just(Token::Op(Op::Ellipsis))
+                    .ignore_then(ty.clone().map_with_span(SrcNode::new))
+                    .or_not())
                 .map(Some)
                 .boxed(),
             Delimiter::Brace,
             |_| None,
         )
-            .map(|tys| tys.map(ast::Type::Record).unwrap_or(ast::Type::Error));
+            .map(|tys| tys
+                .map(|(fields, tail)| match tail {
+                    Some(tail) => ast::Type::Row(fields, tail),
+                    None => ast::Type::Record(fields),
+                })
+                .unwrap_or(ast::Type::Error));
 
         let unknown = just(Token::Question)
             .map(|_| ast::Type::Unknown);
@@ -404,19 +412,23 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             .map(|x| x.map(ast::Expr::Tuple).unwrap_or(ast::Expr::Error))
             .labelled("tuple");
 
+        let field = term_ident_parser()
+            .map_with_span(SrcNode::new)
+            .then(just(Token::Colon)
+                .ignore_then(expr.clone().map_with_span(SrcNode::new))
+                .or_not())
+            .map(|(field, val)| match val {
+                Some(val) => (field, val),
+                None => {
+                    let val = SrcNode::new(ast::Expr::Local(*field), field.span());
+                    (field, val)
+                },
+            })
+            .boxed();
+
         let fields = nested_parser(
-            term_ident_parser()
-                .map_with_span(SrcNode::new)
-                .then(just(Token::Colon)
-                    .ignore_then(expr.clone().map_with_span(SrcNode::new))
-                    .or_not())
-                .map(|(field, val)| match val {
-                    Some(val) => (field, val),
-                    None => {
-                        let val = SrcNode::new(ast::Expr::Local(*field), field.span());
-                        (field, val)
-                    },
-                })
+            field
+                .clone()
                 .separated_by(just(Token::Comma))
                 .allow_trailing()
                 .map(Some)
@@ -425,11 +437,36 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             |_| None,
         );
 
-        let record = fields
-            .clone()
-            .map(|fields| fields.map(ast::Expr::Record).unwrap_or(ast::Expr::Error))
+        let record = nested_parser(
+            field
+                .separated_by(just(Token::Comma))
+                .allow_trailing()
+                .then(just(Token::Op(Op::Ellipsis))
+                    .ignore_then(expr.clone().map_with_span(SrcNode::new))
+                    .or_not())
+                .map(Some)
+                .boxed(),
+            Delimiter::Brace,
+            |_| None,
+        )
+            .map(|x| match x {
+                Some((fields, Some(tail))) => ast::Expr::Extend(tail, fields),
+                Some((fields, None)) => ast::Expr::Record(fields),
+                None => ast::Expr::Error,
+            })
             .labelled("record");
 
+        let field_names = nested_parser(
+            term_ident_parser()
+                .map_with_span(SrcNode::new)
+                .separated_by(just(Token::Comma))
+                .allow_trailing()
+                .map(Some)
+                .boxed(),
+            Delimiter::Brace,
+            |_| None,
+        );
+
         let list = nested_parser(
             expr
                 .clone()
@@ -457,10 +494,14 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             .separated_by(just(Token::Comma))
             .allow_trailing()
             .map_with_span(SrcNode::new)
+            .then(just(Token::If)
+                .ignore_then(expr.clone().map_with_span(SrcNode::new))
+                .or_not())
             .then_ignore(just(Token::Op(Op::RFlow)))
             .then(expr
                 .clone()
                 .map_with_span(SrcNode::new))
+            .map(|((bindings, guard), body)| (bindings, guard, body))
             .boxed();
 
         let pattern_branches = branches(branch)
@@ -566,7 +607,7 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
                                     SrcNode::new(ast::Expr::LangDef(ast::LangDef::IoBind), expr.span()),
                                     rhs,
                                 ), expr_span),
-                                SrcNode::new(ast::Expr::Func(SrcNode::new(vec![(SrcNode::new(vec![binding], expr.span()), expr)], expr_span)), expr_span),
+                                SrcNode::new(ast::Expr::Func(SrcNode::new(vec![(SrcNode::new(vec![binding], expr.span()), None, expr)], expr_span)), expr_span),
                             ), expr_span)
                         },
                         DoItem::Bind(binding, rhs) => {
@@ -577,7 +618,7 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
                                     SrcNode::new(ast::Expr::LangDef(ast::LangDef::IoBind), binding_span),
                                     rhs,
                                 ), binding_span),
-                                SrcNode::new(ast::Expr::Func(SrcNode::new(vec![(SrcNode::new(vec![binding], expr.span()), expr)], expr_span)), expr_span),
+                                SrcNode::new(ast::Expr::Func(SrcNode::new(vec![(SrcNode::new(vec![binding], expr.span()), None, expr)], expr_span)), expr_span),
                             ), expr_span)
                         },
                     };
@@ -633,7 +674,32 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             ))
             .map(|block| block.unwrap_or(ast::Expr::Error));
 
+        let interp_part = select! { Token::Str(s) => s }
+            .map_with_span(|s, span| ast::InterpPart::Str(SrcNode::new(s, span)))
+            .or(nested_parser(
+                expr.clone()
+                    .map_with_span(SrcNode::new)
+                    .then(just(Token::Colon)
+                        .ignore_then(just(Token::Question))
+                        .or_not())
+                    .map(|(expr, debug)| Some(ast::InterpPart::Expr(expr, debug.is_some()))),
+                Delimiter::Brace,
+                |_| None,
+            )
+                // Braces in strings are easily mistaken for literal text, so point out how to write them
+                .validate(|part, span, emit| part.unwrap_or_else(|| {
+                    emit(Error::invalid_literal(span, "this is not a valid embedded expression, use `\\{` and `\\}` for literal braces"));
+                    ast::InterpPart::Expr(SrcNode::new(ast::Expr::Error, span), false)
+                })));
+
+        let interp = just(Token::InterpOpen)
+            .ignore_then(interp_part.repeated())
+            .then_ignore(just(Token::InterpClose))
+            .map(ast::Expr::Interp)
+            .labelled("string");
+
         let atom = litr
+            .or(interp)
             .or(ident)
             .or(nested_parser(expr.clone(), Delimiter::Paren, |_| ast::Expr::Error))
             .or(tuple)
@@ -821,12 +887,25 @@ pub fn expr_parser() -> impl Parser<ast::Expr> {
             })
             .boxed();
 
+        let without = with
+            .then(just(Token::Without).ignore_then(field_names).or_not())
+            .map_with_span(|(expr, names), span| if let Some(names) = names {
+                SrcNode::new(if let Some(names) = names {
+                    ast::Expr::Restrict(expr, names)
+                } else {
+                    ast::Expr::Error
+                }, span)
+            } else {
+                expr
+            })
+            .boxed();
+
         let cons = type_ident_parser()
             .map_with_span(SrcNode::new)
             .then(expr.clone().map_with_span(SrcNode::new))
             .map(|(cons, expr)| ast::Expr::Cons(cons, expr))
             .map_with_span(SrcNode::new)
-            .or(with)
+            .or(without)
             .boxed();
 
         let binding = binding_parser().map_with_span(SrcNode::new);
@@ -1015,9 +1094,13 @@ pub fn fn_parser() -> impl Parser<ast::Def> {
         .separated_by(just(Token::Comma))
         .allow_trailing()
         .map_with_span(SrcNode::new)
+        .then(just(Token::If)
+            .ignore_then(expr_parser().map_with_span(SrcNode::new))
+            .or_not())
         .then_ignore(just(Token::Op(Op::RFlow)))
         .then(expr_parser()
             .map_with_span(SrcNode::new))
+        .map(|((bindings, guard), body)| (bindings, guard, body))
         .boxed();
 
     just(Token::Fn)
@@ -1059,9 +1142,14 @@ pub fn class_parser() -> impl Parser<ast::Class> {
     let value = term_ident_parser()
         .map_with_span(SrcNode::new)
         .then(ty_hint_parser())
-        .map(|(name, ty)| ast::ClassItem::Value {
+        .then(just(Token::Op(Op::Eq))
+            .ignore_then(expr_parser()
+                .map_with_span(SrcNode::new))
+            .or_not())
+        .map(|((name, ty), default)| ast::ClassItem::Value {
             ty: ty.unwrap_or_else(|| SrcNode::new(ast::Type::Unknown, name.span())),
             name,
+            default,
         });
 
     let assoc_type = type_ident_parser()
@@ -1200,6 +1288,7 @@ pub fn effect_alias_parser() -> impl Parser<ast::EffectAlias> {
 
 pub fn item_parser() -> impl Parser<ast::Item> {
     let attr = recursive(|attr| term_ident_parser()
+        .or(type_ident_parser())
         .map_with_span(SrcNode::new)
         .then(nested_parser(
                 attr
@@ -1209,7 +1298,7 @@ pub fn item_parser() -> impl Parser<ast::Item> {
                 |_| Vec::new(),
             )
             .or_not())
-        .map(|(name, args)| ast::Attr { name, args })
+        .map(|(name, args)| ast::Attr { name, args, doc: None })
         .map_with_span(SrcNode::new));
 
     let attrs = just(Token::Dollar)
@@ -1247,8 +1336,7 @@ pub fn item_parser() -> impl Parser<ast::Item> {
 
     attrs
         .then(item)
-        .map(|(attrs, kind)| ast::Item { attrs, kind })
-        .map_with_span(|item, span| (item, span))
+        .map_with_span(|(attrs, kind), span| (ast::Item { attrs, kind, span }, span))
         .then(tail.rewind().map(Ok).map(Some).or_else(|e| Ok(Some(Err(e)))))
         .validate(|((item, span), mut r), _, emit| {
             if let Some(Err(e)) = r.take() {
@@ -1270,6 +1358,7 @@ pub fn module_parser() -> impl Parser<ast::Module> {
             .repeated())
         .then_ignore(end())
         .map(|(imports, items)| ast::Module {
+            attrs: Vec::new(),
             imports,
             items: items.into_iter().flatten().collect(),
         })
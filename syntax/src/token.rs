@@ -1,6 +1,7 @@
 use super::*;
 use chumsky::prelude::*;
 use internment::Intern;
+use std::ops::Range;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Delimiter {
@@ -67,6 +68,9 @@ pub enum Token {
     Real(Intern<String>),
     Char(char),
     Str(Intern<String>),
+    // The quotes around an interpolated string, which contains `Str` tokens and brace-delimited embedded expressions
+    InterpOpen,
+    InterpClose,
     Open(Delimiter),
     Close(Delimiter),
     Op(Op),
@@ -102,6 +106,7 @@ pub enum Token {
     Semicolon,
     Return,
     With,
+    Without,
     Effect,
     Handle,
     Where,
@@ -117,6 +122,7 @@ impl fmt::Display for Token {
             Token::Real(x) => write!(f, "{}", x),
             Token::Char(c) => write!(f, "{}", c),
             Token::Str(s) => write!(f, "{}", s),
+            Token::InterpOpen | Token::InterpClose => write!(f, "\""),
             Token::Open(Delimiter::Paren) => write!(f, "("),
             Token::Open(Delimiter::Brack) => write!(f, "["),
             Token::Open(Delimiter::Brace) => write!(f, "{{"),
@@ -152,6 +158,7 @@ impl fmt::Display for Token {
             Token::Do => write!(f, "do"),
             Token::Return => write!(f, "return"),
             Token::With => write!(f, "with"),
+            Token::Without => write!(f, "without"),
             Token::Effect => write!(f, "effect"),
             Token::Handle => write!(f, "handle"),
             Token::Where => write!(f, "where"),
@@ -164,6 +171,279 @@ impl fmt::Display for Token {
     }
 }
 
+fn escape() -> impl Parser<char, char, Error = Error> + Clone {
+    let hex_digit = filter(|c: &char| c.is_ascii_hexdigit());
+
+    // `\u{1F600}`
+    let unicode = just('u')
+        .ignore_then(hex_digit
+            .repeated()
+            .at_least(1)
+            .at_most(6)
+            .collect::<String>()
+            .delimited_by(just('{'), just('}')))
+        .validate(|digits, span, emit| {
+            let code = u32::from_str_radix(&digits, 16).unwrap();
+            char::from_u32(code).unwrap_or_else(|| {
+                emit(Error::invalid_literal(span, format!("`{:X}` is not a unicode scalar value", code)));
+                char::REPLACEMENT_CHARACTER
+            })
+        });
+
+    // `\x41`
+    let hex = just('x')
+        .ignore_then(hex_digit
+            .repeated()
+            .exactly(2)
+            .collect::<String>())
+        .validate(|digits, span, emit| {
+            let code = u8::from_str_radix(&digits, 16).unwrap();
+            if code > 0x7F {
+                emit(Error::invalid_literal(span, format!("`\\x{}` is not an ASCII character, use `\\u{{{}}}` instead", digits, digits)));
+            }
+            code as char
+        });
+
+    just('\\')
+        .ignore_then(choice((
+            just('\\'),
+            just('/'),
+            just('"'),
+            just('\''),
+            just('{'),
+            just('}'),
+            just('0').to('\0'),
+            just('b').to('\x08'),
+            just('f').to('\x0C'),
+            just('n').to('\n'),
+            just('r').to('\r'),
+            just('t').to('\t'),
+            unicode,
+            hex,
+            // Report anything else, but keep lexing the rest of the literal
+            any().validate(|c: char, span, emit| {
+                emit(Error::invalid_literal(span, match c {
+                    'u' => format!("unicode escapes look like `\\u{{1F600}}`"),
+                    'x' => format!("hex escapes have two digits, like `\\x41`"),
+                    c => format!("`\\{}` is not a valid escape sequence", c),
+                }));
+                c
+            }),
+        )))
+        .labelled("escape sequence")
+}
+
+// The brace-delimited tokens of an expression embedded in an interpolated string
+fn embedded_expr(
+    embedded: impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone,
+) -> impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone {
+    just('{')
+        .map_with_span(|_, span| (Token::Open(Delimiter::Brace), span))
+        .then(embedded.padded().repeated().flatten())
+        .then(just('}').map_with_span(|_, span| (Token::Close(Delimiter::Brace), span)))
+        .map(|((open, mut tokens): (_, Vec<_>), close)| {
+            tokens.insert(0, open);
+            tokens.push(close);
+            tokens
+        })
+}
+
+// A brace in a string literal that doesn't delimit an embedded expression. Literal braces must be escaped, so this is
+// reported, but it's kept as text so that the rest of the literal can still be lexed.
+fn stray_brace() -> impl Parser<char, char, Error = Error> + Clone {
+    just('{')
+        .or(just('}'))
+        .validate(|c, span, emit| {
+            emit(Error::invalid_literal(span, match c {
+                '{' => "`{` does not begin a valid embedded expression, use `\\{` for a literal brace",
+                _ => "`}` does not close an embedded expression, use `\\}` for a literal brace",
+            }));
+            c
+        })
+}
+
+// The parts of a string literal: either text (with its span) or the tokens of an embedded expression
+type StrPart = Result<(String, Span), Vec<(Token, Span)>>;
+
+// Produce the tokens for a string literal, which is only interpolated if it has embedded expressions
+fn str_tokens(open: Span, parts: Vec<StrPart>, close: Span, span: Span) -> Vec<(Token, Span)> {
+    if parts.iter().all(|part| part.is_ok()) {
+        let s = parts
+            .into_iter()
+            .filter_map(|part| part.ok())
+            .map(|(s, _)| s)
+            .collect::<String>();
+        vec![(Token::Str(Intern::new(s)), span)]
+    } else {
+        let mut tokens = vec![(Token::InterpOpen, open)];
+        for part in parts {
+            match part {
+                Ok((s, span)) => tokens.push((Token::Str(Intern::new(s)), span)),
+                Err(embedded) => tokens.extend(embedded),
+            }
+        }
+        tokens.push((Token::InterpClose, close));
+        tokens
+    }
+}
+
+// A string literal. Strings that embed expressions, like `"x = {x}"`, produce an interpolated string token sequence.
+fn string(
+    embedded: impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone,
+) -> impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone {
+    let text = filter(|c| !matches!(*c, '\\' | '"' | '{' | '}'))
+        .or(escape())
+        .repeated()
+        .at_least(1)
+        .collect::<String>()
+        .map_with_span(|s, span| Ok((s, span)));
+
+    just('"')
+        .map_with_span(|_, span| span)
+        .then(text
+            .or(embedded_expr(embedded).map(Err))
+            .or(stray_brace().map_with_span(|c, span| Ok((c.to_string(), span))))
+            .repeated())
+        .then(just('"').map_with_span(|_, span| span))
+        .map_with_span(|((open, parts), close), span| str_tokens(open, parts, close, span))
+        .labelled("string")
+}
+
+// Any kind of string literal
+fn strings(
+    embedded: impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone,
+) -> impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone {
+    multiline_string(embedded.clone())
+        .or(string(embedded))
+        .or(raw_string())
+}
+
+// The most `#`s that may surround a raw string
+const MAX_RAW_HASHES: usize = 8;
+
+// A raw string, like `r"\d+"` or `r#"{ "x": 5 }"#`, which has no escapes or embedded expressions
+fn raw_string() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone {
+    let raw = |hashes: usize| just(format!("r{}\"", "#".repeat(hashes)))
+        .ignore_then(take_until(just(format!("\"{}", "#".repeat(hashes)))))
+        .map(|(s, _)| s.into_iter().collect::<String>());
+
+    (0..MAX_RAW_HASHES)
+        .rev()
+        .fold(raw(MAX_RAW_HASHES).boxed(), |p, hashes| p.or(raw(hashes)).boxed())
+        .map_with_span(|s, span| vec![(Token::Str(Intern::new(s)), span)])
+        .labelled("raw string")
+}
+
+// A multi-line string, like:
+//
+//     """
+//         Hello,
+//         world!
+//         """
+//
+// The first and last lines (which must contain nothing but the opening and closing quotes) are removed, as is the
+// indentation of the closing quotes from every other line.
+fn multiline_string(
+    embedded: impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone,
+) -> impl Parser<char, Vec<(Token, Span)>, Error = Error> + Clone {
+    // Characters are tagged with whether they came from an escape, since escaped whitespace is never stripped
+    let text = filter(|c| !matches!(*c, '\\' | '"' | '{' | '}'))
+        .map(|c| vec![(c, false)])
+        .or(escape().map(|c| vec![(c, true)]))
+        // Quotes are permitted, provided that there are fewer than three of them
+        .or(just('"')
+            .chain(just('"').or_not())
+            .then_ignore(none_of('"').rewind())
+            .map(|quotes| quotes.into_iter().map(|c| (c, false)).collect()))
+        .repeated()
+        .at_least(1)
+        .flatten()
+        .map_with_span(|chars, span| Ok((chars, span)));
+
+    just("\"\"\"")
+        .map_with_span(|_, span| span)
+        .then(text
+            .or(embedded_expr(embedded).map(Err))
+            .or(stray_brace().map_with_span(|c, span| Ok((vec![(c, false)], span))))
+            .repeated())
+        .then(just("\"\"\"").map_with_span(|_, span| span))
+        .validate(|((open, parts), close), span, emit| {
+            let parts = strip_indentation(parts, span, emit);
+            str_tokens(open, parts, close, span)
+        })
+        .labelled("multi-line string")
+}
+
+fn strip_indentation(
+    mut parts: Vec<Result<(Vec<(char, bool)>, Span), Vec<(Token, Span)>>>,
+    span: Span,
+    mut emit: impl FnMut(Error),
+) -> Vec<StrPart> {
+    let is_space = |(c, escaped): &(char, bool)| !escaped && (*c == ' ' || *c == '\t');
+    let is_newline = |(c, escaped): &(char, bool)| !escaped && *c == '\n';
+
+    // The closing quotes must be on their own line, and their indentation is stripped from every line
+    let indent = match parts.last_mut() {
+        Some(Ok((chars, _))) => match chars.iter().rposition(is_newline) {
+            Some(pos) if chars[pos + 1..].iter().all(is_space) => {
+                let indent = chars.len() - pos - 1;
+                chars.truncate(pos);
+                Some(indent)
+            },
+            _ => None,
+        },
+        _ => None,
+    };
+    let indent = indent.unwrap_or_else(|| {
+        emit(Error::invalid_literal(span, "the closing `\"\"\"` of a multi-line string must be on its own line"));
+        0
+    });
+
+    // The opening quotes must be followed by a new line
+    let is_empty = parts.len() == 1 && matches!(&parts[0], Ok((chars, _)) if chars.is_empty());
+    if !is_empty {
+        match parts.first_mut() {
+            Some(Ok((chars, _))) if chars.iter().position(|c| !is_space(c)).map_or(false, |pos| is_newline(&chars[pos])) => {
+                let pos = chars.iter().position(is_newline).unwrap();
+                chars.drain(..=pos);
+            },
+            _ => emit(Error::invalid_literal(span, "the opening `\"\"\"` of a multi-line string must be followed by a new line")),
+        }
+    }
+
+    let mut at_line_start = true;
+    let mut reported = false;
+    parts
+        .into_iter()
+        .map(|part| match part {
+            Ok((chars, span)) => {
+                let mut s = String::new();
+                let mut to_strip = indent;
+                for c in chars {
+                    if at_line_start && to_strip > 0 && is_space(&c) {
+                        to_strip -= 1;
+                        continue;
+                    } else if at_line_start && to_strip > 0 && !is_newline(&c) && !reported {
+                        emit(Error::invalid_literal(span, "a line of this multi-line string is less indented than its closing `\"\"\"`"));
+                        reported = true;
+                    }
+
+                    at_line_start = is_newline(&c);
+                    if at_line_start {
+                        to_strip = indent;
+                    }
+                    s.push(c.0);
+                }
+                Ok((s, span))
+            },
+            Err(embedded) => {
+                at_line_start = false;
+                Err(embedded)
+            },
+        })
+        .collect()
+}
+
 pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
     let real = text::int(10)
         .chain(just('.'))
@@ -219,34 +499,19 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         just(')').to(Token::Close(Delimiter::Paren)),
         just('[').to(Token::Open(Delimiter::Brack)),
         just(']').to(Token::Close(Delimiter::Brack)),
+    ));
+
+    let brace = choice((
         just('{').to(Token::Open(Delimiter::Brace)),
         just('}').to(Token::Close(Delimiter::Brace)),
     ));
 
-    let escape = just('\\')
-        .ignore_then(just('\\')
-        .or(just('/'))
-        .or(just('"'))
-        .or(just('b').to('\x08'))
-        .or(just('f').to('\x0C'))
-        .or(just('n').to('\n'))
-        .or(just('r').to('\r'))
-        .or(just('t').to('\t')));
-
     let r#char = just('\'')
-        .ignore_then(filter(|c| *c != '\\' && *c != '\'').or(escape))
+        .ignore_then(filter(|c| *c != '\\' && *c != '\'').or(escape()))
         .then_ignore(just('\''))
         .map(Token::Char)
         .labelled("character");
 
-    let string = just('"')
-        .ignore_then(filter(|c| *c != '\\' && *c != '"').or(escape).repeated())
-        .then_ignore(just('"'))
-        .collect::<String>()
-        .map(Intern::new)
-        .map(Token::Str)
-        .labelled("string");
-
     let intrinsic = just('@')
         .ignore_then(text::ident())
         .map(ast::Ident::new)
@@ -274,6 +539,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         "do" => Token::Do,
         "return" => Token::Return,
         "with" => Token::With,
+        "without" => Token::Without,
         "effect" => Token::Effect,
         "handle" => Token::Handle,
         "where" => Token::Where,
@@ -298,7 +564,7 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         .ignored()
         .repeated();
 
-    let token = choice((
+    let simple = choice((
         ctrl,
         word,
         real,
@@ -306,28 +572,130 @@ pub fn lexer() -> impl Parser<char, Vec<(Token, Span)>, Error = Error> {
         nat,
         op,
         delim,
-        string,
         r#char,
         intrinsic,
         at,
-    ))
-        .or(any()
-            .map(Token::Error)
-            .validate(|t, span, emit| {
-                emit(Error::expected_input_found(span, None, Some(t)));
-                t
-            }))
-        .map_with_span(move |token, span| (token, span))
+    ));
+
+    // The tokens of an expression embedded in an interpolated string. Braces must be balanced, since the first
+    // unmatched `}` ends the expression.
+    let embedded = recursive(|embedded| {
+        strings(embedded.clone())
+            .or(embedded_expr(embedded))
+            .or(simple.clone().map_with_span(|token, span| vec![(token, span)]))
+    });
+
+    let token = strings(embedded)
+        .or(simple
+            .or(brace)
+            .or(any()
+                .map(Token::Error)
+                .validate(|t, span, emit| {
+                    emit(Error::expected_input_found(span, None, Some(t)));
+                    t
+                }))
+            .map_with_span(move |token, span| vec![(token, span)]))
         .padded()
         .recover_with(skip_then_retry_until([]));
 
     token
         .padded_by(comments)
         .repeated()
+        .flatten()
         .padded()
         .then_ignore(end())
 }
 
+/// Find the comments in some source code, along with their character ranges.
+///
+/// The lexer discards comments, so tools that need them (such as the formatter) should use this instead. Comments are
+/// returned in order and include their leading `#` (and, for block comments, the surrounding `#(` and `)#`).
+pub fn comments(code: &str) -> Vec<(Range<usize>, String)> {
+    let chars = code.chars().collect::<Vec<_>>();
+    let mut comments = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            // Skip over literals, since they might contain `#`
+            '"' => i = skip_string(&chars, i),
+            'r' if i == 0 || !(chars[i - 1].is_alphanumeric() || chars[i - 1] == '_') => {
+                i = skip_raw_string(&chars, i).unwrap_or(i + 1);
+            },
+            '\'' => {
+                // Escapes like `'\u{1F600}'` may be longer than one character
+                let len = if chars.get(i + 1) == Some(&'\\') {
+                    chars
+                        .get(i + 3..)
+                        .and_then(|s| s.iter().position(|c| *c == '\''))
+                        .map_or(chars.len(), |pos| pos + 4)
+                } else {
+                    3
+                };
+                i += len;
+            },
+            '#' => {
+                let start = i;
+                if chars.get(i + 1) == Some(&'(') {
+                    while i < chars.len() && !(chars[i] == ')' && chars.get(i + 1) == Some(&'#')) {
+                        i += 1;
+                    }
+                    i = (i + 2).min(chars.len());
+                } else {
+                    while i < chars.len() && chars[i] != '\n' {
+                        i += 1;
+                    }
+                }
+                let text = chars[start..i].iter().collect::<String>();
+                comments.push((start..i, text.trim_end().to_string()));
+            },
+            _ => i += 1,
+        }
+    }
+    comments
+}
+
+// Find the end of the string literal starting at `i`, including any expressions embedded within it
+fn skip_string(chars: &[char], mut i: usize) -> usize {
+    let quotes = if chars.get(i..i + 3) == Some(&['"'; 3][..]) { 3 } else { 1 };
+    let is_end = |i: usize| chars.get(i..i + quotes).map_or(true, |s| s.iter().all(|c| *c == '"'));
+    i += quotes;
+    while i < chars.len() && !is_end(i) {
+        match chars[i] {
+            '\\' => i += 2,
+            '{' => {
+                let mut depth = 0;
+                i += 1;
+                while i < chars.len() && (depth > 0 || chars[i] != '}') {
+                    match chars[i] {
+                        '"' => { i = skip_string(chars, i); continue },
+                        '{' => depth += 1,
+                        '}' => depth -= 1,
+                        _ => {},
+                    }
+                    i += 1;
+                }
+                i += 1;
+            },
+            _ => i += 1,
+        }
+    }
+    i + quotes
+}
+
+// Find the end of the raw string starting at `i`, if there is one
+fn skip_raw_string(chars: &[char], i: usize) -> Option<usize> {
+    let hashes = chars[i + 1..].iter().take_while(|c| **c == '#').count();
+    if chars.get(i + 1 + hashes) != Some(&'"') {
+        return None;
+    }
+    let is_end = |j: usize| chars[j] == '"' && chars.get(j + 1..j + 1 + hashes).map_or(false, |s| s.iter().all(|c| *c == '#'));
+    let mut j = i + hashes + 2;
+    while j < chars.len() && !is_end(j) {
+        j += 1;
+    }
+    Some((j + 1 + hashes).min(chars.len()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -368,4 +736,86 @@ mod tests {
             ]),
         );
     }
+
+    #[test]
+    fn interpolation() {
+        let code = r#""a" "b{x}c{ {y: "{z}"}.y:? }""#;
+        let len = code.chars().count();
+
+        let span = |i| Span::new(SrcId::empty(), i..i + 1);
+
+        let s = |s: &str| Token::Str(Intern::new(s.to_string()));
+        assert_eq!(
+            lexer()
+                .parse(chumsky::Stream::from_iter(
+                    span(len),
+                    code.chars().enumerate().map(|(i, c)| (c, span(i))),
+                ))
+                .map(|tokens| tokens.into_iter().map(|(tok, _)| tok).collect::<Vec<_>>()),
+            Ok(vec![
+                s("a"),
+                Token::InterpOpen,
+                s("b"),
+                Token::Open(Delimiter::Brace),
+                Token::TermIdent(ast::Ident::new("x")),
+                Token::Close(Delimiter::Brace),
+                s("c"),
+                Token::Open(Delimiter::Brace),
+                Token::Open(Delimiter::Brace),
+                Token::TermIdent(ast::Ident::new("y")),
+                Token::Colon,
+                Token::InterpOpen,
+                Token::Open(Delimiter::Brace),
+                Token::TermIdent(ast::Ident::new("z")),
+                Token::Close(Delimiter::Brace),
+                Token::InterpClose,
+                Token::Close(Delimiter::Brace),
+                Token::Op(Op::Dot),
+                Token::TermIdent(ast::Ident::new("y")),
+                Token::Colon,
+                Token::Question,
+                Token::Close(Delimiter::Brace),
+                Token::InterpClose,
+            ]),
+        );
+    }
+
+    fn lex(code: &str) -> Result<Vec<Token>, Vec<Error>> {
+        let len = code.chars().count();
+        let span = |i| Span::new(SrcId::empty(), i..i + 1);
+        lexer()
+            .parse(chumsky::Stream::from_iter(
+                span(len),
+                code.chars().enumerate().map(|(i, c)| (c, span(i))),
+            ))
+            .map(|tokens| tokens.into_iter().map(|(tok, _)| tok).collect())
+    }
+
+    #[test]
+    fn string_literals() {
+        let s = |s: &str| Token::Str(Intern::new(s.to_string()));
+
+        assert_eq!(lex(r#""\u{48}\x69\0\'\{""#), Ok(vec![s("Hi\0'{")]));
+        assert_eq!(lex(r#"'\u{1F600}'"#), Ok(vec![Token::Char('😀')]));
+        assert_eq!(lex(r##"r"\d+" r#"{ "x": 5 }"#"##), Ok(vec![s(r"\d+"), s(r#"{ "x": 5 }"#)]));
+        assert_eq!(
+            lex("\"\"\"\n    Hello,\n      \"world\"\n\n    \\tbye\n    \"\"\""),
+            Ok(vec![s("Hello,\n  \"world\"\n\n\tbye")]),
+        );
+        assert_eq!(lex("\"\"\"\n  \"\"\""), Ok(vec![s("")]));
+
+        let is_invalid = |code| matches!(
+            lex(code),
+            Err(errors) if errors.iter().any(|e| matches!(e.kind(), ErrorKind::InvalidLiteral(_))),
+        );
+        assert!(is_invalid(r#""\q""#));
+        assert!(is_invalid(r#""\u{D800}""#));
+        assert!(is_invalid(r#""\xFF""#));
+        assert!(is_invalid("\"\"\"hello\n\"\"\""));
+        assert!(is_invalid("\"\"\"\n  hello\n    \"\"\""));
+        // Braces that don't delimit an embedded expression must be escaped
+        assert!(is_invalid(r#""a } b""#));
+        assert!(is_invalid(r#""a { b""#));
+        assert!(is_invalid("\"\"\"\n  }\n  \"\"\""));
+    }
 }
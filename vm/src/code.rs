@@ -1,9 +1,8 @@
 use super::*;
 use std::{
+    collections::BTreeMap,
     io::Write,
-    rc::Rc,
 };
-use im::Vector;
 
 #[derive(Clone, Debug)]
 pub enum Instr {
@@ -12,15 +11,23 @@ pub enum Instr {
     Break,
 
     Call(isize),
+    // Like `Call`, but the callee returns directly to the caller of the current function
+    TailCall(isize),
     Ret,
     // Make a function using the relative offset and by capturing the last N items on the stack
     MakeFunc(isize, usize),
     ApplyFunc,
+    // Apply a function to an argument (both on the stack), replacing the N locals of the current function with those of
+    // the callee, which returns directly to the caller of the current function
+    TailApplyFunc(usize),
+
+    MakeTuple(usize), // T * N => (T, ...)
+    IndexTuple(usize), // Nth field of tuple
+    SetTuple(usize), // Set Nth field of tuple
 
     MakeList(usize), // T * N => [T]
-    IndexList(usize), // Nth field of list/tuple
-    SkipListImm(usize), // (N..) fields of list/tuple
-    SetList(usize), // Set Nth field of list
+    IndexList(usize), // Nth item of list
+    SkipListImm(usize), // (N..) items of list
     LenList,
     JoinList,
     SkipList,
@@ -32,6 +39,9 @@ pub enum Instr {
 
     Jump(isize),
     IfNot,
+    // Pop a sum (or an integer or char) and jump to the relative offset at the index of its variant (or value) in the
+    // table, continuing with the next instruction if there is no such index
+    Switch(Box<[isize]>),
 
     Imm(Value),
     Pop(usize),
@@ -40,7 +50,8 @@ pub enum Instr {
     Dup, // Duplicate value on top of stack
     PushLocal,
     PopLocal(usize), // Don't push to stack
-    GetLocal(usize), // Duplicate value in locals position (len - 1 - N) and put on stack
+    GetLocal(usize), // Duplicate value in slot N of the current function's frame and put on stack
+    TakeLocal(usize), // Move value in slot N of the current function's frame to stack, the local must never be read again
 
     NotBool, // Bool -> Bool
     NegInt, // Int -> Int
@@ -56,6 +67,7 @@ pub enum Instr {
     EqInt, // Int -> Int -> Bool
     EqBool, // Bool -> Bool -> Bool
     EqChar, // Char -> Char -> Bool
+    EqReal, // Real -> Real -> Bool
     LessInt,
     MoreInt,
     LessEqInt,
@@ -65,6 +77,8 @@ pub enum Instr {
 
     Print,
     Input,
+    // Call the named host function with the last N items on the stack
+    CallHost(Ident, usize),
 
     // Make an effect object using the relative offset and by capturing the last N items on the stack
     MakeEffect(isize, usize),
@@ -73,15 +87,98 @@ pub enum Instr {
     Register(EffectId),
     Resume(EffectId),
     EndHandlers(usize),
+
+    // Superinstructions, each of which does the work of a common sequence of the instructions above
+
+    JumpIfNot(isize), // Bool => _, `if_not` followed by `jump`
+    TestVariant(usize), // Sum => Sum, Bool (whether the sum has the given variant)
+    TestLen(usize), // [T] => [T], Bool (whether the list has exactly N items)
+    TestLenAtLeast(usize), // [T] => [T], Bool (whether the list has at least N items)
+    PeekTuple(usize), // (T, ...) => (T, ...), T (`dup` followed by `tuple.index`)
+    PeekList(usize), // [T] => [T], T (`dup` followed by `list.index`)
+    GetLocalField(usize, usize), // `local.get` followed by `tuple.index`
 }
 
 impl Instr {
     pub fn bool(x: bool) -> Self {
-        Self::Imm(Value::Sum(x as usize, Rc::new(Value::List(Vector::new()))))
+        Self::Imm(Value::new_bool(x))
+    }
+
+    /// The name of the instruction's opcode, without any operands.
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            Instr::Error(_) => "error",
+            Instr::Nop => "nop",
+            Instr::Break => "break",
+            Instr::Imm(_) => "imm",
+            Instr::Pop(_) => "pop",
+            Instr::Replace => "replace",
+            Instr::Swap => "swap",
+            Instr::Call(_) => "call",
+            Instr::TailCall(_) => "call.tail",
+            Instr::Ret => "ret",
+            Instr::MakeFunc(_, _) => "func.make",
+            Instr::ApplyFunc => "func.apply",
+            Instr::TailApplyFunc(_) => "func.apply_tail",
+            Instr::MakeTuple(_) => "tuple.make",
+            Instr::IndexTuple(_) => "tuple.index",
+            Instr::SetTuple(_) => "tuple.set",
+            Instr::MakeList(_) => "list.make",
+            Instr::IndexList(_) => "list.index",
+            Instr::SkipListImm(_) => "list.skip_imm",
+            Instr::LenList => "list.len",
+            Instr::JoinList => "list.join",
+            Instr::SkipList => "list.skip",
+            Instr::TrimList => "list.trim",
+            Instr::MakeSum(_) => "sum.make",
+            Instr::IndexSum(_) => "sum.index",
+            Instr::VariantSum => "sum.variant",
+            Instr::Dup => "dup",
+            Instr::Jump(_) => "jump",
+            Instr::IfNot => "if_not",
+            Instr::Switch(_) => "switch",
+            Instr::PushLocal => "local.push",
+            Instr::PopLocal(_) => "local.pop",
+            Instr::GetLocal(_) => "local.get",
+            Instr::TakeLocal(_) => "local.take",
+            Instr::NotBool => "bool.not",
+            Instr::NegInt => "int.neg",
+            Instr::NegReal => "real.neg",
+            Instr::Display => "any.display",
+            Instr::Codepoint => "char.codepoint",
+            Instr::AddInt => "int.add",
+            Instr::SubInt => "int.sub",
+            Instr::MulInt => "int.mul",
+            Instr::EqInt => "int.eq",
+            Instr::EqBool => "bool.eq",
+            Instr::EqChar => "char.eq",
+            Instr::EqReal => "real.eq",
+            Instr::LessInt => "int.less",
+            Instr::MoreInt => "int.more",
+            Instr::LessEqInt => "int.less_eq",
+            Instr::MoreEqInt => "int.more_eq",
+            Instr::AndBool => "bool.and",
+            Instr::Print => "io.print",
+            Instr::Input => "io.input",
+            Instr::CallHost(_, _) => "host.call",
+            Instr::MakeEffect(_, _) => "eff.make",
+            Instr::Propagate => "eff.propagate",
+            Instr::Suspend(_) => "eff.suspend",
+            Instr::Register(_) => "eff.register",
+            Instr::Resume(_) => "eff.resume",
+            Instr::EndHandlers(_) => "eff.end_handlers",
+            Instr::JumpIfNot(_) => "jump_if_not",
+            Instr::TestVariant(_) => "sum.test_variant",
+            Instr::TestLen(_) => "list.test_len",
+            Instr::TestLenAtLeast(_) => "list.test_len_at_least",
+            Instr::PeekTuple(_) => "tuple.peek",
+            Instr::PeekList(_) => "list.peek",
+            Instr::GetLocalField(_, _) => "local.get_field",
+        }
     }
 }
 
-#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Addr(pub usize);
 
 impl Addr {
@@ -94,8 +191,27 @@ impl Addr {
 pub struct Program {
     instrs: Vec<Instr>,
     debug: Vec<(Addr, String)>,
+    // The constants section: the values of procedures that were evaluated at compile-time, which references to those
+    // procedures push with `imm` instead of calling them
+    pub(crate) constants: BTreeMap<ProcId, Value>,
     pub entry: Addr,
     pub does_io: bool,
+    /// The representation of the argument that the entry point is applied to, if it takes one (see
+    /// [`Program::from_mir_applied`]).
+    pub entry_arg: Option<Repr>,
+    /// The representation of the value produced by the entry point, if it produces one.
+    pub result_repr: Option<Repr>,
+    pub reprs: Reprs,
+    // Whether superinstructions should be emitted during lowering (see [`LowerConfig`])
+    pub(crate) superinstructions: bool,
+    // Whether pattern matches should be compiled to decision trees (see [`LowerConfig`])
+    pub(crate) decision_trees: bool,
+    // Whether calls in tail position should reuse the current frame during lowering (see [`LowerConfig`])
+    pub(crate) tail_calls: bool,
+    // Whether values should be moved out of locals at their last use during lowering (see [`LowerConfig`])
+    pub(crate) reuse: bool,
+    // The last uses of locals in the procedure currently being lowered
+    pub(crate) last_uses: reuse::LastUses,
 }
 
 impl Program {
@@ -105,13 +221,19 @@ impl Program {
 
     pub fn next_addr(&self) -> Addr { Addr(self.instrs.len()) }
 
-    pub fn instr(&self, ip: Addr) -> Instr {
-        self.instrs
-            .get(ip.0)
-            .cloned()
-            .unwrap_or(Instr::Error("out of bounds instruction"))
+    /// Find the closest debug label at or before the given address, if any.
+    pub fn label_at(&self, addr: Addr) -> Option<(Addr, &str)> {
+        let idx = self.debug.partition_point(|(a, _)| *a <= addr);
+        idx
+            .checked_sub(1)
+            .map(|idx| (self.debug[idx].0, self.debug[idx].1.as_str()))
     }
 
+    pub fn instr(&self, ip: Addr) -> Option<&Instr> { self.instrs.get(ip.0) }
+
+    /// The value of the given procedure, if it was evaluated at compile-time.
+    pub fn constant(&self, proc: ProcId) -> Option<&Value> { self.constants.get(&proc) }
+
     pub fn push(&mut self, instr: Instr) -> Addr {
         let addr = self.next_addr();
         self.instrs.push(instr);
@@ -122,29 +244,52 @@ impl Program {
         self.instrs[addr.0] = make_instr(addr.jump_to(tgt));
     }
 
+    /// Point the entries of the `switch` at the given address at the given targets.
+    pub fn fixup_switch(&mut self, addr: Addr, tgts: impl IntoIterator<Item = Addr>) {
+        self.instrs[addr.0] = Instr::Switch(tgts
+            .into_iter()
+            .map(|tgt| addr.jump_to(tgt))
+            .collect());
+    }
+
+    /// Point the branch (`jump`, `jump_if_not`, `call` or `call.tail`) at the given address to a new target.
+    pub fn fixup_branch(&mut self, addr: Addr, tgt: Addr) {
+        let offset = addr.jump_to(tgt);
+        match &mut self.instrs[addr.0] {
+            Instr::Jump(x) | Instr::JumpIfNot(x) | Instr::Call(x) | Instr::TailCall(x) => *x = offset,
+            instr => panic!("Instruction {:?} at 0x{:03X} is not a branch", instr, addr.0),
+        }
+    }
+
     pub fn write(&self, mut writer: impl Write) {
+        for (proc, value) in &self.constants {
+            writeln!(writer, "const {:?} = `{}`", proc, value).unwrap();
+        }
+
         let mut debug = self.debug.iter().peekable();
-        for addr in (0..self.instrs.len()).map(Addr) {
+        for (addr, instr) in self.instrs.iter().enumerate().map(|(i, instr)| (Addr(i), instr)) {
             while debug.peek().map_or(false, |(a, _)| *a == addr) {
                 writeln!(writer, " ...  | <--------- {}", debug.next().unwrap().1).unwrap();
             }
 
-            let instr = self.instr(addr);
-
-            let stack_diff = match instr {
+            let stack_diff = match *instr {
                 Instr::Error(_) | Instr::Nop | Instr::Break => 0,
                 Instr::Imm(_) => 1,
                 Instr::Pop(n) => -(n as isize),
                 Instr::Replace => -1,
                 Instr::Swap => 0,
                 Instr::Call(_) => 0,
+                Instr::TailCall(_) => 0,
                 Instr::Ret => 0,
                 Instr::MakeFunc(_, n) => -(n as isize),
                 Instr::ApplyFunc => 0, // Turns input stack item into output stack item
+                Instr::TailApplyFunc(_) => -1,
+                Instr::MakeTuple(n) => -(n as isize) + 1,
+                Instr::IndexTuple(_) => 0,
+                Instr::SetTuple(_) => -1,
                 Instr::MakeList(n) => -(n as isize) + 1,
                 Instr::IndexList(_) => 0,
                 Instr::SkipListImm(_) => 0,
-                Instr::SetList(_) => -1,
                 Instr::LenList => 0,
                 Instr::JoinList => -1,
                 Instr::SkipList => -1,
@@ -155,9 +300,11 @@ impl Program {
                 Instr::Dup => 1,
                 Instr::Jump(_) => 0,
                 Instr::IfNot => -1,
+                Instr::Switch(_) => -1,
                 Instr::PushLocal => -1,
                 Instr::PopLocal(_) => 0,
                 Instr::GetLocal(_) => 1,
+                Instr::TakeLocal(_) => 1,
                 Instr::NotBool
                 | Instr::NegInt
                 | Instr::NegReal
@@ -169,6 +316,7 @@ impl Program {
                 | Instr::EqInt
                 | Instr::EqBool
                 | Instr::EqChar
+                | Instr::EqReal
                 | Instr::LessInt
                 | Instr::MoreInt
                 | Instr::LessEqInt
@@ -176,30 +324,42 @@ impl Program {
                 | Instr::AndBool => -1,
                 Instr::Print => -1,
                 Instr::Input => 0,
+                Instr::CallHost(_, n) => -(n as isize) + 1,
                 Instr::MakeEffect(_, n) => -(n as isize),
                 Instr::Propagate => 0,
                 Instr::Suspend(_) => 0,
                 Instr::Register(_) => -1,
                 Instr::Resume(_) => 0,
                 Instr::EndHandlers(_) => -2,
+                Instr::JumpIfNot(_) => -1,
+                Instr::TestVariant(_)
+                | Instr::TestLen(_)
+                | Instr::TestLenAtLeast(_)
+                | Instr::PeekTuple(_)
+                | Instr::PeekList(_)
+                | Instr::GetLocalField(_, _) => 1,
             };
 
-            let instr_display = match instr {
+            let instr_display = match *instr {
                 Instr::Error(msg) => format!("error \"{}\"", msg),
                 Instr::Nop => format!("nop"),
                 Instr::Break => format!("break"),
-                Instr::Imm(x) => format!("imm `{}`", x),
+                Instr::Imm(ref x) => format!("imm `{}`", x),
                 Instr::Pop(n) => format!("pop {}", n),
                 Instr::Replace => format!("replace"),
                 Instr::Swap => format!("swap"),
                 Instr::Call(x) => format!("call {:+} (0x{:03X})", x, addr.jump(x).0),
+                Instr::TailCall(x) => format!("call.tail {:+} (0x{:03X})", x, addr.jump(x).0),
                 Instr::Ret => format!("ret"),
                 Instr::MakeFunc(i, n) => format!("func.make {:+} (0x{:03X}) {}", i, addr.jump(i).0, n),
                 Instr::ApplyFunc => format!("func.apply"),
+                Instr::TailApplyFunc(n) => format!("func.apply_tail {}", n),
+                Instr::MakeTuple(n) => format!("tuple.make {}", n),
+                Instr::IndexTuple(i) => format!("tuple.index #{}", i),
+                Instr::SetTuple(idx) => format!("tuple.set #{}", idx),
                 Instr::MakeList(n) => format!("list.make {}", n),
                 Instr::IndexList(i) => format!("list.index #{}", i),
                 Instr::SkipListImm(i) => format!("list.skip_imm #{}", i),
-                Instr::SetList(idx) => format!("list.set #{}", idx),
                 Instr::LenList => format!("list.len"),
                 Instr::JoinList => format!("list.join"),
                 Instr::SkipList => format!("list.skip"),
@@ -210,9 +370,15 @@ impl Program {
                 Instr::Dup => format!("dup"),
                 Instr::Jump(x) => format!("jump {:+} (0x{:03X})", x, addr.jump(x).0),
                 Instr::IfNot => format!("if_not"),
+                Instr::Switch(ref targets) => format!("switch [{}]", targets
+                    .iter()
+                    .map(|x| format!("{:+} (0x{:03X})", x, addr.jump(*x).0))
+                    .collect::<Vec<_>>()
+                    .join(", ")),
                 Instr::PushLocal => format!("local.push"),
                 Instr::PopLocal(n) => format!("local.pop {}", n),
-                Instr::GetLocal(x) => format!("local.get +{}", x),
+                Instr::GetLocal(slot) => format!("local.get %{}", slot),
+                Instr::TakeLocal(slot) => format!("local.take %{}", slot),
                 Instr::NotBool => format!("bool.not"),
                 Instr::NegInt => format!("int.neg"),
                 Instr::NegReal => format!("real.neg"),
@@ -224,6 +390,7 @@ impl Program {
                 Instr::EqInt => format!("int.eq"),
                 Instr::EqBool => format!("bool.eq"),
                 Instr::EqChar => format!("char.eq"),
+                Instr::EqReal => format!("real.eq"),
                 Instr::LessInt => format!("int.less"),
                 Instr::MoreInt => format!("int.more"),
                 Instr::LessEqInt => format!("int.less_eq"),
@@ -231,12 +398,20 @@ impl Program {
                 Instr::AndBool => format!("bool.and"),
                 Instr::Print => format!("io.print"),
                 Instr::Input => format!("io.input"),
+                Instr::CallHost(name, n) => format!("host.call {} {}", name, n),
                 Instr::MakeEffect(i, n) => format!("eff.make {:+} (0x{:03X}) {}", i, addr.jump(i).0, n),
                 Instr::Propagate => format!("eff.propagate"),
                 Instr::Suspend(eff) => format!("eff.suspend {:?}", eff),
                 Instr::Register(eff) => format!("eff.register {:?}", eff),
                 Instr::Resume(eff) => format!("eff.resume {:?}", eff),
                 Instr::EndHandlers(n) => format!("eff.end_handlers {}", n),
+                Instr::JumpIfNot(x) => format!("jump_if_not {:+} (0x{:03X})", x, addr.jump(x).0),
+                Instr::TestVariant(i) => format!("sum.test_variant #{}", i),
+                Instr::TestLen(n) => format!("list.test_len {}", n),
+                Instr::TestLenAtLeast(n) => format!("list.test_len_at_least {}", n),
+                Instr::PeekTuple(i) => format!("tuple.peek #{}", i),
+                Instr::PeekList(i) => format!("list.peek #{}", i),
+                Instr::GetLocalField(slot, i) => format!("local.get_field %{} #{}", slot, i),
             };
 
             writeln!(writer, "0x{:03X} | {:>+3} | {}", addr.0, stack_diff, instr_display).unwrap();
@@ -0,0 +1,319 @@
+use super::*;
+use std::{fmt, rc::Rc};
+use im::Vector;
+
+/// Tracks the state of a structural comparison between a Rust type and a Tao [`Repr`].
+pub struct ReprCheck<'a> {
+    reprs: &'a Reprs,
+    seen: Vec<ConDataId>,
+}
+
+impl<'a> ReprCheck<'a> {
+    pub fn new(reprs: &'a Reprs) -> Self {
+        Self { reprs, seen: Vec::new() }
+    }
+
+    /// Check the variants of a sum or data type with the given predicate.
+    ///
+    /// Recursive occurrences of a data type that is already being checked are assumed to match, since the outer check
+    /// will determine the result.
+    pub fn variants(&mut self, repr: &Repr, f: impl FnOnce(&mut Self, &[Repr]) -> bool) -> bool {
+        match repr {
+            Repr::Sum(variants) => f(self, variants),
+            Repr::Data(data) if self.seen.contains(data) => true,
+            Repr::Data(data) => {
+                let reprs = self.reprs;
+                match &reprs.get(*data).repr {
+                    Repr::Sum(variants) => {
+                        self.seen.push(*data);
+                        let ok = f(self, variants);
+                        self.seen.pop();
+                        ok
+                    },
+                    _ => false,
+                }
+            },
+            _ => false,
+        }
+    }
+
+    /// Check a tuple (or record, with fields ordered by name) with the given predicate.
+    pub fn fields(&mut self, repr: &Repr, n: usize, f: impl FnOnce(&mut Self, &[Repr]) -> bool) -> bool {
+        match repr {
+            Repr::Tuple(fields) if fields.len() == n => f(self, fields),
+            _ => false,
+        }
+    }
+}
+
+/// A Rust type that can be converted into a Tao value.
+pub trait IntoTao {
+    fn into_tao(self) -> Value;
+}
+
+/// A Rust type that can be converted from a Tao value.
+///
+/// Implementations must agree with the layout of the Tao type they correspond to: data types become sums with one
+/// variant per constructor (in declaration order), tuples and records become tuples (record fields ordered by name)
+/// and `Nat`, `Int`, `Real` and `Char` correspond to `u64`, `i64`, `f64` and `char` respectively. This trait can be
+/// derived for structs and enums.
+pub trait FromTao: Sized {
+    fn from_tao(val: Value) -> Option<Self>;
+
+    /// Determine whether values of the given representation can be converted into this type.
+    fn check_repr(repr: &Repr, check: &mut ReprCheck) -> bool;
+}
+
+#[derive(Debug)]
+pub enum ConvertError {
+    /// The program's result type does not correspond to the requested Rust type.
+    ReprMismatch(Option<Repr>),
+    /// The value did not have the expected layout.
+    InvalidValue,
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConvertError::ReprMismatch(Some(repr)) => write!(f, "program result has representation {:?}, which does not match the requested type", repr),
+            ConvertError::ReprMismatch(None) => write!(f, "program does not produce a result"),
+            ConvertError::InvalidValue => write!(f, "value does not have the expected layout"),
+        }
+    }
+}
+
+impl Program {
+    /// Convert a value produced by executing this program into a Rust type, checking that the type of the entry point
+    /// corresponds to it.
+    pub fn convert_result<T: FromTao>(&self, val: Value) -> Result<T, ConvertError> {
+        match &self.result_repr {
+            Some(repr) if T::check_repr(repr, &mut ReprCheck::new(&self.reprs)) => {
+                T::from_tao(val).ok_or(ConvertError::InvalidValue)
+            },
+            repr => Err(ConvertError::ReprMismatch(repr.clone())),
+        }
+    }
+}
+
+// Helpers used by implementations, including derived ones
+
+impl Value {
+    pub fn into_sum(self) -> Option<(usize, Self)> {
+        match self {
+            Value::Sum(variant, inner) => Some((variant, Rc::try_unwrap(inner).unwrap_or_else(|inner| (*inner).clone()))),
+            _ => None,
+        }
+    }
+
+    pub fn into_tuple(self) -> Option<Rc<[Self]>> {
+        match self {
+            Value::Tuple(xs) => Some(xs),
+            _ => None,
+        }
+    }
+
+    pub fn into_list(self) -> Option<Vector<Self>> {
+        match self {
+            Value::List(xs) => Some(xs),
+            _ => None,
+        }
+    }
+
+    pub fn new_sum(variant: usize, inner: Self) -> Self {
+        Value::Sum(variant, Rc::new(inner))
+    }
+
+    pub fn new_tuple(fields: Vec<Self>) -> Self {
+        Value::Tuple(fields.into())
+    }
+
+    pub fn unit() -> Self {
+        Value::new_tuple(Vec::new())
+    }
+}
+
+fn is_unit(repr: &Repr) -> bool {
+    matches!(repr, Repr::Tuple(xs) if xs.is_empty())
+}
+
+macro_rules! impl_prim {
+    ($ty:ty, $variant:ident, $prim:ident, $into:expr, $from:expr) => {
+        impl IntoTao for $ty {
+            fn into_tao(self) -> Value { Value::$variant($into(self)) }
+        }
+
+        impl FromTao for $ty {
+            fn from_tao(val: Value) -> Option<Self> {
+                if let Value::$variant(x) = val { Some($from(x)) } else { None }
+            }
+
+            fn check_repr(repr: &Repr, _: &mut ReprCheck) -> bool {
+                matches!(repr, Repr::Prim(Prim::$prim))
+            }
+        }
+    };
+}
+
+impl_prim!(u64, Int, Nat, |x| x as i64, |x| x as u64);
+impl_prim!(i64, Int, Int, |x| x, |x| x);
+impl_prim!(f64, Real, Real, |x| x, |x| x);
+impl_prim!(char, Char, Char, |x| x, |x| x);
+
+impl IntoTao for bool {
+    fn into_tao(self) -> Value { Value::new_bool(self) }
+}
+
+impl FromTao for bool {
+    fn from_tao(val: Value) -> Option<Self> {
+        match val.into_sum()? {
+            (0, _) => Some(false),
+            (1, _) => Some(true),
+            _ => None,
+        }
+    }
+
+    fn check_repr(repr: &Repr, check: &mut ReprCheck) -> bool {
+        matches!(repr, Repr::Prim(Prim::Bool))
+            || check.variants(repr, |_, variants| matches!(variants, [f, t] if is_unit(f) && is_unit(t)))
+    }
+}
+
+impl IntoTao for () {
+    fn into_tao(self) -> Value { Value::unit() }
+}
+
+impl FromTao for () {
+    fn from_tao(val: Value) -> Option<Self> {
+        val.into_tuple().filter(|xs| xs.is_empty()).map(|_| ())
+    }
+
+    fn check_repr(repr: &Repr, _: &mut ReprCheck) -> bool { is_unit(repr) }
+}
+
+impl IntoTao for String {
+    fn into_tao(self) -> Value { Value::List(self.chars().map(Value::Char).collect()) }
+}
+
+impl<'a> IntoTao for &'a str {
+    fn into_tao(self) -> Value { Value::List(self.chars().map(Value::Char).collect()) }
+}
+
+impl FromTao for String {
+    fn from_tao(val: Value) -> Option<Self> {
+        val.into_list()?
+            .into_iter()
+            .map(|c| if let Value::Char(c) = c { Some(c) } else { None })
+            .collect()
+    }
+
+    fn check_repr(repr: &Repr, _: &mut ReprCheck) -> bool {
+        matches!(repr, Repr::List(item) if **item == Repr::Prim(Prim::Char))
+    }
+}
+
+impl<T: IntoTao> IntoTao for Vec<T> {
+    fn into_tao(self) -> Value { Value::List(self.into_iter().map(T::into_tao).collect()) }
+}
+
+impl<T: FromTao> FromTao for Vec<T> {
+    fn from_tao(val: Value) -> Option<Self> {
+        val.into_list()?.into_iter().map(T::from_tao).collect()
+    }
+
+    fn check_repr(repr: &Repr, check: &mut ReprCheck) -> bool {
+        matches!(repr, Repr::List(item) if T::check_repr(item, check))
+    }
+}
+
+// `Maybe A` has the variants `Just A` and `None`, in that order
+impl<T: IntoTao> IntoTao for Option<T> {
+    fn into_tao(self) -> Value {
+        match self {
+            Some(x) => Value::new_sum(0, x.into_tao()),
+            None => Value::new_sum(1, Value::unit()),
+        }
+    }
+}
+
+impl<T: FromTao> FromTao for Option<T> {
+    fn from_tao(val: Value) -> Option<Self> {
+        match val.into_sum()? {
+            (0, inner) => Some(Some(T::from_tao(inner)?)),
+            (1, _) => Some(None),
+            _ => None,
+        }
+    }
+
+    fn check_repr(repr: &Repr, check: &mut ReprCheck) -> bool {
+        check.variants(repr, |check, variants| match variants {
+            [just, none] => T::check_repr(just, check) && is_unit(none),
+            _ => false,
+        })
+    }
+}
+
+// `Result A, E` has the variants `Ok A` and `Err E`, in that order
+impl<T: IntoTao, E: IntoTao> IntoTao for Result<T, E> {
+    fn into_tao(self) -> Value {
+        match self {
+            Ok(x) => Value::new_sum(0, x.into_tao()),
+            Err(e) => Value::new_sum(1, e.into_tao()),
+        }
+    }
+}
+
+impl<T: FromTao, E: FromTao> FromTao for Result<T, E> {
+    fn from_tao(val: Value) -> Option<Self> {
+        match val.into_sum()? {
+            (0, inner) => Some(Ok(T::from_tao(inner)?)),
+            (1, inner) => Some(Err(E::from_tao(inner)?)),
+            _ => None,
+        }
+    }
+
+    fn check_repr(repr: &Repr, check: &mut ReprCheck) -> bool {
+        check.variants(repr, |check, variants| match variants {
+            [ok, err] => T::check_repr(ok, check) && E::check_repr(err, check),
+            _ => false,
+        })
+    }
+}
+
+macro_rules! impl_tuple {
+    ($n:literal, $($T:ident),*) => {
+        #[allow(non_snake_case)]
+        impl<$($T: IntoTao),*> IntoTao for ($($T,)*) {
+            fn into_tao(self) -> Value {
+                let ($($T,)*) = self;
+                Value::new_tuple(vec![$($T.into_tao()),*])
+            }
+        }
+
+        #[allow(non_snake_case)]
+        impl<$($T: FromTao),*> FromTao for ($($T,)*) {
+            fn from_tao(val: Value) -> Option<Self> {
+                let xs = val.into_tuple()?;
+                if xs.len() != $n { return None; }
+                let mut xs = xs.iter().cloned();
+                Some(($($T::from_tao(xs.next()?)?,)*))
+            }
+
+            fn check_repr(repr: &Repr, check: &mut ReprCheck) -> bool {
+                check.fields(repr, $n, |check, fields| {
+                    let mut fields = fields.iter();
+                    $($T::check_repr(fields.next().unwrap(), check))&&*
+                })
+            }
+        }
+    };
+}
+
+// Tuple fields are ordered by name, so these impls stop before `10` would sort before `2`
+impl_tuple!(1, A);
+impl_tuple!(2, A, B);
+impl_tuple!(3, A, B, C);
+impl_tuple!(4, A, B, C, D);
+impl_tuple!(5, A, B, C, D, E);
+impl_tuple!(6, A, B, C, D, E, F);
+impl_tuple!(7, A, B, C, D, E, F, G);
+impl_tuple!(8, A, B, C, D, E, F, G, H);
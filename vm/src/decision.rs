@@ -0,0 +1,234 @@
+//! Compilation of pattern matches into decision trees.
+//!
+//! Rather than testing each arm's pattern from scratch, a decision tree tests parts of the value being matched one at
+//! a time and uses the outcome to rule out arms that can no longer match. Tests that several arms have in common (such
+//! as the variant of a sum) are therefore performed only once.
+
+use super::*;
+
+/// A step from a value to one of its parts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Step {
+    /// The Nth field of a tuple.
+    Field(usize),
+    /// The Nth item of a list.
+    Item(usize),
+    /// The items of a list after the first N.
+    Skip(usize),
+    /// The inner value of a sum with the given variant.
+    Variant(usize),
+    /// A natural number minus N.
+    Sub(u64),
+}
+
+/// A part of the value being matched, given by the steps needed to reach it from the whole value.
+pub type Occ = Vec<Step>;
+
+/// A test on part of the value being matched.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Test {
+    Variant(usize),
+    Literal(mir::Literal, Prim),
+    Len(usize),
+    LenAtLeast(usize),
+    AtLeast(u64),
+}
+
+impl Test {
+    fn of(binding: &MirNode<mir::Binding>) -> Self {
+        match &binding.pat {
+            mir::Pat::Variant(variant, _) => Test::Variant(*variant),
+            mir::Pat::Literal(litr) => match binding.meta() {
+                Repr::Prim(prim) => Test::Literal(litr.clone(), *prim),
+                _ => unreachable!("Literals that aren't primitives should have been matched structurally"),
+            },
+            mir::Pat::ListExact(items) => Test::Len(items.len()),
+            mir::Pat::ListFront(items, _) => Test::LenAtLeast(items.len()),
+            mir::Pat::Add(_, n) => Test::AtLeast(*n),
+            _ => unreachable!("Pattern should have been broken down into tests"),
+        }
+    }
+
+    // If this test succeeds, the other must fail
+    fn excludes(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Test::Variant(x), Test::Variant(y)) => x != y,
+            (Test::Literal(x, _), Test::Literal(y, _)) => x != y,
+            (Test::Len(x), Test::Len(y)) => x != y,
+            _ => false,
+        }
+    }
+
+    // Whether the test is one of a family of mutually exclusive tests, which may all be dispatched on at once
+    fn is_exclusive(&self) -> bool {
+        matches!(self, Test::Variant(_) | Test::Literal(_, _) | Test::Len(_))
+    }
+}
+
+#[derive(Debug)]
+pub enum Decision {
+    /// No arm can match. Matches are exhaustive, so this is unreachable.
+    Fail,
+    /// Take the arm with the given index. If the arm has a guard and it fails, continue with the fallback instead.
+    Arm(usize, Option<Box<Decision>>),
+    /// Perform each test on a part of the value in turn, continuing with the first that succeeds (or the default, if
+    /// none do).
+    Switch(Occ, Vec<(Test, Decision)>, Box<Decision>),
+}
+
+// An arm that might still match, along with the tests that must succeed for it to do so
+#[derive(Clone)]
+struct Row<'a> {
+    tests: Vec<(Occ, &'a MirNode<mir::Binding>)>,
+    arm: usize,
+}
+
+fn with_step(occ: &[Step], step: Step) -> Occ {
+    let mut occ = occ.to_vec();
+    occ.push(step);
+    occ
+}
+
+// Break a pattern down into the tests required to match it
+fn add_tests<'a>(tests: &mut Vec<(Occ, &'a MirNode<mir::Binding>)>, occ: Occ, binding: &'a MirNode<mir::Binding>) {
+    if !binding.is_refutable() {
+        return;
+    }
+
+    match &binding.pat {
+        mir::Pat::Single(inner) | mir::Pat::Data(_, inner) => add_tests(tests, occ, inner),
+        mir::Pat::Tuple(fields) => for (i, field) in fields.iter().enumerate() {
+            add_tests(tests, with_step(&occ, Step::Field(i)), field);
+        },
+        _ => tests.push((occ, binding)),
+    }
+}
+
+fn has_compound_literal(binding: &MirNode<mir::Binding>) -> bool {
+    match &binding.pat {
+        mir::Pat::Wildcard => false,
+        mir::Pat::Literal(_) => binding.is_refutable() && !matches!(binding.meta(), Repr::Prim(_)),
+        mir::Pat::Single(inner)
+        | mir::Pat::Add(inner, _)
+        | mir::Pat::Variant(_, inner)
+        | mir::Pat::Data(_, inner) => has_compound_literal(inner),
+        mir::Pat::Tuple(items) | mir::Pat::ListExact(items) => items.iter().any(has_compound_literal),
+        mir::Pat::ListFront(items, tail) => items.iter().any(has_compound_literal)
+            || tail.as_ref().map_or(false, has_compound_literal),
+    }
+}
+
+// Find the tests required by the parts of a pattern, once the pattern's own test has succeeded
+fn sub_tests<'a>(occ: &[Step], binding: &'a MirNode<mir::Binding>) -> Vec<(Occ, &'a MirNode<mir::Binding>)> {
+    let mut tests = Vec::new();
+    match &binding.pat {
+        mir::Pat::Variant(variant, inner) => add_tests(&mut tests, with_step(occ, Step::Variant(*variant)), inner),
+        mir::Pat::Literal(_) => {},
+        mir::Pat::ListExact(items) => for (i, item) in items.iter().enumerate() {
+            add_tests(&mut tests, with_step(occ, Step::Item(i)), item);
+        },
+        mir::Pat::ListFront(items, tail) => {
+            for (i, item) in items.iter().enumerate() {
+                add_tests(&mut tests, with_step(occ, Step::Item(i)), item);
+            }
+            if let Some(tail) = tail {
+                add_tests(&mut tests, with_step(occ, Step::Skip(items.len())), tail);
+            }
+        },
+        mir::Pat::Add(lhs, n) => add_tests(&mut tests, with_step(occ, Step::Sub(*n)), lhs),
+        _ => unreachable!("Pattern should have been broken down into tests"),
+    }
+    tests
+}
+
+// The rows that might still match once a test on a part of the value has succeeded or failed
+fn specialize<'a>(rows: &[Row<'a>], occ: &[Step], test: &Test, success: bool) -> Vec<Row<'a>> {
+    rows
+        .iter()
+        .filter_map(|row| {
+            let mut row = row.clone();
+            if let Some(idx) = row.tests.iter().position(|(o, _)| o.as_slice() == occ) {
+                let row_test = Test::of(row.tests[idx].1);
+                if row_test == *test {
+                    if !success {
+                        return None;
+                    }
+                    let (_, binding) = row.tests.remove(idx);
+                    row.tests.splice(idx..idx, sub_tests(occ, binding));
+                } else if success && row_test.excludes(test) {
+                    return None;
+                }
+                // Otherwise, the outcome of the test tells us nothing about this row, so its test is kept
+            }
+            Some(row)
+        })
+        .collect()
+}
+
+fn decide(rows: &[Row], guarded: &[bool], budget: &mut usize) -> Option<Decision> {
+    // Give up if the tree is getting too large
+    *budget = budget.checked_sub(1)?;
+
+    let first = match rows.first() {
+        Some(first) => first,
+        None => return Some(Decision::Fail),
+    };
+
+    let (occ, test) = match first.tests.first() {
+        // Every test of the first row has succeeded, so its arm is taken
+        None => return Some(Decision::Arm(first.arm, if guarded[first.arm] {
+            Some(Box::new(decide(&rows[1..], guarded, budget)?))
+        } else {
+            None
+        })),
+        Some((occ, binding)) => (occ.clone(), Test::of(binding)),
+    };
+
+    // Mutually exclusive tests on the same part of the value are dispatched on together
+    let mut tests = vec![test.clone()];
+    if test.is_exclusive() {
+        for row in rows {
+            if let Some((_, binding)) = row.tests.iter().find(|(o, _)| *o == occ) {
+                let row_test = Test::of(binding);
+                if row_test.excludes(&test) && !tests.contains(&row_test) {
+                    tests.push(row_test);
+                }
+            }
+        }
+    }
+
+    let cases = tests
+        .iter()
+        .map(|test| Some((test.clone(), decide(&specialize(rows, &occ, test, true), guarded, budget)?)))
+        .collect::<Option<Vec<_>>>()?;
+    let default = tests
+        .iter()
+        .fold(rows.to_vec(), |rows, test| specialize(&rows, &occ, test, false));
+
+    Some(Decision::Switch(occ, cases, Box::new(decide(&default, guarded, budget)?)))
+}
+
+/// Build a decision tree for the arms of a match, given as their patterns and whether they have a guard.
+///
+/// Returns `None` if the tree would have more than `budget` nodes. Decision trees can grow exponentially with the
+/// number of arms, so the caller should fall back to testing each arm in turn.
+pub fn decision_tree(arms: &[(&MirNode<mir::Binding>, bool)], mut budget: usize) -> Option<Decision> {
+    // Only literals with a primitive representation can be tested directly, so matches on other literals (such as
+    // strings) fall back to testing each arm in turn, which matches them structurally
+    if arms.iter().any(|(binding, _)| has_compound_literal(binding)) {
+        return None;
+    }
+
+    let rows = arms
+        .iter()
+        .enumerate()
+        .map(|(arm, (binding, _))| {
+            let mut tests = Vec::new();
+            add_tests(&mut tests, Vec::new(), binding);
+            Row { tests, arm }
+        })
+        .collect::<Vec<_>>();
+    let guarded = arms.iter().map(|(_, guarded)| *guarded).collect::<Vec<_>>();
+
+    decide(&rows, &guarded, &mut budget)
+}
@@ -3,7 +3,8 @@ use std::{
     fmt,
     rc::Rc,
 };
-use im::{Vector, vector};
+use im::Vector;
+use hashbrown::HashSet;
 
 #[derive(Clone, Debug)]
 pub struct Effect {
@@ -16,6 +17,7 @@ pub enum Value {
     Int(i64),
     Real(f64),
     Char(char),
+    Tuple(Rc<[Self]>),
     List(Vector<Self>),
     Func(Addr, Vector<Self>),
     Sum(usize, Rc<Self>),
@@ -25,19 +27,60 @@ pub enum Value {
 
 impl Value {
     pub fn new_bool(x: bool) -> Self {
-        Value::Sum(x as usize, Rc::new(Value::List(Vector::new())))
+        Value::Sum(x as usize, Rc::new(Value::unit()))
     }
 
     pub fn int(self) -> i64 { if let Value::Int(x) = self { x } else { panic!("{}", self) } }
     pub fn real(self) -> f64 { if let Value::Real(x) = self { x } else { panic!("{}", self) } }
     pub fn char(self) -> char { if let Value::Char(c) = self { c } else { panic!("{}", self) } }
     pub fn bool(self) -> bool { if let Value::Sum(x, _) = self { x > 0 } else { panic!("{}", self) } }
+    pub fn tuple(self) -> Rc<[Self]> { if let Value::Tuple(xs) = self { xs } else { panic!("{}", self) } }
     pub fn list(self) -> Vector<Self> { if let Value::List(xs) = self { xs } else { panic!("{}", self) } }
     pub fn func(self) -> (Addr, Vector<Self>) { if let Value::Func(f_addr, captures) = self { (f_addr, captures) } else { panic!("{}", self) } }
     pub fn sum(self) -> (usize, Rc<Self>) { if let Value::Sum(variant, inner) = self { (variant, inner) } else { panic!("{}", self) } }
     pub fn universe(self) -> u64 { if let Value::Universe(x) = self { x } else { panic!("{}", self) } }
     pub fn eff(self) -> Rc<Effect> { if let Value::Effect(eff) = self { eff } else { panic!("{}", self) } }
 
+    /// A rough estimate of the memory occupied by this value, in bytes. Structure that is shared with anything in
+    /// `seen` (the addresses of the shared allocations counted so far) isn't counted again, since it was only
+    /// allocated once.
+    pub fn size_estimate(&self, seen: &mut HashSet<usize>) -> usize {
+        std::mem::size_of::<Self>() + match self {
+            Value::Int(_) | Value::Real(_) | Value::Char(_) | Value::Universe(_) => 0,
+            Value::Tuple(fields) => if seen.insert(fields.as_ptr() as usize) {
+                fields.iter().map(|field| field.size_estimate(seen)).sum()
+            } else {
+                0
+            },
+            Value::List(items) | Value::Func(_, items) => Self::items_size_estimate(items, seen),
+            Value::Sum(_, inner) => if seen.insert(Rc::as_ptr(inner) as usize) {
+                inner.size_estimate(seen)
+            } else {
+                0
+            },
+            Value::Effect(eff) => if seen.insert(Rc::as_ptr(eff) as usize) {
+                Self::items_size_estimate(&eff.captures, seen)
+            } else {
+                0
+            },
+        }
+    }
+
+    // Vectors that share structure share the chunks that their items are stored in, so each chunk is counted only once
+    fn items_size_estimate(items: &Vector<Self>, seen: &mut HashSet<usize>) -> usize {
+        let mut size = 0;
+        let mut focus = items.focus();
+        let mut idx = 0;
+        while idx < items.len() {
+            let (range, chunk) = focus.chunk_at(idx);
+            if seen.insert(chunk.as_ptr() as usize) {
+                size += chunk.iter().map(|item| item.size_estimate(seen)).sum::<usize>();
+            }
+            idx = range.end;
+        }
+        size
+    }
+
     pub fn display(self) -> String {
         match self {
             Value::Int(x) => format!("{}", x),
@@ -54,6 +97,11 @@ impl fmt::Display for Value {
             Value::Int(x) => write!(f, "{}i", x),
             Value::Real(x) => write!(f, "{}f", x),
             Value::Char(c) => write!(f, "{}", c),
+            Value::Tuple(fields) => write!(f, "[{}]", fields
+                .iter()
+                .map(|x| format!("{}", x))
+                .collect::<Vec<_>>()
+                .join(", ")),
             Value::List(items) => match items.iter().next() {
                 Some(Value::Char(_)) => items
                     .iter()
@@ -82,35 +130,179 @@ impl fmt::Display for Value {
     }
 }
 
-pub fn exec(prog: &Program) -> Option<Value> {
+/// Limits on the resources that a program may consume during execution. The default configuration imposes no limits.
+#[derive(Clone, Debug, Default)]
+pub struct ExecConfig {
+    /// The maximum number of instructions that may be executed.
+    pub fuel: Option<u64>,
+    /// The maximum depth of the call stack.
+    pub max_call_depth: Option<usize>,
+    /// The maximum number of effect handlers that may be active at once.
+    pub max_handler_depth: Option<usize>,
+    /// The maximum estimated size, in bytes, of all live values (see [`Value::size_estimate`]).
+    pub max_memory: Option<usize>,
+}
+
+const VALUE_SIZE: usize = std::mem::size_of::<Value>();
+
+// Once memory has been measured, at least this fraction of the memory limit must be allocated before it's measured
+// again. Otherwise, a program running close to the limit would measure all of its memory after every allocation. The
+// limit may therefore be exceeded by up to this fraction before execution stops.
+const REMEASURE_FRACTION: usize = 16;
+
+// Move a value out of somewhere that will never be read again, leaving behind a value that is cheap to create
+fn take(x: &mut Value) -> Value {
+    std::mem::replace(x, Value::Int(0))
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExecError {
+    /// The program hit an error instruction.
+    Error(&'static str),
+    OutOfFuel(u64),
+    CallDepthExceeded(usize),
+    HandlerDepthExceeded(usize),
+    MemoryExceeded(usize),
+    /// The program called a host function that has not been registered.
+    NoSuchHost(Ident),
+    /// A host function returned an error.
+    Host(Ident, String),
+}
+
+impl fmt::Display for ExecError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExecError::Error(msg) => write!(f, "{}", msg),
+            ExecError::OutOfFuel(fuel) => write!(f, "program ran out of fuel after {} instructions", fuel),
+            ExecError::CallDepthExceeded(depth) => write!(f, "call depth exceeded the limit of {}", depth),
+            ExecError::HandlerDepthExceeded(depth) => write!(f, "effect handler depth exceeded the limit of {}", depth),
+            ExecError::MemoryExceeded(bytes) => write!(f, "estimated memory usage exceeded the limit of {} bytes", bytes),
+            ExecError::NoSuchHost(name) => write!(f, "no host function named `{}` was registered", name),
+            ExecError::Host(name, err) => write!(f, "host function `{}` failed: {}", name, err),
+        }
+    }
+}
+
+pub fn exec(
+    prog: &Program,
+    cfg: &ExecConfig,
+    io: &mut dyn Io,
+    hosts: &mut Hosts,
+    profiler: Option<&mut Profiler>,
+) -> Result<Option<Value>, ExecError> {
+    exec_inner(prog, cfg, io, hosts, profiler, None)
+}
+
+/// Like [`exec`], but applies the entry point of a program created with [`Program::from_mir_applied`] to the given
+/// argument.
+pub fn exec_with_arg(
+    prog: &Program,
+    cfg: &ExecConfig,
+    io: &mut dyn Io,
+    hosts: &mut Hosts,
+    profiler: Option<&mut Profiler>,
+    arg: Value,
+) -> Result<Option<Value>, ExecError> {
+    assert!(prog.entry_arg.is_some(), "Program entry point does not take an argument");
+    exec_inner(prog, cfg, io, hosts, profiler, Some(arg))
+}
+
+fn exec_inner(
+    prog: &Program,
+    cfg: &ExecConfig,
+    io: &mut dyn Io,
+    hosts: &mut Hosts,
+    mut profiler: Option<&mut Profiler>,
+    arg: Option<Value>,
+) -> Result<Option<Value>, ExecError> {
     let mut addr = prog.entry;
     let mut universe_counter = 0;
 
+    // The return address of each active call, along with the frame of the caller
     let mut funcs = Vec::new();
     let mut stack = Vec::new();
     let mut locals = if prog.does_io {
         vec![Value::Universe(universe_counter)]
     } else {
-        Vec::new()
+        arg.into_iter().collect()
     };
+    // The index in `locals` of the first slot of the current function's frame. Locals are addressed by their slot in
+    // the frame, so they keep the same address for as long as they live. The entry point's argument (if any) belongs
+    // to the frame of the function that it's applied to, not to that of the entry point.
+    let mut frame = locals.len();
     let mut handlers: Vector<(_, Value, usize)> = Vector::new();
 
+    if let Some(profiler) = profiler.as_deref_mut() {
+        profiler.start(prog, addr);
+    }
+
+    // Measuring memory means visiting every live value, so it's only done when enough might have been allocated since
+    // the last measurement to exceed the limit. `allocated` is a (generous) estimate of the bytes allocated since then,
+    // not counting the slots of the stacks themselves, which are tracked by their length.
+    let mut measured = 0;
+    let mut measured_slots = 0;
+    let mut allocated = 0;
+
     let mut tick = 0u64;
-    loop {
+    let result = loop {
         let mut next_addr = addr.incr();
 
+        if let Some(fuel) = cfg.fuel {
+            if tick >= fuel {
+                break Err(ExecError::OutOfFuel(fuel));
+            }
+        }
+        if let Some(max) = cfg.max_call_depth {
+            if funcs.len() > max {
+                break Err(ExecError::CallDepthExceeded(max));
+            }
+        }
+        if let Some(max) = cfg.max_handler_depth {
+            if handlers.len() > max {
+                break Err(ExecError::HandlerDepthExceeded(max));
+            }
+        }
+        if let Some(max_memory) = cfg.max_memory {
+            let slots = stack.len() + locals.len();
+            let unmeasured = allocated + slots.saturating_sub(measured_slots) * VALUE_SIZE;
+            if measured + unmeasured > max_memory && unmeasured >= max_memory / REMEASURE_FRACTION {
+                let mut seen = HashSet::new();
+                measured = stack
+                    .iter()
+                    .chain(locals.iter())
+                    .chain(handlers.iter().map(|(_, handler, _)| handler))
+                    .map(|x| x.size_estimate(&mut seen))
+                    .sum::<usize>();
+                measured_slots = slots;
+                allocated = 0;
+                if measured > max_memory {
+                    break Err(ExecError::MemoryExceeded(max_memory));
+                }
+            }
+        }
+
         // println!("Executing 0x{:03X}... Stack: {}", addr.0, stack.iter().rev().map(|x| format!("{}", x)).collect::<Vec<_>>().join(", "));
 
-        match prog.instr(addr) {
-            Instr::Error(err) => panic!("Error: {}", err),
+        // Instructions are borrowed rather than cloned, since many of them (`imm`, for example) are expensive to clone
+        let instr = match prog.instr(addr) {
+            Some(instr) => instr,
+            None => break Err(ExecError::Error("out of bounds instruction")),
+        };
+
+        if let Some(profiler) = profiler.as_deref_mut() {
+            profiler.tick(instr);
+        }
+
+        match *instr {
+            Instr::Error(err) => break Err(ExecError::Error(err)),
             Instr::Nop => {},
             Instr::Break => {
-                println!("Breakpoint at 0x{:03X?}", addr.0);
+                io.print(&format!("Breakpoint at 0x{:03X?}", addr.0));
                 for (i, x) in stack.iter().rev().enumerate() {
-                    println!("{:02} | {:?}", i, x);
+                    io.print(&format!("{:02} | {:?}", i, x));
                 }
             },
-            Instr::Imm(x) => stack.push(x.clone()),
+            Instr::Imm(ref x) => stack.push(x.clone()),
             Instr::Pop(n) => {
                 assert!(n > 0, "Popped zero items, this is probably a bug");
                 stack.truncate(stack.len().saturating_sub(n));
@@ -127,38 +319,104 @@ pub fn exec(prog: &Program) -> Option<Value> {
                 stack.push(y);
             },
             Instr::Call(n) => {
-                funcs.push(next_addr);
+                funcs.push((next_addr, frame));
+                frame = locals.len();
                 next_addr = addr.jump(n);
+                if let Some(profiler) = profiler.as_deref_mut() {
+                    profiler.enter(prog, next_addr);
+                }
+            },
+            Instr::TailCall(n) => {
+                next_addr = addr.jump(n);
+                if let Some(profiler) = profiler.as_deref_mut() {
+                    profiler.exit();
+                    profiler.enter(prog, next_addr);
+                }
             },
-            Instr::Ret => if let Some(addr) = funcs.pop() {
+            Instr::Ret => if let Some((addr, caller_frame)) = funcs.pop() {
                 next_addr = addr;
+                frame = caller_frame;
+                if let Some(profiler) = profiler.as_deref_mut() {
+                    profiler.exit();
+                }
             } else {
                 assert_eq!(locals.len(), 0, "Local stack still has values, this is probably a bug");
                 assert_eq!(stack.len(), 1, "Stack size must be 1 on program exit");
-                println!("Executed {} instructions.", tick);
-                break if prog.does_io {
-                    let mut r = stack.pop().unwrap().list();
-                    assert_eq!(r.remove(0).universe(), universe_counter);
+                break Ok(if prog.does_io {
+                    let r = stack.pop().unwrap().tuple();
+                    assert_eq!(r[0].clone().universe(), universe_counter);
                     None
                 } else {
                     stack.pop()
-                };
+                });
             },
             Instr::MakeFunc(i, n) => {
                 let f_addr = addr.jump(i);
                 let func = Value::Func(f_addr, stack.split_off(stack.len().saturating_sub(n)).into());
+                allocated += n * VALUE_SIZE;
                 stack.push(func);
             },
             Instr::ApplyFunc => {
-                let (f_addr, mut captures) = stack.pop().unwrap().func();
+                let (f_addr, captures) = stack.pop().unwrap().func();
+
+                funcs.push((next_addr, frame));
+                next_addr = f_addr;
+                if let Some(profiler) = profiler.as_deref_mut() {
+                    profiler.enter(prog, next_addr);
+                }
+
+                // The argument has already been pushed, and is the first slot of the callee's frame
+                frame = locals.len() - 1;
+                locals.extend(captures.into_iter());
+            },
+            Instr::TailApplyFunc(n) => {
+                let arg = stack.pop().unwrap();
+                let (f_addr, captures) = stack.pop().unwrap().func();
 
-                funcs.push(next_addr);
                 next_addr = f_addr;
+                if let Some(profiler) = profiler.as_deref_mut() {
+                    profiler.exit();
+                    profiler.enter(prog, next_addr);
+                }
 
+                // The callee's frame takes the place of the current one
+                locals.truncate(locals.len() - n);
+                debug_assert_eq!(locals.len(), frame, "Tail application must replace the whole frame");
+                locals.push(arg);
                 locals.extend(captures.into_iter());
             },
+            Instr::MakeTuple(n) => {
+                let val = Value::Tuple(stack.split_off(stack.len().saturating_sub(n)).into());
+                allocated += n * VALUE_SIZE;
+                stack.push(val);
+            },
+            Instr::IndexTuple(i) => {
+                let mut x = stack.pop().unwrap().tuple();
+                // If nothing else refers to the tuple, the field can be moved out of it rather than copied
+                let field = match Rc::get_mut(&mut x) {
+                    Some(fields) => take(&mut fields[i]),
+                    None => x[i].clone(),
+                };
+                stack.push(field);
+            },
+            Instr::SetTuple(idx) => {
+                let item = stack.pop().unwrap();
+                let mut xs = stack.pop().unwrap().tuple();
+                // If nothing else refers to the tuple, it can be updated in place
+                match Rc::get_mut(&mut xs) {
+                    Some(fields) => fields[idx] = item,
+                    None => {
+                        allocated += xs.len() * VALUE_SIZE;
+                        let mut fields = xs.to_vec();
+                        fields[idx] = item;
+                        xs = fields.into();
+                    },
+                }
+                stack.push(Value::Tuple(xs));
+            },
             Instr::MakeList(n) => {
                 let val = Value::List(stack.split_off(stack.len().saturating_sub(n)).into());
+                allocated += n * VALUE_SIZE;
                 stack.push(val);
             },
             Instr::IndexList(i) => {
@@ -172,19 +430,14 @@ pub fn exec(prog: &Program) -> Option<Value> {
                 let x = stack.pop().unwrap().list();
                 stack.push(Value::List(x.skip(i)));
             },
-            Instr::SetList(idx) => {
-                let item = stack.pop().unwrap();
-                let mut xs = stack.pop().unwrap().list();
-                xs[idx] = item;
-                stack.push(Value::List(xs));
-            },
             Instr::LenList => {
                 let len = stack.pop().unwrap().list().len();
                 stack.push(Value::Int(len as i64));
             },
             Instr::JoinList => {
-                let mut y = stack.pop().unwrap().list();
+                let y = stack.pop().unwrap().list();
                 let mut x = stack.pop().unwrap().list();
+                allocated += (x.len() + y.len()) * VALUE_SIZE;
                 x.append(y);
                 stack.push(Value::List(x));
             },
@@ -201,12 +454,13 @@ pub fn exec(prog: &Program) -> Option<Value> {
             },
             Instr::MakeSum(variant) => {
                 let x = stack.pop().unwrap();
+                allocated += VALUE_SIZE;
                 stack.push(Value::Sum(variant, Rc::new(x)));
             },
             Instr::IndexSum(variant) => {
                 let (v, inner) = stack.pop().unwrap().sum();
                 debug_assert_eq!(variant, v);
-                stack.push((*inner).clone());
+                stack.push(Rc::try_unwrap(inner).unwrap_or_else(|inner| (*inner).clone()));
             },
             Instr::VariantSum => {
                 let (variant, _) = stack.pop().unwrap().sum();
@@ -221,9 +475,21 @@ pub fn exec(prog: &Program) -> Option<Value> {
                     next_addr = next_addr.jump(1);
                 }
             },
+            Instr::Switch(ref targets) => {
+                let idx = match stack.pop().unwrap() {
+                    Value::Sum(variant, _) => variant,
+                    Value::Int(x) => usize::try_from(x).unwrap_or(usize::MAX),
+                    Value::Char(c) => c as usize,
+                    x => panic!("{}", x),
+                };
+                if let Some(offset) = targets.get(idx) {
+                    next_addr = addr.jump(*offset);
+                }
+            },
             Instr::PushLocal => locals.push(stack.pop().unwrap()),
             Instr::PopLocal(n) => locals.truncate(locals.len() - n),
-            Instr::GetLocal(x) => stack.push(locals[locals.len() - 1 - x].clone()),
+            Instr::GetLocal(slot) => stack.push(locals[frame + slot].clone()),
+            Instr::TakeLocal(slot) => stack.push(take(&mut locals[frame + slot])),
             Instr::NotBool => {
                 let x = stack.pop().unwrap().bool();
                 stack.push(Value::new_bool(!x))
@@ -238,6 +504,7 @@ pub fn exec(prog: &Program) -> Option<Value> {
             },
             Instr::Display => {
                 let s = stack.pop().unwrap().display();
+                allocated += s.len() * VALUE_SIZE;
                 stack.push(Value::List(s.chars().map(Value::Char).collect()))
             },
             Instr::Codepoint => {
@@ -274,6 +541,11 @@ pub fn exec(prog: &Program) -> Option<Value> {
                 let x = stack.pop().unwrap().char();
                 stack.push(Value::new_bool(x == y))
             },
+            Instr::EqReal => {
+                let y = stack.pop().unwrap().real();
+                let x = stack.pop().unwrap().real();
+                stack.push(Value::new_bool(x == y))
+            },
             Instr::LessInt => {
                 let y = stack.pop().unwrap().int();
                 let x = stack.pop().unwrap().int();
@@ -304,40 +576,51 @@ pub fn exec(prog: &Program) -> Option<Value> {
                 let universe = stack.pop().unwrap().universe();
                 assert!(universe == universe_counter, "Universe forked, the thread of prophecy has been broken");
                 universe_counter += 1;
-                println!("{}", s.into_iter().map(|c| c.char()).collect::<String>());
+                io.print(&s.into_iter().map(|c| c.char()).collect::<String>());
                 stack.push(Value::Universe(universe_counter))
             },
             Instr::Input => {
-                use std::io::{stdin, stdout, Write};
-
                 let universe = stack.pop().unwrap().universe();
                 assert!(universe == universe_counter, "Universe forked, the thread of prophecy has been broken");
                 universe_counter += 1;
 
-                let mut s = String::new();
-                print!("> ");
-                stdout().flush().expect("IO error");
-                stdin().read_line(&mut s).expect("IO error");
+                let s = io.input();
+                allocated += (s.len() + 2) * VALUE_SIZE;
 
-                stack.push(Value::List(vector![
+                stack.push(Value::new_tuple(vec![
                     Value::Universe(universe_counter),
-                    Value::List(s.trim_end().chars().map(Value::Char).collect()),
+                    Value::List(s.chars().map(Value::Char).collect()),
                 ]));
             },
+            Instr::CallHost(name, n) => {
+                let args = stack.split_off(stack.len().saturating_sub(n));
+                match hosts.call(name, args) {
+                    Ok(val) => {
+                        allocated += val.size_estimate(&mut HashSet::new());
+                        stack.push(val);
+                    },
+                    Err(err) => break Err(err),
+                }
+            },
             Instr::MakeEffect(i, n) => {
                 let addr = addr.jump(i);
                 let func = Value::Effect(Rc::new(Effect {
                     addr,
                     captures: stack.split_off(stack.len().saturating_sub(n)).into(),
                 }));
+                allocated += (n + 1) * VALUE_SIZE;
                 stack.push(func);
             },
             Instr::Propagate => {
                 let eff = stack.pop().unwrap().eff();
 
-                funcs.push(next_addr);
+                funcs.push((next_addr, frame));
                 next_addr = eff.addr;
+                if let Some(profiler) = profiler.as_deref_mut() {
+                    profiler.enter(prog, next_addr);
+                }
 
+                frame = locals.len();
                 locals.extend(eff.captures.iter().cloned());
             },
             Instr::Suspend(eff_id) => {
@@ -350,27 +633,34 @@ pub fn exec(prog: &Program) -> Option<Value> {
                         eff_id,
                         handlers,
                     ));
-                let (f_addr, mut captures) = handler.1.clone().func();
+                let (f_addr, captures) = handler.1.clone().func();
 
-                funcs.push(next_addr);
+                funcs.push((next_addr, frame));
                 next_addr = f_addr;
+                if let Some(profiler) = profiler.as_deref_mut() {
+                    profiler.enter(prog, next_addr);
+                }
 
-                locals.push(stack[handler.2].clone()); // Push effect
+                // The value sent by the effect has already been pushed, and is the first slot of the handler's frame
+                frame = locals.len() - 1;
+                locals.push(stack[handler.2].clone()); // Push state
                 locals.extend(captures.into_iter());
             },
             Instr::Register(eff_id) => {
                 let handler = stack.pop().unwrap();
                 // let state = stack.pop().unwrap();
+                allocated += VALUE_SIZE;
                 handlers.push_back((eff_id, handler, stack.len() - 1));
             },
             Instr::EndHandlers(n) => {
                 let out = stack.pop().unwrap();
                 let state = stack.pop().unwrap();
                 handlers.truncate(handlers.len() - n);
-                stack.push(Value::List([out, state].into_iter().collect()));
+                allocated += 2 * VALUE_SIZE;
+                stack.push(Value::new_tuple(vec![out, state]));
             },
             Instr::Resume(eff_id) => {
-                let out_and_state = stack.pop().unwrap().list();
+                let out_and_state = stack.pop().unwrap().tuple();
                 let out = out_and_state[0].clone();
                 let state = out_and_state[1].clone();
 
@@ -384,10 +674,51 @@ pub fn exec(prog: &Program) -> Option<Value> {
 
                 stack.push(out);
             },
+            Instr::JumpIfNot(n) => {
+                if !stack.pop().unwrap().bool() {
+                    next_addr = addr.jump(n);
+                }
+            },
+            Instr::TestVariant(variant) => {
+                let is_variant = matches!(stack.last().unwrap(), Value::Sum(v, _) if *v == variant);
+                stack.push(Value::new_bool(is_variant));
+            },
+            Instr::TestLen(n) => {
+                let is_len = matches!(stack.last().unwrap(), Value::List(xs) if xs.len() == n);
+                stack.push(Value::new_bool(is_len));
+            },
+            Instr::TestLenAtLeast(n) => {
+                let is_len = matches!(stack.last().unwrap(), Value::List(xs) if xs.len() >= n);
+                stack.push(Value::new_bool(is_len));
+            },
+            Instr::PeekTuple(i) => {
+                let x = match stack.last().unwrap() {
+                    Value::Tuple(xs) => xs[i].clone(),
+                    x => panic!("{}", x),
+                };
+                stack.push(x);
+            },
+            Instr::PeekList(i) => {
+                let x = match stack.last().unwrap() {
+                    Value::List(xs) => xs[i].clone(),
+                    x => panic!("{}", x),
+                };
+                stack.push(x);
+            },
+            Instr::GetLocalField(slot, i) => match &locals[frame + slot] {
+                Value::Tuple(xs) => stack.push(xs[i].clone()),
+                x => panic!("{}", x),
+            },
         }
 
         tick += 1;
 
         addr = next_addr;
+    };
+
+    if let Some(profiler) = profiler {
+        profiler.finish();
     }
+
+    result
 }
@@ -0,0 +1,50 @@
+use super::*;
+
+pub type HostFn = Box<dyn FnMut(Vec<Value>) -> Result<Value, String>>;
+
+struct HostDecl {
+    ty: String,
+    func: HostFn,
+}
+
+/// A registry of functions provided by the host application.
+///
+/// Each function is registered with a name and a (non-generic) Tao type, written in Tao's type syntax. Tao code calls
+/// the function as an intrinsic, passing one argument for each of the type's parameters: a function registered as
+/// `get` with type `Str -> Nat -> Maybe Str` is called as `@get(key, n)`. Functions with side effects should take and
+/// return the universe (`@`), like the built-in I/O intrinsics, so that their calls are not reordered or removed.
+#[derive(Default)]
+pub struct Hosts {
+    fns: HashMap<Ident, HostDecl>,
+}
+
+impl Hosts {
+    /// Register a host function. Arguments are passed in the order they were given and the returned value must match
+    /// the declared output type. Returning an error aborts execution.
+    pub fn register(
+        &mut self,
+        name: &str,
+        ty: impl ToString,
+        func: impl FnMut(Vec<Value>) -> Result<Value, String> + 'static,
+    ) -> &mut Self {
+        self.fns.insert(Ident::new(name), HostDecl {
+            ty: ty.to_string(),
+            func: Box::new(func),
+        });
+        self
+    }
+
+    /// Iterate over the names and declared types of every registered function.
+    pub fn decls(&self) -> impl Iterator<Item = (Ident, &str)> + '_ {
+        self.fns
+            .iter()
+            .map(|(name, decl)| (*name, decl.ty.as_str()))
+    }
+
+    pub(crate) fn call(&mut self, name: Ident, args: Vec<Value>) -> Result<Value, ExecError> {
+        let decl = self.fns
+            .get_mut(&name)
+            .ok_or(ExecError::NoSuchHost(name))?;
+        (decl.func)(args).map_err(|err| ExecError::Host(name, err))
+    }
+}
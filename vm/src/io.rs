@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+
+/// The interface through which a running program talks to the outside world (`Instr::Print` and `Instr::Input`).
+pub trait Io {
+    /// Output a line of text.
+    fn print(&mut self, line: &str);
+
+    /// Read a line of text, without the trailing newline.
+    fn input(&mut self) -> String;
+}
+
+/// I/O using the process' stdin and stdout.
+#[derive(Default)]
+pub struct StdIo;
+
+impl Io for StdIo {
+    fn print(&mut self, line: &str) {
+        println!("{}", line);
+    }
+
+    fn input(&mut self) -> String {
+        use std::io::{stdin, stdout, Write};
+
+        let mut s = String::new();
+        print!("> ");
+        stdout().flush().expect("IO error");
+        stdin().read_line(&mut s).expect("IO error");
+        s.trim_end().to_string()
+    }
+}
+
+/// I/O that reads from a pre-determined list of input lines and captures everything the program prints, without
+/// touching the process' stdio. Once the input lines are exhausted, further input produces empty lines.
+#[derive(Default)]
+pub struct CapturedIo {
+    pub input: VecDeque<String>,
+    pub output: Vec<String>,
+}
+
+impl CapturedIo {
+    pub fn new(input: impl IntoIterator<Item = String>) -> Self {
+        Self {
+            input: input.into_iter().collect(),
+            output: Vec::new(),
+        }
+    }
+}
+
+impl Io for CapturedIo {
+    fn print(&mut self, line: &str) {
+        self.output.push(line.to_string());
+    }
+
+    fn input(&mut self) -> String {
+        self.input.pop_front().unwrap_or_default()
+    }
+}
@@ -1,17 +1,31 @@
 pub mod code;
+pub mod convert;
+pub mod decision;
 pub mod exec;
+pub mod host;
+pub mod io;
 pub mod lower;
+pub mod profile;
+pub mod reuse;
 
 pub use crate::{
     code::{Instr, Program, Addr},
-    exec::{exec, Value},
+    convert::{IntoTao, FromTao, ReprCheck, ConvertError},
+    exec::{exec, exec_with_arg, Value, ExecConfig, ExecError},
+    io::{Io, StdIo, CapturedIo},
+    lower::{litr_to_value, LowerConfig},
+    host::{Hosts, HostFn},
+    profile::Profiler,
 };
+pub use tao_middle::{Repr, Reprs};
+pub use tao_vm_derive::{IntoTao, FromTao};
 use tao_middle::{
     mir,
     Context as MirContext,
     MirNode,
     ProcId,
-    repr,
+    repr::{self, Prim},
+    ConDataId,
     Ident,
     EffectId,
 };
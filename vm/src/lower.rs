@@ -4,7 +4,7 @@ use std::{
     rc::Rc,
 };
 
-fn litr_to_value(literal: &mir::Literal) -> Option<Value> {
+pub fn litr_to_value(literal: &mir::Literal) -> Option<Value> {
     Some(match literal {
         mir::Literal::Never => return None, // Evaluating a `Never` is UB anyway, so who cares what it generates?
         mir::Literal::Unknown(x) => *x,
@@ -12,7 +12,7 @@ fn litr_to_value(literal: &mir::Literal) -> Option<Value> {
         mir::Literal::Int(x) => Value::Int(*x),
         mir::Literal::Real(x) => Value::Real(*x),
         mir::Literal::Char(c) => Value::Char(*c),
-        mir::Literal::Tuple(fields) => Value::List(fields
+        mir::Literal::Tuple(fields) => Value::Tuple(fields
             .iter()
             .map(litr_to_value)
             .collect::<Option<_>>()?),
@@ -25,7 +25,135 @@ fn litr_to_value(literal: &mir::Literal) -> Option<Value> {
     })
 }
 
+/// Options that control how MIR is lowered to bytecode.
+#[derive(Clone, Debug)]
+pub struct LowerConfig {
+    /// Emit superinstructions in place of common instruction sequences. Disabling this produces slower code, but is
+    /// useful for measuring their benefit.
+    pub superinstructions: bool,
+    /// Compile pattern matches with more than one arm to decision trees, rather than testing each arm in turn.
+    pub decision_trees: bool,
+    /// Reuse the current function's frame for calls in tail position, so that recursion in tail position runs in
+    /// constant space.
+    pub tail_calls: bool,
+    /// Move values out of locals at their last use (see [`reuse`]), so that values that nothing else refers to can be
+    /// updated in place.
+    pub reuse: bool,
+}
+
+impl Default for LowerConfig {
+    fn default() -> Self {
+        Self {
+            superinstructions: true,
+            decision_trees: true,
+            tail_calls: true,
+            reuse: true,
+        }
+    }
+}
+
+// Decision trees that grow beyond this many nodes per arm are abandoned in favour of testing each arm in turn
+const DECISION_BUDGET_PER_ARM: usize = 32;
+
+// Integers and chars at or above this are not dispatched on with a jump table, since the table would be too large
+const SWITCH_TABLE_LIMIT: usize = 128;
+
+// The index of the jump table entry that the given test corresponds to, if it can be performed with a `switch`
+fn switch_idx(test: &decision::Test) -> Option<usize> {
+    use decision::Test;
+    let idx = match test {
+        Test::Variant(variant) => return Some(*variant),
+        Test::Literal(mir::Literal::Nat(x), _) => usize::try_from(*x).ok()?,
+        Test::Literal(mir::Literal::Int(x), _) => usize::try_from(*x).ok()?,
+        Test::Literal(mir::Literal::Char(c), _) => *c as usize,
+        Test::Literal(mir::Literal::Sum(variant, _), _) => *variant,
+        _ => return None,
+    };
+    Some(idx).filter(|idx| *idx < SWITCH_TABLE_LIMIT)
+}
+
+// [.., T, T] -> [.., Bool]
+fn eq_prim(prim: Prim) -> Instr {
+    match prim {
+        Prim::Nat | Prim::Int => Instr::EqInt,
+        Prim::Real => Instr::EqReal,
+        Prim::Char => Instr::EqChar,
+        Prim::Bool => Instr::EqBool,
+        Prim::Universe => unreachable!("Universes cannot be compared"),
+    }
+}
+
+// The kind of value that the items of a pattern are found within
+#[derive(Copy, Clone)]
+enum Items {
+    Tuple,
+    List,
+    // The pattern has a single item, which is the value itself
+    Whole,
+}
+
+// The slot of a local in the frame of the function being compiled, where `stack` holds the locals of the frame in the
+// order that they were pushed
+fn local_slot(stack: &[mir::Local], local: mir::Local) -> usize {
+    stack
+        .iter()
+        .rposition(|name| *name == local)
+        .unwrap_or_else(|| panic!("Tried to find local ${}, but it was not found. Stack: {:?}", local.0, stack))
+}
+
 impl Program {
+    // [.., Bool] -> [..]
+    // Returns the address of a branch, to be fixed up with `fixup_branch`, that is taken if the boolean is false
+    fn branch_if_not(&mut self) -> Addr {
+        if self.superinstructions {
+            self.push(Instr::JumpIfNot(0))
+        } else {
+            self.push(Instr::IfNot);
+            self.push(Instr::Jump(0))
+        }
+    }
+
+    // [.., T] -> [.., T, U] where U is the Nth item of T
+    fn peek_item(&mut self, items: Items, i: usize) {
+        match (items, self.superinstructions) {
+            (Items::Tuple, true) => { self.push(Instr::PeekTuple(i)); },
+            (Items::List, true) => { self.push(Instr::PeekList(i)); },
+            (Items::Tuple, false) => {
+                self.push(Instr::Dup);
+                self.push(Instr::IndexTuple(i));
+            },
+            (Items::List, false) => {
+                self.push(Instr::Dup);
+                self.push(Instr::IndexList(i));
+            },
+            (Items::Whole, _) => { self.push(Instr::Dup); },
+        }
+    }
+
+    // [.., Sum] -> [.., Sum, Bool]
+    fn test_variant(&mut self, variant: usize) {
+        if self.superinstructions {
+            self.push(Instr::TestVariant(variant));
+        } else {
+            self.push(Instr::Dup);
+            self.push(Instr::VariantSum);
+            self.push(Instr::Imm(Value::Int(variant as i64)));
+            self.push(Instr::EqInt);
+        }
+    }
+
+    // [.., [T]] -> [.., [T], Bool]
+    fn test_len(&mut self, len: usize, exact: bool) {
+        if self.superinstructions {
+            self.push(if exact { Instr::TestLen(len) } else { Instr::TestLenAtLeast(len) });
+        } else {
+            self.push(Instr::Dup);
+            self.push(Instr::LenList);
+            self.push(Instr::Imm(Value::Int(len as i64)));
+            self.push(if exact { Instr::EqInt } else { Instr::MoreEqInt });
+        }
+    }
+
     // [.., T] => [..]
     pub fn compile_extractor(&mut self, mir: &MirContext, binding: &MirNode<mir::Binding>) {
         // The total number of locals this pattern produces
@@ -61,12 +189,18 @@ impl Program {
                 self.push(Instr::SubInt);
                 self.compile_extractor(mir, lhs);
             },
-            mir::Pat::Tuple(items) | mir::Pat::ListExact(items) => {
+            mir::Pat::Tuple(items) => {
                 for (i, item) in items.iter().enumerate() {
                     if item.binds() {
-                        self.push(Instr::Dup);
-                        self.push(Instr::IndexList(i));
-
+                        self.peek_item(Items::Tuple, i);
+                        self.compile_extractor(mir, item);
+                    }
+                }
+            },
+            mir::Pat::ListExact(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    if item.binds() {
+                        self.peek_item(Items::List, i);
                         self.compile_extractor(mir, item);
                     }
                 }
@@ -78,9 +212,7 @@ impl Program {
 
                 for (i, item) in items.iter().enumerate() {
                     if item.binds() {
-                        self.push(Instr::Dup);
-                        self.push(Instr::IndexList(i));
-
+                        self.peek_item(Items::List, i);
                         self.compile_extractor(mir, item);
                     }
                 }
@@ -104,19 +236,15 @@ impl Program {
         self.push(Instr::Pop(1));
     }
 
-    // [.., [T]] -> [.., Bool]
-    pub fn compile_item_matcher<'a>(&mut self, items: impl IntoIterator<Item = &'a MirNode<mir::Binding>>, is_list: bool, fail_fixup: impl IntoIterator<Item = Addr>) {
+    // [.., T] -> [.., Bool]
+    fn compile_item_matcher<'a>(&mut self, items: impl IntoIterator<Item = &'a MirNode<mir::Binding>>, kind: Items, fail_fixup: impl IntoIterator<Item = Addr>) {
         let mut fixups = fail_fixup.into_iter().collect::<Vec<_>>();
 
         for (i, item) in items.into_iter().enumerate() {
-            self.push(Instr::Dup);
-            if is_list {
-                self.push(Instr::IndexList(i));
-            }
+            self.peek_item(kind, i);
             self.compile_matcher(item);
 
-            self.push(Instr::IfNot);
-            fixups.push(self.push(Instr::Jump(0))); // Fixed by #2
+            fixups.push(self.branch_if_not()); // Fixed by #2
         }
 
         self.push(Instr::Pop(1));
@@ -124,7 +252,7 @@ impl Program {
         let success = self.push(Instr::Jump(0)); // Fixed by #3
 
         for fixup in fixups {
-            self.fixup(fixup, self.next_addr(), Instr::Jump); // Fixes #2
+            self.fixup_branch(fixup, self.next_addr()); // Fixes #2
         }
 
         self.push(Instr::Pop(1));
@@ -133,6 +261,30 @@ impl Program {
         self.fixup(success, self.next_addr(), Instr::Jump); // Fixes #3
     }
 
+    // A pattern that matches a literal by its parts, for literals that have no primitive representation
+    fn literal_binding(&self, litr: &mir::Literal, repr: &repr::Repr) -> MirNode<mir::Binding> {
+        let pat = match (litr, repr) {
+            (mir::Literal::Tuple(fields), repr::Repr::Tuple(reprs)) => mir::Pat::Tuple(fields
+                .iter()
+                .zip(reprs)
+                .map(|(field, repr)| self.literal_binding(field, repr))
+                .collect()),
+            (mir::Literal::List(items), repr::Repr::List(item_repr)) => mir::Pat::ListExact(items
+                .iter()
+                .map(|item| self.literal_binding(item, item_repr))
+                .collect()),
+            (mir::Literal::Sum(variant, inner), repr::Repr::Sum(variants)) => {
+                mir::Pat::Variant(*variant, self.literal_binding(inner, &variants[*variant]))
+            },
+            (mir::Literal::Data(data, inner), repr::Repr::Data(_)) => {
+                mir::Pat::Data(*data, self.literal_binding(inner, &self.reprs.get(*data).repr))
+            },
+            (litr, repr::Repr::Prim(_)) => mir::Pat::Literal(litr.clone()),
+            (litr, repr) => panic!("Literal {:?} does not have representation {:?}", litr, repr),
+        };
+        MirNode::new(mir::Binding { pat, name: None }, repr.clone())
+    }
+
     // [.., T] -> [.., Bool]
     pub fn compile_matcher(&mut self, binding: &MirNode<mir::Binding>) {
         if !binding.is_refutable() {
@@ -141,71 +293,57 @@ impl Program {
         } else {
             match &binding.pat {
                 mir::Pat::Wildcard => unreachable!(), // Caught by refutability check above
-                mir::Pat::Literal(litr) => {
-                    if let Some(val) = litr_to_value(litr) {
-                        self.push(Instr::Imm(val));
-                    }
-                    match binding.meta() {
-                        repr::Repr::Prim(repr::Prim::Bool) => self.push(Instr::EqBool),
-                        repr::Repr::Prim(repr::Prim::Nat) => self.push(Instr::EqInt),
-                        repr::Repr::Prim(repr::Prim::Int) => self.push(Instr::EqInt),
-                        repr::Repr::Prim(repr::Prim::Char) => self.push(Instr::EqChar),
-                        r => todo!("repr = {:?}, litr = {:?}", r, litr),
-                    };
+                mir::Pat::Literal(litr) => match binding.meta() {
+                    repr::Repr::Prim(prim) => {
+                        if let Some(val) = litr_to_value(litr) {
+                            self.push(Instr::Imm(val));
+                        }
+                        self.push(eq_prim(*prim));
+                    },
+                    // Literals of other types (such as strings) are matched by their parts
+                    repr => {
+                        let binding = self.literal_binding(litr, repr);
+                        self.compile_matcher(&binding);
+                    },
                 },
                 mir::Pat::Single(inner) => self.compile_matcher(inner),
                 mir::Pat::Add(lhs, rhs) => {
                     self.push(Instr::Dup);
                     self.push(Instr::Imm(Value::Int(*rhs as i64)));
                     self.push(Instr::MoreEqInt);
-                    self.push(Instr::IfNot);
-                    let fail_fixup = self.push(Instr::Jump(0)); // Fixed by #2
-                    self.compile_item_matcher(Some(lhs), false, Some(fail_fixup));
+                    let fail_fixup = self.branch_if_not(); // Fixed by #2
+                    self.compile_item_matcher(Some(lhs), Items::Whole, Some(fail_fixup));
                 },
                 mir::Pat::Tuple(items) => {
-                    self.compile_item_matcher(items, true, None);
+                    self.compile_item_matcher(items, Items::Tuple, None);
                 },
                 mir::Pat::ListExact(items) => if items.len() == 0 {
-                    self.push(Instr::LenList);
-                    self.push(Instr::Imm(Value::Int(items.len() as i64)));
-                    self.push(Instr::EqInt);
+                    self.test_len(0, true);
+                    self.push(Instr::Replace);
                 } else {
-                    self.push(Instr::Dup);
-                    self.push(Instr::LenList);
-                    self.push(Instr::Imm(Value::Int(items.len() as i64)));
-                    self.push(Instr::EqInt);
-                    self.push(Instr::IfNot);
-                    let fail_fixup = Some(self.push(Instr::Jump(0))); // Fixed by #2
+                    self.test_len(items.len(), true);
+                    let fail_fixup = Some(self.branch_if_not()); // Fixed by #2
 
-                    self.compile_item_matcher(items, true, fail_fixup);
+                    self.compile_item_matcher(items, Items::List, fail_fixup);
                 },
                 mir::Pat::ListFront(items, tail) => {
-                    self.push(Instr::Dup);
-                    self.push(Instr::LenList);
-                    self.push(Instr::Imm(Value::Int(items.len() as i64)));
-                    self.push(Instr::MoreEqInt);
-                    self.push(Instr::IfNot);
-                    let mut fail_fixup = vec![self.push(Instr::Jump(0))]; // Fixed by #2
+                    self.test_len(items.len(), false);
+                    let mut fail_fixup = vec![self.branch_if_not()]; // Fixed by #2
 
                     if let Some(tail) = tail.as_ref() {
                         self.push(Instr::Dup);
                         self.push(Instr::SkipListImm(items.len()));
                         self.compile_matcher(tail);
-                        self.push(Instr::IfNot);
-                        fail_fixup.push(self.push(Instr::Jump(0))); // Fixed by #2
+                        fail_fixup.push(self.branch_if_not()); // Fixed by #2
                     }
 
-                    self.compile_item_matcher(items, true, fail_fixup);
+                    self.compile_item_matcher(items, Items::List, fail_fixup);
                 },
                 mir::Pat::Variant(variant, inner) => {
-                    self.push(Instr::Dup);
-                    self.push(Instr::VariantSum);
-                    self.push(Instr::Imm(Value::Int(*variant as i64)));
-                    self.push(Instr::EqInt);
-                    self.push(Instr::IfNot);
-                    let fail_fixup = self.push(Instr::Jump(0)); // Fixed by #2
+                    self.test_variant(*variant);
+                    let fail_fixup = self.branch_if_not(); // Fixed by #2
                     self.push(Instr::IndexSum(*variant));
-                    self.compile_item_matcher(Some(inner), false, Some(fail_fixup));
+                    self.compile_item_matcher(Some(inner), Items::Whole, Some(fail_fixup));
                 },
                 mir::Pat::Data(_, inner) => self.compile_matcher(inner),
             }
@@ -237,7 +375,7 @@ impl Program {
 
         // A function with an undefined body doesn't need to be compiled!
         if !matches!(&*body, mir::Expr::Undefined) {
-            self.compile_expr(mir, body, &mut f_stack, proc_fixups);
+            self.compile_tail_expr(mir, body, &mut f_stack, proc_fixups);
             self.push(Instr::PopLocal(args.len() + captures.len())); // +1 is for the argument
             self.push(Instr::Ret);
         }
@@ -245,14 +383,7 @@ impl Program {
         self.fixup(jump_over, self.next_addr(), Instr::Jump); // Fixes #5
 
         for &capture in captures.iter() {
-            let idx = stack
-                .iter()
-                .rev()
-                .enumerate()
-                .find(|(_, name)| **name == capture)
-                .unwrap_or_else(|| unreachable!("${}", capture.0))
-                .0;
-            self.push(Instr::GetLocal(idx));
+            self.push(Instr::GetLocal(local_slot(stack, capture)));
         }
 
         stack.truncate(old_stack);
@@ -260,6 +391,383 @@ impl Program {
         (f_addr, captures.len())
     }
 
+    // [.., T] -> [.., T, Bool]
+    fn compile_test(&mut self, test: &decision::Test) {
+        use decision::Test;
+        match test {
+            Test::Variant(variant) => self.test_variant(*variant),
+            Test::Len(len) => self.test_len(*len, true),
+            Test::LenAtLeast(len) => self.test_len(*len, false),
+            Test::AtLeast(n) => {
+                self.push(Instr::Dup);
+                self.push(Instr::Imm(Value::Int(*n as i64)));
+                self.push(Instr::MoreEqInt);
+            },
+            Test::Literal(litr, prim) => {
+                self.push(Instr::Dup);
+                if let Some(val) = litr_to_value(litr) {
+                    self.push(Instr::Imm(val));
+                }
+                self.push(eq_prim(*prim));
+            },
+        }
+    }
+
+    // [..] -> [.., T], where T is the part of the value being matched at the given occurrence
+    // Parts are kept in locals once found, so each one is only found once
+    fn load_occ(&mut self, occ: &[decision::Step], occs: &mut Vec<(decision::Occ, mir::Local)>, stack: &mut Vec<mir::Local>) {
+        use decision::Step;
+
+        if let Some((_, local)) = occs.iter().find(|(o, _)| o.as_slice() == occ) {
+            self.push(Instr::GetLocal(local_slot(stack, *local)));
+            return;
+        }
+
+        let (step, parent) = occ.split_last().expect("Value being matched must be in a local");
+        self.load_occ(parent, occs, stack);
+        match step {
+            Step::Field(i) => { self.push(Instr::IndexTuple(*i)); },
+            Step::Item(i) => { self.push(Instr::IndexList(*i)); },
+            Step::Skip(n) => { self.push(Instr::SkipListImm(*n)); },
+            Step::Variant(variant) => { self.push(Instr::IndexSum(*variant)); },
+            Step::Sub(n) => {
+                self.push(Instr::Imm(Value::Int(*n as i64)));
+                self.push(Instr::SubInt);
+            },
+        }
+
+        self.push(Instr::Dup);
+        self.push(Instr::PushLocal);
+        let local = mir::Local::new();
+        stack.push(local);
+        occs.push((occ.to_vec(), local));
+    }
+
+    // [..] -> [..]
+    // Every path through the emitted code ends with a jump to the code of an arm, which is recorded in `arm_jumps`
+    fn compile_decision(
+        &mut self,
+        mir: &MirContext,
+        arms: &[(MirNode<mir::Binding>, Option<MirNode<mir::Expr>>, MirNode<mir::Expr>)],
+        decision: &decision::Decision,
+        occs: &mut Vec<(decision::Occ, mir::Local)>,
+        arm_jumps: &mut Vec<Vec<Addr>>,
+        stack: &mut Vec<mir::Local>,
+        proc_fixups: &mut Vec<(ProcId, Addr)>,
+    ) {
+        use decision::Decision;
+        match decision {
+            Decision::Fail => { self.push(Instr::Error("no arm of the match matched")); },
+            Decision::Arm(arm, fallback) => {
+                // Only the whole value is needed by the arm, so the parts found along the way can be discarded
+                let parts = occs.len() - 1;
+                let (binding, guard, _) = &arms[*arm];
+                match (guard, fallback) {
+                    (Some(guard), Some(fallback)) => {
+                        self.push(Instr::GetLocal(local_slot(stack, occs[0].1)));
+                        self.compile_extractor(mir, binding);
+
+                        let old_stack = stack.len();
+                        let names = binding.binding_names();
+                        stack.extend(names.iter().copied()); // Start scope
+
+                        self.compile_expr(mir, guard, stack, proc_fixups);
+                        let guard_fail = self.branch_if_not(); // Fixed by #8
+                        // The arm extracts its own bindings
+                        if names.len() + parts > 0 {
+                            self.push(Instr::PopLocal(names.len() + parts));
+                        }
+                        arm_jumps[*arm].push(self.push(Instr::Jump(0)));
+
+                        // If the guard failed, discard the bindings and carry on testing
+                        self.fixup_branch(guard_fail, self.next_addr()); // Fixes #8
+                        if names.len() > 0 {
+                            self.push(Instr::PopLocal(names.len()));
+                        }
+                        stack.truncate(old_stack); // End scope
+
+                        self.compile_decision(mir, arms, fallback, occs, arm_jumps, stack, proc_fixups);
+                    },
+                    _ => {
+                        if parts > 0 {
+                            self.push(Instr::PopLocal(parts));
+                        }
+                        arm_jumps[*arm].push(self.push(Instr::Jump(0)));
+                    },
+                }
+            },
+            Decision::Switch(occ, cases, default) => {
+                self.load_occ(occ, occs, stack);
+                let (old_occs, old_stack) = (occs.len(), stack.len());
+
+                // Tests on the variant of a sum (or on small integers and chars) can all be performed by a single jump
+                // table
+                if let Some(idxs) = cases.iter().map(|(test, _)| switch_idx(test)).collect::<Option<Vec<_>>>() {
+                    let switch = self.push(Instr::Switch(Box::new([]))); // Fixed by #12
+
+                    // Entries that aren't in the table fall through to the default
+                    self.compile_decision(mir, arms, default, occs, arm_jumps, stack, proc_fixups);
+                    occs.truncate(old_occs);
+                    stack.truncate(old_stack);
+
+                    let mut targets = vec![switch.incr(); idxs.iter().max().map_or(0, |max| max + 1)];
+                    for (idx, (_, decision)) in idxs.into_iter().zip(cases) {
+                        targets[idx] = self.next_addr();
+                        self.compile_decision(mir, arms, decision, occs, arm_jumps, stack, proc_fixups);
+                        occs.truncate(old_occs);
+                        stack.truncate(old_stack);
+                    }
+
+                    self.fixup_switch(switch, targets); // Fixes #12
+                    return;
+                }
+
+                // Matches are exhaustive, so if the default is unreachable then the last test need not be performed
+                let unconditional = matches!(&**default, Decision::Fail) as usize;
+                for (i, (test, decision)) in cases.iter().enumerate() {
+                    let next = if i + unconditional < cases.len() {
+                        self.compile_test(test);
+                        Some(self.branch_if_not()) // Fixed by #9
+                    } else {
+                        None
+                    };
+
+                    self.push(Instr::Pop(1));
+                    self.compile_decision(mir, arms, decision, occs, arm_jumps, stack, proc_fixups);
+                    occs.truncate(old_occs);
+                    stack.truncate(old_stack);
+
+                    if let Some(next) = next {
+                        self.fixup_branch(next, self.next_addr()); // Fixes #9
+                    }
+                }
+
+                if unconditional == 0 || cases.is_empty() {
+                    self.push(Instr::Pop(1));
+                    self.compile_decision(mir, arms, default, occs, arm_jumps, stack, proc_fixups);
+                }
+            },
+        }
+    }
+
+    // [.., T] -> [.., U]
+    // Test the value against a decision tree, so that no part of it is tested more than once
+    fn compile_match_tree(
+        &mut self,
+        mir: &MirContext,
+        arms: &[(MirNode<mir::Binding>, Option<MirNode<mir::Expr>>, MirNode<mir::Expr>)],
+        tree: &decision::Decision,
+        stack: &mut Vec<mir::Local>,
+        proc_fixups: &mut Vec<(ProcId, Addr)>,
+        tail: bool,
+    ) {
+        // The value being matched is kept in a local so that its parts can be found whenever they're needed
+        let value = mir::Local::new();
+        self.push(Instr::PushLocal);
+        stack.push(value);
+
+        let old_stack = stack.len();
+        let mut arm_jumps = vec![Vec::new(); arms.len()];
+        self.compile_decision(mir, arms, tree, &mut vec![(Vec::new(), value)], &mut arm_jumps, stack, proc_fixups);
+        stack.truncate(old_stack); // Parts of the value are discarded before jumping to an arm
+
+        // Arms that can't be reached have no code
+        let last_arm = arm_jumps.iter().rposition(|jumps| !jumps.is_empty());
+        let mut end_matches = Vec::new();
+        for (i, (binding, _, body)) in arms.iter().enumerate() {
+            if arm_jumps[i].is_empty() {
+                continue;
+            }
+
+            for jump in &arm_jumps[i] {
+                self.fixup_branch(*jump, self.next_addr()); // Fixes #10
+            }
+
+            // The value being matched is never needed again, so the arm can take ownership of it
+            if self.reuse {
+                self.push(Instr::TakeLocal(local_slot(stack, value)));
+            } else {
+                self.push(Instr::GetLocal(local_slot(stack, value)));
+            }
+            self.compile_extractor(mir, binding);
+
+            let old_stack = stack.len();
+            let names = binding.binding_names();
+            stack.extend(names.iter().copied()); // Start scope
+            self.compile_arm_body(mir, body, stack, proc_fixups, tail);
+            stack.truncate(old_stack); // End scope
+
+            self.push(Instr::PopLocal(names.len() + 1)); // +1 is for the value being matched
+
+            if Some(i) != last_arm {
+                end_matches.push(self.push(Instr::Jump(0))); // Fixed by #11
+            }
+        }
+
+        stack.pop();
+
+        let end_match = self.next_addr();
+        for end_arm in end_matches {
+            self.fixup(end_arm, end_match, Instr::Jump); // Fixes #11
+        }
+    }
+
+    // [.., T] -> [.., U]
+    // Test each arm's pattern in turn
+    fn compile_match_linear(
+        &mut self,
+        mir: &MirContext,
+        arms: &[(MirNode<mir::Binding>, Option<MirNode<mir::Expr>>, MirNode<mir::Expr>)],
+        stack: &mut Vec<mir::Local>,
+        proc_fixups: &mut Vec<(ProcId, Addr)>,
+        tail: bool,
+    ) {
+        let mut end_matches = Vec::new();
+
+        for (i, (binding, guard, body)) in arms.iter().enumerate() {
+            let is_last = i + 1 == arms.len();
+
+            let mut fail_jumps = Vec::new();
+
+            // Skip pattern match if pattern is irrefutable or it's the last unguarded pattern
+            if binding.is_refutable() && (!is_last || guard.is_some()) {
+                self.push(Instr::Dup);
+                self.compile_matcher(binding);
+
+                fail_jumps.push(self.branch_if_not());
+            }
+
+            // A guard might fail after the bindings have been extracted, so keep the predicate around until then
+            if guard.is_some() {
+                self.push(Instr::Dup);
+            }
+
+            self.compile_extractor(mir, binding);
+
+            let old_stack = stack.len();
+            let names = binding.binding_names();
+            stack.extend(names.iter().copied()); // Start scope
+
+            let guard_fail = if let Some(guard) = guard {
+                self.compile_expr(mir, guard, stack, proc_fixups);
+                let guard_fail = self.branch_if_not(); // Fixed by #7
+                self.push(Instr::Pop(1)); // The guard succeeded, so the predicate is no longer needed
+                Some(guard_fail)
+            } else {
+                None
+            };
+
+            self.compile_arm_body(mir, body, stack, proc_fixups, tail);
+
+            if names.len() > 0 {
+                self.push(Instr::PopLocal(names.len()));
+            }
+
+            if !is_last || guard.is_some() {
+                end_matches.push(self.push(Instr::Jump(0))); // Fixed by #1
+            }
+
+            // If the guard failed, discard the bindings and try the next arm
+            if let Some(guard_fail) = guard_fail {
+                self.fixup_branch(guard_fail, self.next_addr()); // Fixes #7
+                if names.len() > 0 {
+                    self.push(Instr::PopLocal(names.len()));
+                }
+            }
+            stack.truncate(old_stack); // End scope
+
+            for fail_jump in fail_jumps {
+                self.fixup_branch(fail_jump, self.next_addr()); // Fixes #0
+            }
+        }
+
+        let end_match = self.next_addr();
+
+        for end_arm in end_matches {
+            self.fixup(end_arm, end_match, Instr::Jump); // Fixes #1
+        }
+    }
+
+    // [..] -> [.., T]
+    fn compile_match(
+        &mut self,
+        mir: &MirContext,
+        pred: &mir::Expr,
+        arms: &[(MirNode<mir::Binding>, Option<MirNode<mir::Expr>>, MirNode<mir::Expr>)],
+        stack: &mut Vec<mir::Local>,
+        proc_fixups: &mut Vec<(ProcId, Addr)>,
+        tail: bool,
+    ) {
+        self.compile_expr(mir, pred, stack, proc_fixups);
+
+        // Matches with a single arm gain nothing from a decision tree
+        let tree = if self.decision_trees && arms.len() > 1 {
+            let patterns = arms
+                .iter()
+                .map(|(binding, guard, _)| (binding, guard.is_some()))
+                .collect::<Vec<_>>();
+            decision::decision_tree(&patterns, arms.len() * DECISION_BUDGET_PER_ARM)
+        } else {
+            None
+        };
+
+        match tree {
+            Some(tree) => self.compile_match_tree(mir, arms, &tree, stack, proc_fixups, tail),
+            None => self.compile_match_linear(mir, arms, stack, proc_fixups, tail),
+        }
+    }
+
+    // [..] -> [.., T]
+    fn compile_arm_body(
+        &mut self,
+        mir: &MirContext,
+        body: &mir::Expr,
+        stack: &mut Vec<mir::Local>,
+        proc_fixups: &mut Vec<(ProcId, Addr)>,
+        tail: bool,
+    ) {
+        if tail {
+            self.compile_tail_expr(mir, body, stack, proc_fixups);
+        } else {
+            self.compile_expr(mir, body, stack, proc_fixups);
+        }
+    }
+
+    // [..] -> [.., T]
+    // The value of the expression is returned from the current function as soon as it is produced, and every local on
+    // the stack belongs to the current function. Calls can therefore reuse the current function's frame, since nothing
+    // remains to be done in it once they return.
+    fn compile_tail_expr(
+        &mut self,
+        mir: &MirContext,
+        expr: &mir::Expr,
+        stack: &mut Vec<mir::Local>,
+        proc_fixups: &mut Vec<(ProcId, Addr)>,
+    ) {
+        if !self.tail_calls {
+            return self.compile_expr(mir, expr, stack, proc_fixups);
+        }
+
+        match expr {
+            mir::Expr::Global(global, _) if !self.constants.contains_key(global) => {
+                if stack.len() > 0 {
+                    self.push(Instr::PopLocal(stack.len()));
+                }
+                proc_fixups.push((*global, self.push(Instr::TailCall(0)))); // Fixed by #4
+            },
+            mir::Expr::Apply(f, arg) => {
+                self.compile_expr(mir, f, stack, proc_fixups);
+                self.compile_expr(mir, arg, stack, proc_fixups);
+                self.push(Instr::TailApplyFunc(stack.len()));
+            },
+            mir::Expr::Match(pred, arms) => self.compile_match(mir, pred, arms, stack, proc_fixups, true),
+            mir::Expr::Data(_, inner) | mir::Expr::AccessData(inner, _) => {
+                self.compile_tail_expr(mir, inner, stack, proc_fixups);
+            },
+            _ => self.compile_expr(mir, expr, stack, proc_fixups),
+        }
+    }
+
     // [..] -> [.., T]
     pub fn compile_expr(
         &mut self,
@@ -275,20 +783,25 @@ impl Program {
                     self.push(Instr::Imm(val));
                 }
             },
-            mir::Expr::Local(local) => {
-                let idx = stack
-                    .iter()
-                    .rev()
-                    .enumerate()
-                    .find(|(_, name)| *name == local)
-                    .unwrap_or_else(|| panic!("Tried to find local ${}, but it was not found. Stack: {:?}", local.0, stack))
-                    .0;
-                self.push(Instr::GetLocal(idx));
-            },
-            mir::Expr::Global(global, _) => { proc_fixups.push((*global, self.push(Instr::Call(0)))); }, // Fixed by #4
+            mir::Expr::Local(local) => if self.reuse && self.last_uses.contains(expr) {
+                self.push(Instr::TakeLocal(local_slot(stack, *local)));
+            } else {
+                self.push(Instr::GetLocal(local_slot(stack, *local)));
+            },
+            mir::Expr::Global(global, _) => if let Some(value) = self.constants.get(global).cloned() {
+                self.push(Instr::Imm(value));
+            } else {
+                proc_fixups.push((*global, self.push(Instr::Call(0)))); // Fixed by #4
+            },
             mir::Expr::Intrinsic(intrinsic, args) => {
-                for arg in args {
-                    self.compile_expr(mir, arg, stack, proc_fixups);
+                if self.reuse && matches!(intrinsic, mir::Intrinsic::UpdateField(_)) && reuse::updates_field_first(args) {
+                    self.compile_expr(mir, &args[1], stack, proc_fixups);
+                    self.compile_expr(mir, &args[0], stack, proc_fixups);
+                    self.push(Instr::Swap);
+                } else {
+                    for arg in args {
+                        self.compile_expr(mir, arg, stack, proc_fixups);
+                    }
                 }
                 use mir::Intrinsic;
                 match intrinsic {
@@ -318,7 +831,7 @@ impl Program {
                     Intrinsic::Join(_) => { self.push(Instr::JoinList); },
                     Intrinsic::Print => { self.push(Instr::Print); },
                     Intrinsic::Input => { self.push(Instr::Input); },
-                    Intrinsic::UpdateField(idx) => { self.push(Instr::SetList(*idx)); },
+                    Intrinsic::UpdateField(idx) => { self.push(Instr::SetTuple(*idx)); },
                     Intrinsic::LenList => { self.push(Instr::LenList); },
                     Intrinsic::SkipList => { self.push(Instr::SkipList); },
                     Intrinsic::TrimList => { self.push(Instr::TrimList); },
@@ -330,13 +843,14 @@ impl Program {
                     Intrinsic::Propagate(effs) => {
                         self.push(Instr::Propagate);
                     },
+                    Intrinsic::Host(name) => { self.push(Instr::CallHost(*name, args.len())); },
                 };
             },
             mir::Expr::Tuple(fields) => {
                 for field in fields {
                     self.compile_expr(mir, field, stack, proc_fixups);
                 }
-                self.push(Instr::MakeList(fields.len()));
+                self.push(Instr::MakeTuple(fields.len()));
             },
             mir::Expr::List(items) => {
                 for item in items {
@@ -344,53 +858,7 @@ impl Program {
                 }
                 self.push(Instr::MakeList(items.len()));
             },
-            mir::Expr::Match(pred, arms) => {
-                self.compile_expr(mir, pred, stack, proc_fixups);
-
-                let mut end_matches = Vec::new();
-
-                for (i, (binding, body)) in arms.iter().enumerate() {
-                    let is_last = i + 1 == arms.len();
-
-                    let mut fail_jumps = Vec::new();
-
-                    // Skip pattern match if pattern is irrefutable or it's the last pattern
-                    if binding.is_refutable() && !is_last {
-                        self.push(Instr::Dup);
-                        self.compile_matcher(binding);
-
-                        self.push(Instr::IfNot);
-                        fail_jumps.push(self.push(Instr::Jump(0)));
-                    }
-
-                    self.compile_extractor(mir, binding);
-
-                    let old_stack = stack.len();
-                    let names = binding.binding_names();
-                    stack.extend(names.iter().copied()); // Start scope
-
-                    self.compile_expr(mir, body, stack, proc_fixups);
-
-                    if names.len() > 0 {
-                        self.push(Instr::PopLocal(names.len()));
-                    }
-                    stack.truncate(old_stack); // End scope
-
-                    if !is_last {
-                        end_matches.push(self.push(Instr::Jump(0))); // Fixed by #1
-                    }
-
-                    for fail_jump in fail_jumps {
-                        self.fixup(fail_jump, self.next_addr(), Instr::Jump); // Fixes #0
-                    }
-                }
-
-                let end_match = self.next_addr();
-
-                for end_arm in end_matches {
-                    self.fixup(end_arm, end_match, Instr::Jump); // Fixes #1
-                }
-            },
+            mir::Expr::Match(pred, arms) => self.compile_match(mir, pred, arms, stack, proc_fixups, false),
             mir::Expr::Func(arg, body) => {
                 let (f_addr, captures_len) = self.compile_body(mir, vec![**arg], body, stack, proc_fixups);
 
@@ -411,17 +879,13 @@ impl Program {
                 const DONE_VARIANT: usize = 1;
 
                 // Try unwrapping result
-                self.push(Instr::Dup);
-                self.push(Instr::VariantSum);
-                self.push(Instr::Imm(Value::Int(NEXT_VARIANT as i64)));
-                self.push(Instr::EqInt);
-                self.push(Instr::IfNot);
-                let done = self.push(Instr::Jump(0)); // Fixed by #6
+                self.test_variant(NEXT_VARIANT);
+                let done = self.branch_if_not(); // Fixed by #6
                 self.push(Instr::IndexSum(NEXT_VARIANT));
                 let again = self.push(Instr::Jump(0));
                 self.fixup(again, luup, Instr::Jump);
 
-                self.fixup(done, self.next_addr(), Instr::Jump); // Fixes #6
+                self.fixup_branch(done, self.next_addr()); // Fixes #6
                 self.push(Instr::IndexSum(DONE_VARIANT));
                 self.push(Instr::Replace);
             },
@@ -435,9 +899,14 @@ impl Program {
                 self.compile_expr(mir, inner, stack, proc_fixups);
                 self.push(Instr::MakeSum(*variant));
             },
-            mir::Expr::Access(record, field) => {
-                self.compile_expr(mir, record, stack, proc_fixups);
-                self.push(Instr::IndexList(*field));
+            mir::Expr::Access(record, field) => match &**record {
+                mir::Expr::Local(local) if self.superinstructions => {
+                    self.push(Instr::GetLocalField(local_slot(stack, *local), *field));
+                },
+                _ => {
+                    self.compile_expr(mir, record, stack, proc_fixups);
+                    self.push(Instr::IndexTuple(*field));
+                },
             },
             mir::Expr::AccessVariant(inner, variant) => {
                 self.compile_expr(mir, inner, stack, proc_fixups);
@@ -467,10 +936,9 @@ impl Program {
                 }
 
                 // Get state
-                self.push(Instr::Dup);
-                self.push(Instr::IndexList(1));
+                self.peek_item(Items::Tuple, 1);
                 self.push(Instr::Swap);
-                self.push(Instr::IndexList(0));
+                self.push(Instr::IndexTuple(0));
                 self.push(Instr::Propagate);
 
                 self.push(Instr::EndHandlers(handlers.len()));
@@ -478,22 +946,48 @@ impl Program {
         }
     }
 
-    pub fn compile_proc(&mut self, mir: &MirContext, proc: ProcId, entry_io: bool, proc_fixups: &mut Vec<(ProcId, Addr)>) -> Addr {
+    pub fn compile_proc(&mut self, mir: &MirContext, proc: ProcId, apply_entry: bool, proc_fixups: &mut Vec<(ProcId, Addr)>) -> Addr {
         self.debug(format!("Proc {:?}", proc));
         let addr = self.next_addr();
-        self.compile_expr(mir, &mir.procs.get(proc).unwrap().body, &mut Vec::new(), proc_fixups);
-        if entry_io {
+        let body = &mir.procs.get(proc).unwrap().body;
+        if self.reuse {
+            self.last_uses = reuse::LastUses::of(body);
+        }
+        // The entry point's argument (the universe, for programs that do I/O) is already on the local stack
+        if apply_entry {
+            self.compile_expr(mir, body, &mut Vec::new(), proc_fixups);
             self.push(Instr::ApplyFunc);
+        } else {
+            self.compile_tail_expr(mir, body, &mut Vec::new(), proc_fixups);
         }
         self.push(Instr::Ret);
         addr
     }
 
     pub fn from_mir(mir: &MirContext) -> Self {
+        Self::from_mir_with(mir, &LowerConfig::default())
+    }
+
+    /// Like [`Program::from_mir`], but with the given lowering options.
+    pub fn from_mir_with(mir: &MirContext, cfg: &LowerConfig) -> Self {
+        Self::from_mir_inner(mir, false, cfg)
+    }
+
+    /// Like [`Program::from_mir`], but the entry point must be a function. When the program is executed with
+    /// [`exec_with_arg`], the function is applied to the given argument.
+    pub fn from_mir_applied(mir: &MirContext) -> Self {
+        Self::from_mir_inner(mir, true, &LowerConfig::default())
+    }
+
+    fn from_mir_inner(mir: &MirContext, applied: bool, cfg: &LowerConfig) -> Self {
         let mut this = Self::default();
+        this.superinstructions = cfg.superinstructions;
+        this.decision_trees = cfg.decision_trees;
+        this.tail_calls = cfg.tail_calls;
+        this.reuse = cfg.reuse;
 
         let entry = mir.entry.expect("No entry point");
-        this.does_io = if let repr::Repr::Func(i, o) = mir.procs.get(entry).unwrap().body.meta() {
+        this.does_io = !applied && if let repr::Repr::Func(i, o) = mir.procs.get(entry).unwrap().body.meta() {
             if let (repr::Repr::Prim(repr::Prim::Universe), repr::Repr::Tuple(xs)) = (&**i, &**o) {
                 if let [repr::Repr::Prim(repr::Prim::Universe), repr::Repr::Tuple(xs)] = &xs[..] {
                     xs.len() == 0
@@ -507,15 +1001,44 @@ impl Program {
             false
         };
 
+        this.result_repr = if this.does_io {
+            None
+        } else if applied {
+            let repr::Repr::Func(i, o) = mir.procs.get(entry).unwrap().body.meta() else {
+                panic!("Applied entry point must be a function")
+            };
+            this.entry_arg = Some((**i).clone());
+            Some((**o).clone())
+        } else {
+            Some(mir.procs.get(entry).unwrap().body.meta().clone())
+        };
+        this.reprs = mir.reprs.clone();
+
         let mut procs = BTreeMap::new();
         let mut proc_fixups = Vec::new();
 
+        // Procedures whose bodies are literals (such as those evaluated by `EvalConstants`) go in the constants
+        // section, and references to them push their values rather than calling them
+        for proc_id in mir.reachable_procs() {
+            if let mir::Expr::Literal(literal) = &*mir.procs.get(proc_id).unwrap().body {
+                if let Some(value) = litr_to_value(literal) {
+                    this.constants.insert(proc_id, value);
+                }
+            }
+        }
+
         for proc_id in mir.reachable_procs() {
-            procs.insert(proc_id, this.compile_proc(mir, proc_id, this.does_io && proc_id == entry, &mut proc_fixups));
+            if proc_id != entry && this.constants.contains_key(&proc_id) {
+                continue;
+            }
+            let apply_entry = (this.does_io || applied) && proc_id == entry;
+            procs.insert(proc_id, this.compile_proc(mir, proc_id, apply_entry, &mut proc_fixups));
         }
+        // The MIR that the last uses refer to doesn't outlive lowering
+        this.last_uses = Default::default();
 
         for (proc_id, addr) in proc_fixups {
-            this.fixup(addr, procs[&proc_id], Instr::Call); // Fixes #4
+            this.fixup_branch(addr, procs[&proc_id]); // Fixes #4
         }
 
         this.entry = procs[&entry];
@@ -0,0 +1,197 @@
+use super::*;
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    time::{Duration, Instant},
+};
+
+// A node in the dynamic call tree. Each distinct call path gets its own node, which makes it trivial to produce
+// folded stacks later. Recursive calls are merged into the node of the earlier call to the same procedure on the path,
+// so the tree stays as shallow as the program's call graph no matter how deeply the program recurses.
+#[derive(Debug)]
+struct Frame {
+    parent: Option<usize>,
+    proc: Addr,
+    calls: u64,
+    instrs: u64,
+    time: Duration,
+}
+
+/// Collects statistics about the execution of a program.
+///
+/// Instructions and time are attributed to procedures by following `call`/`ret` (and function application, effect
+/// propagation and suspension) and mapping each callee address back to its label in the program's debug table.
+#[derive(Debug)]
+pub struct Profiler {
+    frames: Vec<Frame>,
+    children: HashMap<(usize, Addr), usize>,
+    stack: Vec<usize>,
+    opcodes: BTreeMap<&'static str, u64>,
+    instrs: u64,
+    last_event: Instant,
+    started: Instant,
+    elapsed: Duration,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        let now = Instant::now();
+        Self {
+            frames: Vec::new(),
+            children: HashMap::default(),
+            stack: Vec::new(),
+            opcodes: BTreeMap::new(),
+            instrs: 0,
+            last_event: now,
+            started: now,
+            elapsed: Duration::ZERO,
+        }
+    }
+}
+
+impl Profiler {
+    pub fn new() -> Self { Self::default() }
+
+    /// The total number of instructions executed.
+    pub fn instrs(&self) -> u64 { self.instrs }
+
+    fn proc_of(prog: &Program, addr: Addr) -> Addr {
+        prog.label_at(addr).map_or(addr, |(proc, _)| proc)
+    }
+
+    // Attribute the time since the last event to whatever procedure is currently executing
+    fn flush_time(&mut self) {
+        let now = Instant::now();
+        if let Some(&frame) = self.stack.last() {
+            self.frames[frame].time += now - self.last_event;
+        }
+        self.last_event = now;
+    }
+
+    pub(crate) fn start(&mut self, prog: &Program, entry: Addr) {
+        self.started = Instant::now();
+        self.last_event = self.started;
+        self.enter(prog, entry);
+    }
+
+    pub(crate) fn enter(&mut self, prog: &Program, addr: Addr) {
+        self.flush_time();
+        let proc = Self::proc_of(prog, addr);
+        let parent = self.stack.last().copied();
+        let frames = &mut self.frames;
+        let frame = *self.children
+            .entry((parent.unwrap_or(usize::MAX), proc))
+            .or_insert_with(|| {
+                // Find an earlier call to the same procedure on this path, if there is one
+                let mut ancestor = parent;
+                while let Some(idx) = ancestor {
+                    if frames[idx].proc == proc {
+                        return idx;
+                    }
+                    ancestor = frames[idx].parent;
+                }
+
+                frames.push(Frame {
+                    parent,
+                    proc,
+                    calls: 0,
+                    instrs: 0,
+                    time: Duration::ZERO,
+                });
+                frames.len() - 1
+            });
+        self.frames[frame].calls += 1;
+        self.stack.push(frame);
+    }
+
+    pub(crate) fn exit(&mut self) {
+        self.flush_time();
+        self.stack.pop();
+    }
+
+    pub(crate) fn finish(&mut self) {
+        self.flush_time();
+        self.stack.clear();
+        self.elapsed = self.started.elapsed();
+    }
+
+    pub(crate) fn tick(&mut self, instr: &Instr) {
+        self.instrs += 1;
+        *self.opcodes.entry(instr.mnemonic()).or_insert(0) += 1;
+        if let Some(&frame) = self.stack.last() {
+            self.frames[frame].instrs += 1;
+        }
+    }
+
+    fn proc_name(prog: &Program, proc: Addr) -> String {
+        match prog.label_at(proc) {
+            Some((addr, label)) if addr == proc => label.to_string(),
+            _ => format!("0x{:03X}", proc.0),
+        }
+    }
+
+    /// Write a human-readable summary of the profile: a per-procedure breakdown followed by an opcode histogram.
+    pub fn write_report(&self, prog: &Program, mut writer: impl Write) {
+        #[derive(Default)]
+        struct ProcStats {
+            calls: u64,
+            instrs: u64,
+            time: Duration,
+        }
+
+        let mut procs = BTreeMap::<Addr, ProcStats>::new();
+        for frame in &self.frames {
+            let stats = procs.entry(frame.proc).or_default();
+            stats.calls += frame.calls;
+            stats.instrs += frame.instrs;
+            stats.time += frame.time;
+        }
+        let mut procs = procs.into_iter().collect::<Vec<_>>();
+        procs.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.instrs));
+
+        let percent = |n: u64| 100.0 * n as f64 / self.instrs.max(1) as f64;
+
+        writeln!(writer, "Executed {} instructions in {:?}.\n", self.instrs, self.elapsed).unwrap();
+
+        writeln!(writer, "{:>12} | {:>6} | {:>12} | {:>10} | procedure", "instrs", "%", "time", "calls").unwrap();
+        for (proc, stats) in &procs {
+            writeln!(
+                writer,
+                "{:>12} | {:>5.1}% | {:>12?} | {:>10} | {}",
+                stats.instrs,
+                percent(stats.instrs),
+                stats.time,
+                stats.calls,
+                Self::proc_name(prog, *proc),
+            ).unwrap();
+        }
+
+        let mut opcodes = self.opcodes.iter().collect::<Vec<_>>();
+        opcodes.sort_by_key(|(_, n)| std::cmp::Reverse(**n));
+
+        writeln!(writer, "\n{:>12} | {:>6} | opcode", "count", "%").unwrap();
+        for (opcode, n) in opcodes {
+            writeln!(writer, "{:>12} | {:>5.1}% | {}", n, percent(*n), opcode).unwrap();
+        }
+    }
+
+    /// Write the profile as folded stacks (one `a;b;c <count>` line per call path), weighted by instruction count.
+    /// This is the format understood by `flamegraph.pl`, `inferno` and most other flame graph tools.
+    pub fn write_folded(&self, prog: &Program, mut writer: impl Write) {
+        // Frames are always created after their parents, so each path can be built from its parent's
+        let mut paths = Vec::<String>::with_capacity(self.frames.len());
+        for frame in &self.frames {
+            // Semicolons separate frames in the folded format, so they can't appear in names
+            let name = Self::proc_name(prog, frame.proc).replace(';', ":");
+            let path = match frame.parent {
+                Some(parent) => format!("{};{}", paths[parent], name),
+                None => name,
+            };
+
+            if frame.instrs > 0 {
+                writeln!(writer, "{} {}", path, frame.instrs).unwrap();
+            }
+            paths.push(path);
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Reuse analysis, which finds the uses of locals after which those locals are never used again.
+//!
+//! At its last use, a value can be moved out of its local rather than copied. If nothing else refers to the value, it
+//! is then uniquely owned, and the instructions that update or take apart records (`tuple.set`, `tuple.index`) can
+//! reuse it rather than copying it. This is similar in spirit to Perceus: ownership is decided at compile-time, but
+//! whether a value really is unique is checked at run-time. Lists are persistent vectors that already share structure
+//! between versions, so they aren't updated in place.
+
+use super::*;
+use hashbrown::HashSet;
+
+/// Whether a record update evaluates its new field before the record (rather than after), so that the record can be
+/// moved out of its local if this is its last use. This is only possible if evaluating the record has no effect.
+pub fn updates_field_first(args: &[MirNode<mir::Expr>]) -> bool {
+    !args[0].may_have_effect()
+}
+
+/// The uses of locals (`Expr::Local` expressions) after which the local is never used again, on any path.
+#[derive(Default, Debug)]
+pub struct LastUses {
+    // Expressions are identified by their address, since MIR is not modified while it is being lowered
+    uses: HashSet<usize>,
+}
+
+impl LastUses {
+    /// Find the last uses of locals within an expression (usually the body of a procedure).
+    pub fn of(expr: &mir::Expr) -> Self {
+        let mut this = Self::default();
+        this.visit(expr, &mut HashSet::new());
+        this
+    }
+
+    pub fn contains(&self, expr: &mir::Expr) -> bool {
+        self.uses.contains(&(expr as *const mir::Expr as usize))
+    }
+
+    // Expressions are visited in the reverse of the order in which they are evaluated. `live` contains the locals used
+    // after the expression is evaluated and, afterwards, the locals used by or after it.
+    fn visit(&mut self, expr: &mir::Expr, live: &mut HashSet<mir::Local>) {
+        use mir::Expr;
+        match expr {
+            Expr::Undefined | Expr::Literal(_) | Expr::Global(_, _) => {},
+            Expr::Local(local) => if live.insert(*local) {
+                self.uses.insert(expr as *const Expr as usize);
+            },
+            Expr::Intrinsic(mir::Intrinsic::UpdateField(_), args) if updates_field_first(args) => {
+                self.visit(&args[0], live);
+                self.visit(&args[1], live);
+            },
+            Expr::Intrinsic(_, args) | Expr::Tuple(args) | Expr::List(args) => for arg in args.iter().rev() {
+                self.visit(arg, live);
+            },
+            Expr::Match(pred, arms) => {
+                // Locals used by the arms after this one, any of which might be tried if this arm's guard fails
+                let mut later = HashSet::new();
+                for (_, guard, body) in arms.iter().rev() {
+                    let mut arm = live.clone();
+                    self.visit(body, &mut arm);
+                    if let Some(guard) = guard {
+                        arm.extend(later.iter().copied());
+                        self.visit(guard, &mut arm);
+                    }
+                    later.extend(arm);
+                }
+                *live = later;
+                self.visit(pred, live);
+            },
+            Expr::Func(arg, body) => self.visit_body(&[**arg], body, live),
+            Expr::Go(arg, body, init) => {
+                // The body is evaluated repeatedly within the current function, so the locals it uses from outside
+                // itself are used again by the next iteration
+                let outer = body.required_locals(Some(**arg));
+                self.visit(body, &mut outer.iter().copied().collect());
+                live.extend(outer);
+                self.visit(init, live);
+            },
+            Expr::Apply(f, arg) => {
+                self.visit(arg, live);
+                self.visit(f, live);
+            },
+            Expr::Access(inner, _)
+            | Expr::Variant(_, inner)
+            | Expr::AccessVariant(inner, _)
+            | Expr::Data(_, inner)
+            | Expr::AccessData(inner, _) => self.visit(inner, live),
+            Expr::Basin(_, inner) => self.visit_body(&[], inner, live),
+            Expr::Handle { expr, handlers } => {
+                for mir::Handler { send, state, recv, .. } in handlers.iter().rev() {
+                    self.visit_body(&[**send, **state], recv, live);
+                }
+                self.visit(expr, live);
+            },
+        }
+    }
+
+    // Function bodies are evaluated apart from the expression that creates the function, and have their own copies of
+    // the locals that they capture
+    fn visit_body(&mut self, args: &[mir::Local], body: &mir::Expr, live: &mut HashSet<mir::Local>) {
+        self.visit(body, &mut HashSet::new());
+        live.extend(body.required_locals(args.iter().copied()));
+    }
+}
@@ -0,0 +1,184 @@
+//! Derive macros for `tao_vm`'s value conversion traits.
+//!
+//! The derived conversions follow the layout that Tao uses for data types: a struct corresponds to a data type with a
+//! single constructor and an enum to a data type with one constructor per variant, in declaration order. Constructors
+//! with named fields hold a record (whose fields are ordered by name), constructors with several unnamed fields hold a
+//! tuple, constructors with a single unnamed field hold that field directly and constructors without fields hold `()`.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, format_ident};
+use syn::{parse_macro_input, DeriveInput, Data, Fields, Ident, Type, Member, Index};
+
+/// The fields of a constructor, in the order Tao lays them out.
+struct Layout<'a> {
+    fields: &'a Fields,
+    // (binding, member, type), sorted by field name
+    sorted: Vec<(Ident, Member, &'a Type)>,
+}
+
+impl<'a> Layout<'a> {
+    fn new(fields: &'a Fields) -> Self {
+        let mut sorted = fields
+            .iter()
+            .enumerate()
+            .map(|(i, field)| {
+                let member = match &field.ident {
+                    Some(ident) => Member::Named(ident.clone()),
+                    None => Member::Unnamed(Index::from(i)),
+                };
+                (format_ident!("__field{}", i), member, &field.ty)
+            })
+            .collect::<Vec<_>>();
+        // Record fields are ordered by name, and tuple fields are named after their index
+        sorted.sort_by_key(|(_, member, _)| match member {
+            Member::Named(ident) => ident.to_string(),
+            Member::Unnamed(idx) => idx.index.to_string(),
+        });
+        Self { fields, sorted }
+    }
+
+    /// A pattern that binds every field of the constructor at the given path.
+    fn pattern(&self, path: TokenStream2) -> TokenStream2 {
+        let fields = self.sorted.iter().map(|(binding, member, _)| quote!(#member: #binding));
+        quote!(#path { #(#fields),* })
+    }
+
+    fn into_tao(&self) -> TokenStream2 {
+        match &self.sorted[..] {
+            [] => quote!(::tao_vm::Value::unit()),
+            [(binding, _, _)] if matches!(self.fields, Fields::Unnamed(_)) => {
+                quote!(::tao_vm::IntoTao::into_tao(#binding))
+            },
+            sorted => {
+                let bindings = sorted.iter().map(|(binding, _, _)| binding);
+                quote!(::tao_vm::Value::new_tuple(vec![#(::tao_vm::IntoTao::into_tao(#bindings)),*]))
+            },
+        }
+    }
+
+    /// An expression that converts `__inner` into the constructor at the given path, returning `None` on failure.
+    fn from_tao(&self, path: TokenStream2) -> TokenStream2 {
+        let construct = self.pattern(path);
+        match &self.sorted[..] {
+            [] => quote!({
+                if !__inner.into_tuple()?.is_empty() { return None; }
+                #construct
+            }),
+            [(binding, _, ty)] if matches!(self.fields, Fields::Unnamed(_)) => quote!({
+                let #binding = <#ty as ::tao_vm::FromTao>::from_tao(__inner)?;
+                #construct
+            }),
+            sorted => {
+                let n = sorted.len();
+                let bindings = sorted.iter().map(|(binding, _, ty)| quote! {
+                    let #binding = <#ty as ::tao_vm::FromTao>::from_tao(__items.next()?)?;
+                });
+                quote!({
+                    let __items = __inner.into_tuple()?;
+                    if __items.len() != #n { return None; }
+                    let mut __items = __items.iter().cloned();
+                    #(#bindings)*
+                    #construct
+                })
+            },
+        }
+    }
+
+    /// An expression that checks `__repr` against the layout of the constructor.
+    fn check_repr(&self) -> TokenStream2 {
+        match &self.sorted[..] {
+            [] => quote!(matches!(__repr, ::tao_vm::Repr::Tuple(__xs) if __xs.is_empty())),
+            [(_, _, ty)] if matches!(self.fields, Fields::Unnamed(_)) => {
+                quote!(<#ty as ::tao_vm::FromTao>::check_repr(__repr, __check))
+            },
+            sorted => {
+                let n = sorted.len();
+                let checks = sorted.iter().enumerate().map(|(i, (_, _, ty))| {
+                    quote!(<#ty as ::tao_vm::FromTao>::check_repr(&__fields[#i], __check))
+                });
+                quote!(__check.fields(__repr, #n, |__check, __fields| true #(&& #checks)*))
+            },
+        }
+    }
+}
+
+/// The constructors of the type, along with the path used to construct or match each of them.
+fn constructors(input: &DeriveInput) -> syn::Result<Vec<(TokenStream2, Layout)>> {
+    let name = &input.ident;
+    match &input.data {
+        Data::Struct(data) => Ok(vec![(quote!(#name), Layout::new(&data.fields))]),
+        Data::Enum(data) => Ok(data.variants
+            .iter()
+            .map(|variant| {
+                let variant_name = &variant.ident;
+                (quote!(#name::#variant_name), Layout::new(&variant.fields))
+            })
+            .collect()),
+        Data::Union(_) => Err(syn::Error::new_spanned(input, "unions cannot be converted to or from Tao values")),
+    }
+}
+
+#[proc_macro_derive(IntoTao)]
+pub fn derive_into_tao(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ctors = match constructors(&input) {
+        Ok(ctors) => ctors,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let arms = ctors.iter().enumerate().map(|(idx, (path, layout))| {
+        let pat = layout.pattern(path.clone());
+        let inner = layout.into_tao();
+        quote!(#pat => ::tao_vm::Value::new_sum(#idx, #inner))
+    });
+
+    quote! {
+        impl #impl_generics ::tao_vm::IntoTao for #name #ty_generics #where_clause {
+            fn into_tao(self) -> ::tao_vm::Value {
+                match self {
+                    #(#arms,)*
+                }
+            }
+        }
+    }.into()
+}
+
+#[proc_macro_derive(FromTao)]
+pub fn derive_from_tao(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ctors = match constructors(&input) {
+        Ok(ctors) => ctors,
+        Err(err) => return err.to_compile_error().into(),
+    };
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let n = ctors.len();
+    let arms = ctors.iter().enumerate().map(|(idx, (path, layout))| {
+        let construct = layout.from_tao(path.clone());
+        quote!(#idx => Some(#construct))
+    });
+    let checks = ctors.iter().enumerate().map(|(idx, (_, layout))| {
+        let check = layout.check_repr();
+        quote!({ let __repr = &__variants[#idx]; #check })
+    });
+
+    quote! {
+        impl #impl_generics ::tao_vm::FromTao for #name #ty_generics #where_clause {
+            fn from_tao(__val: ::tao_vm::Value) -> Option<Self> {
+                let (__variant, __inner) = __val.into_sum()?;
+                match __variant {
+                    #(#arms,)*
+                    _ => None,
+                }
+            }
+
+            fn check_repr(__repr: &::tao_vm::Repr, __check: &mut ::tao_vm::ReprCheck) -> bool {
+                __check.variants(__repr, |__check, __variants| __variants.len() == #n #(&& #checks)*)
+            }
+        }
+    }.into()
+}